@@ -1,19 +1,35 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize, Serializer};
+
+/// ソース中の範囲 (バイトオフセット)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
 
 /// 汎用AST ノード
 /// 入力BNFでパースした結果を保持する
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ASTNode {
     /// ルール名 (例: "func_decl", "arg")
     pub name: String,
 
     /// マッチした生テキスト (葉ノードやリテラルの場合)
+    #[serde(default)]
     pub value: String,
 
     /// 子ノードマップ
     /// Key: Input BNFで定義された子要素のルール名
     /// Value: マッチしたノードのリスト (`*` や `+` に対応するため Vec)
+    #[serde(default, serialize_with = "serialize_sorted")]
     pub children: HashMap<String, Vec<ASTNode>>,
+
+    /// ノードがマッチしたソース中の範囲 (先頭の空白を除く)
+    /// パーサーが作ったノードにだけ付く
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
 }
 
 impl ASTNode {
@@ -22,6 +38,7 @@ impl ASTNode {
             name: name.to_string(),
             value: String::new(),
             children: HashMap::new(),
+            span: None,
         }
     }
 
@@ -30,6 +47,23 @@ impl ASTNode {
             name: name.to_string(),
             value: value.to_string(),
             children: HashMap::new(),
+            span: None,
+        }
+    }
+
+    /// パースエラーから回復したノード
+    /// ルール名のノードに、読み飛ばしたテキストを値とする error の子を1つ持たせる
+    pub fn error(rule: &str, skipped: &str) -> Self {
+        let mut node = ASTNode::new(rule);
+        node.add_child(ASTNode::with_value("error", skipped));
+        node
+    }
+
+    /// パースエラーから回復したノードなら、読み飛ばしたテキスト
+    pub fn error_text(&self) -> Option<&str> {
+        match self.get_children("error") {
+            [error] if self.value.is_empty() && self.children.len() == 1 => Some(&error.value),
+            _ => None,
         }
     }
 
@@ -37,7 +71,7 @@ impl ASTNode {
     pub fn add_child(&mut self, child: ASTNode) {
         self.children
             .entry(child.name.clone())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(child);
     }
 
@@ -50,4 +84,80 @@ impl ASTNode {
     pub fn get_children(&self, name: &str) -> &[ASTNode] {
         self.children.get(name).map(|v| v.as_slice()).unwrap_or(&[])
     }
+
+    /// JSON形式にシリアライズ
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("ASTNode is always serializable")
+    }
+
+    /// JSON形式からデシリアライズ
+    pub fn from_json(json: &str) -> Result<ASTNode, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// S式形式に変換
+    /// 例: (func_decl (name "my_func") (ret_type "int"))
+    pub fn to_sexp(&self) -> String {
+        let mut result = String::new();
+        self.write_sexp(&mut result);
+        result
+    }
+
+    fn write_sexp(&self, out: &mut String) {
+        out.push('(');
+        out.push_str(&self.name);
+        if !self.value.is_empty() {
+            out.push(' ');
+            out.push_str(&format!("{:?}", self.value));
+        }
+        // 出力を安定させるため子ノードはルール名順に並べる
+        let sorted: BTreeMap<_, _> = self.children.iter().collect();
+        for children in sorted.values() {
+            for child in children.iter() {
+                out.push(' ');
+                child.write_sexp(out);
+            }
+        }
+        out.push(')');
+    }
+}
+
+/// 子ノードマップをルール名順にシリアライズ (出力を安定させるため)
+fn serialize_sorted<S: Serializer>(
+    children: &HashMap<String, Vec<ASTNode>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let sorted: BTreeMap<_, _> = children.iter().collect();
+    sorted.serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ASTNode {
+        let mut arg = ASTNode::new("arg");
+        arg.add_child(ASTNode::with_value("type", "int"));
+        arg.add_child(ASTNode::with_value("name", "a"));
+        let mut root = ASTNode::new("func_decl");
+        root.add_child(ASTNode::with_value("name", "f"));
+        root.add_child(arg);
+        root
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        let ast = sample();
+        let restored = ASTNode::from_json(&ast.to_json()).unwrap();
+        assert_eq!(restored.to_sexp(), ast.to_sexp());
+        assert_eq!(restored.get_child("arg").unwrap().get_child("type").unwrap().value, "int");
+    }
+
+    #[test]
+    fn test_sexp() {
+        assert_eq!(
+            sample().to_sexp(),
+            r#"(func_decl (arg (name "a") (type "int")) (name "f"))"#
+        );
+    }
 }
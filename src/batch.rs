@@ -0,0 +1,290 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::generator::Generator;
+use crate::meta_parser::{InputGrammar, OutputGrammar};
+use crate::parser::{ParseError, Parser};
+use crate::rewrite::{self, RewritePass};
+
+/// ファイルの変換に失敗した理由
+#[derive(Debug)]
+pub enum Failure {
+    /// 読み書きに失敗した
+    Io(String),
+    /// パースに失敗した (回復したエラーがあれば出力は書いてある)
+    Parse { source: String, errors: Vec<ParseError> },
+    /// 書き換えパスに失敗した (描画済みの診断)
+    Pass(String),
+}
+
+/// 1ファイルの変換結果
+#[derive(Debug)]
+pub struct FileResult {
+    /// ソースファイル
+    pub path: PathBuf,
+    /// 書き出したファイル
+    pub output: Option<PathBuf>,
+    pub failure: Option<Failure>,
+}
+
+/// ディレクトリ全体の変換結果 (入力パスの順)
+#[derive(Debug)]
+pub struct BatchReport {
+    pub files: Vec<FileResult>,
+}
+
+impl BatchReport {
+    pub fn failures(&self) -> impl Iterator<Item = &FileResult> {
+        self.files.iter().filter(|f| f.failure.is_some())
+    }
+
+    /// "Translated 3 files (1 failed)" の形の要約
+    pub fn summary(&self) -> String {
+        let failed = self.failures().count();
+        let translated = self.files.len() - failed;
+        let plural = if translated == 1 { "" } else { "s" };
+        format!("Translated {} file{} ({} failed)", translated, plural, failed)
+    }
+}
+
+/// ソースファイルを1組の文法でまとめて変換する
+/// ファイルは CPU コア数のスレッドで並列に変換し、ディレクトリ構造をそのまま出力先に写す
+pub struct BatchTranslator<'a> {
+    input: &'a InputGrammar,
+    output: &'a OutputGrammar,
+    /// 出力ファイルの拡張子 (None なら入力と同じ名前)
+    output_ext: Option<String>,
+    start_rule: Option<String>,
+    recovery_rules: Vec<String>,
+    passes: &'a [RewritePass],
+    jobs: usize,
+}
+
+impl<'a> BatchTranslator<'a> {
+    pub fn new(input: &'a InputGrammar, output: &'a OutputGrammar) -> Self {
+        BatchTranslator {
+            input,
+            output,
+            output_ext: None,
+            start_rule: None,
+            recovery_rules: Vec::new(),
+            passes: &[],
+            jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    /// 出力ファイルの拡張子を付け替える
+    pub fn with_output_ext(mut self, ext: &str) -> Self {
+        self.output_ext = Some(ext.trim_start_matches('.').to_string());
+        self
+    }
+
+    /// 開始ルール (Parser::with_start_rule と同じ)
+    pub fn with_start_rule(mut self, rule: Option<&str>) -> Self {
+        self.start_rule = rule.map(|rule| rule.to_string());
+        self
+    }
+
+    /// 指定したルールでエラーから回復する (Parser::with_recovery と同じ)
+    pub fn with_recovery(mut self, rules: &[String]) -> Self {
+        self.recovery_rules = rules.to_vec();
+        self
+    }
+
+    /// 出力の前に AST を書き換えるパス
+    pub fn with_passes(mut self, passes: &'a [RewritePass]) -> Self {
+        self.passes = passes;
+        self
+    }
+
+    /// 並列に変換するスレッド数
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// (ディレクトリ, そこからの相対パス) の組のファイルを全て変換し、out_dir の同じ相対パスに書き出す
+    /// 個々のファイルの失敗は結果に含める
+    pub fn translate_files(&self, files: &[(PathBuf, PathBuf)], out_dir: &Path) -> BatchReport {
+        // 空いたスレッドが次のファイルを取る
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(files.len()));
+        thread::scope(|scope| {
+            for _ in 0..self.jobs.min(files.len()) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some((dir, path)) = files.get(index) else { break };
+                    let result = self.translate_file(dir, out_dir, path);
+                    results.lock().unwrap().push((index, result));
+                });
+            }
+        });
+
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|(index, _)| *index);
+        BatchReport { files: results.into_iter().map(|(_, result)| result).collect() }
+    }
+
+    /// 1ファイルを変換して書き出す
+    fn translate_file(&self, src_dir: &Path, out_dir: &Path, path: &Path) -> FileResult {
+        let mut result = FileResult { path: src_dir.join(path), output: None, failure: None };
+        let source = match fs::read_to_string(&result.path) {
+            Ok(source) => source,
+            Err(e) => {
+                result.failure = Some(Failure::Io(format!("Error reading {}: {}", result.path.display(), e)));
+                return result;
+            }
+        };
+
+        let mut parser = Parser::new(self.input, &source).with_recovery(&self.recovery_rules);
+        if let Some(rule) = &self.start_rule {
+            parser = parser.with_start_rule(rule);
+        }
+        let parsed = parser.parse();
+        let mut errors = parser.errors().to_vec();
+        let ast = match parsed {
+            Ok(ast) => ast,
+            Err(err) => {
+                errors.push(err);
+                result.failure = Some(Failure::Parse { source, errors });
+                return result;
+            }
+        };
+        let ast = match rewrite::apply_passes(self.passes, ast) {
+            Ok(ast) => ast,
+            Err(diagnostic) => {
+                result.failure = Some(Failure::Pass(diagnostic));
+                return result;
+            }
+        };
+
+        let output_path = output_path(out_dir, path, self.output_ext.as_deref());
+        let text = Generator::new(self.output).generate(&ast);
+        let written = output_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&output_path, format!("{}\n", text)));
+        match written {
+            Ok(()) => result.output = Some(output_path),
+            Err(e) => result.failure = Some(Failure::Io(format!("Error writing {}: {}", output_path.display(), e))),
+        }
+        if !errors.is_empty() && result.failure.is_none() {
+            result.failure = Some(Failure::Parse { source, errors });
+        }
+        result
+    }
+}
+
+/// src_dir 以下で拡張子が exts のどれかに当たるファイルを、相対パスの順に集める
+/// (exts が空なら全て。隠しファイルと、src_dir の中にある out_dir は除く)
+pub fn source_files(src_dir: &Path, out_dir: &Path, exts: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    collect_sources(src_dir, Path::new(""), out_dir, exts, &mut paths)?;
+    paths.sort();
+    Ok(paths)
+}
+
+fn collect_sources(src_dir: &Path, relative: &Path, out_dir: &Path, exts: &[String], paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    let dir = src_dir.join(relative);
+    if out_dir.exists() && dir.canonicalize()? == out_dir.canonicalize()? {
+        return Ok(());
+    }
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = relative.join(&name);
+        if entry.file_type()?.is_dir() {
+            collect_sources(src_dir, &path, out_dir, exts, paths)?;
+        } else {
+            let ext = path.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+            if exts.is_empty() || exts.contains(&ext) {
+                paths.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 入力の相対パスに対応する出力ファイルのパス (ext があれば拡張子を付け替える)
+pub fn output_path(out_dir: &Path, path: &Path, ext: Option<&str>) -> PathBuf {
+    let mut output_path = out_dir.join(path);
+    if let Some(ext) = ext {
+        output_path.set_extension(ext);
+    }
+    output_path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta_parser::MetaParser;
+
+    #[test]
+    fn test_translate_files() {
+        let input = MetaParser::new(
+            r#"
+            program := call+;
+            call    := name "(" ")" NEWLINE;
+            name    := "[a-z]+";
+            "#,
+        )
+        .parse_input_grammar().unwrap();
+        let output = MetaParser::new(
+            r#"
+            program := call join "\n";
+            call    := name "();";
+            "#,
+        )
+        .parse_output_grammar().unwrap();
+
+        let dir = std::env::temp_dir().join(format!("hensan-batch-{}", std::process::id()));
+        let src = dir.join("src");
+        fs::create_dir_all(src.join("sub/deep")).unwrap();
+        fs::write(src.join("a.py"), "f()\ng()\n").unwrap();
+        fs::write(src.join("sub/b.py"), "h()\n").unwrap();
+        fs::write(src.join("sub/deep/c.py"), "i()\nj(\nk()\n").unwrap();
+        fs::write(src.join("sub/notes.txt"), "not source").unwrap();
+        fs::write(src.join(".hidden.py"), "x(").unwrap();
+
+        let paths = source_files(&src, &dir.join("gen"), &["py".to_string()]).unwrap();
+        let files: Vec<_> = paths.into_iter().map(|path| (src.clone(), path)).collect();
+        let translator = BatchTranslator::new(&input, &output).with_output_ext("rs").with_jobs(2);
+        let report = translator.translate_files(&files, &dir.join("gen"));
+
+        let paths: Vec<_> = report.files.iter().map(|f| f.path.strip_prefix(&src).unwrap().to_string_lossy().replace('\\', "/")).collect();
+        assert_eq!(paths, ["a.py", "sub/b.py", "sub/deep/c.py"]);
+        assert_eq!(fs::read_to_string(dir.join("gen/a.rs")).unwrap(), "f();\ng();\n");
+        assert_eq!(fs::read_to_string(dir.join("gen/sub/b.rs")).unwrap(), "h();\n");
+        assert!(!dir.join("gen/sub/deep/c.rs").exists());
+        assert_eq!(report.summary(), "Translated 2 files (1 failed)");
+        match &report.files[2].failure {
+            Some(Failure::Parse { errors, .. }) => assert_eq!((errors[0].line, errors[0].column), (2, 3)),
+            other => panic!("{:?}", other),
+        }
+
+        // 回復したファイルは書き出すが、失敗として数える
+        let report = translator
+            .with_recovery(&["call".to_string()])
+            .translate_files(&files, &dir.join("gen"));
+        assert_eq!(report.summary(), "Translated 2 files (1 failed)");
+        assert!(fs::read_to_string(dir.join("gen/sub/deep/c.rs")).unwrap().contains("k();"));
+
+        // 開始ルールを変えると断片をパースできる
+        fs::write(src.join("name.py"), "abc").unwrap();
+        let files = [(src.clone(), PathBuf::from("name.py"))];
+        let report = BatchTranslator::new(&input, &output).translate_files(&files, &dir.join("gen"));
+        assert_eq!(report.summary(), "Translated 0 files (1 failed)");
+        let report = BatchTranslator::new(&input, &output).with_start_rule(Some("name")).translate_files(&files, &dir.join("gen"));
+        assert_eq!(report.summary(), "Translated 1 file (0 failed)");
+        assert_eq!(fs::read_to_string(dir.join("gen/name.py")).unwrap(), "abc\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
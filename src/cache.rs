@@ -0,0 +1,273 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::meta_parser::{GrammarKind, InputGrammar, MetaParseError, MetaParser, OutputGrammar};
+
+/// キャッシュを置くディレクトリ (入力BNFと同じディレクトリに作る。バージョン管理からは外す)
+pub const CACHE_DIR: &str = ".cache";
+
+/// キャッシュファイルの先頭に置くマジックバイト
+const CACHE_MAGIC: &[u8; 8] = b"HENSANGC";
+
+/// キャッシュ形式のバージョン
+/// GrammarExpr / OutputExpr などの構造を変更したら必ず上げること
+const CACHE_VERSION: u32 = 3;
+
+/// コンパイル済みの入力・出力文法の組
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompiledGrammars {
+    pub input: InputGrammar,
+    pub output: OutputGrammar,
+    /// キャッシュを書けなかったなどの警告 (文法は使える)
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+impl CompiledGrammars {
+    /// キャッシュを使わずに入力・出力BNFをコンパイルする
+    pub fn compile(input_bnf: &str, output_bnf: &str) -> Result<Self, (GrammarKind, MetaParseError)> {
+        Ok(CompiledGrammars {
+            input: MetaParser::new(input_bnf).parse_input_grammar().map_err(|e| (GrammarKind::Input, e))?,
+            output: MetaParser::new(output_bnf).parse_output_grammar().map_err(|e| (GrammarKind::Output, e))?,
+            warnings: Vec::new(),
+        })
+    }
+}
+
+/// 文法ファイルを読み込めなかった理由
+#[derive(Debug)]
+pub enum LoadError {
+    /// ファイルを読めなかった
+    Io { path: PathBuf, error: io::Error },
+    /// 文法に誤りがあった
+    Syntax { path: PathBuf, error: MetaParseError },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { path, error } => write!(f, "Error reading {}: {}", path.display(), error),
+            LoadError::Syntax { path, error } => write!(f, "Error in {}: {}", path.display(), error),
+        }
+    }
+}
+
+fn read_grammar(path: &Path) -> Result<String, LoadError> {
+    fs::read_to_string(path).map_err(|error| LoadError::Io { path: path.to_path_buf(), error })
+}
+
+/// 入力BNFファイルを読み込む
+pub fn load_input_grammar(path: &Path) -> Result<InputGrammar, LoadError> {
+    MetaParser::new(&read_grammar(path)?)
+        .parse_input_grammar()
+        .map_err(|error| LoadError::Syntax { path: path.to_path_buf(), error })
+}
+
+/// 出力BNFファイルを読み込む
+pub fn load_output_grammar(path: &Path) -> Result<OutputGrammar, LoadError> {
+    MetaParser::new(&read_grammar(path)?)
+        .parse_output_grammar()
+        .map_err(|error| LoadError::Syntax { path: path.to_path_buf(), error })
+}
+
+impl CompiledGrammars {
+    /// 入力・出力BNFファイルを読み込んでコンパイルする
+    /// use_cache なら入力BNFと同じディレクトリの .cache に、文法ファイルの組ごとに1つキャッシュする
+    pub fn load(input_path: &Path, output_path: &Path, use_cache: bool) -> Result<Self, LoadError> {
+        let input_bnf = read_grammar(input_path)?;
+        let output_bnf = read_grammar(output_path)?;
+        let compiled = if use_cache {
+            let dir = input_path.parent().unwrap_or(Path::new("")).join(CACHE_DIR);
+            let stem = |path: &Path| path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string());
+            GrammarCache::new(dir, &format!("{}-{}", stem(input_path), stem(output_path))).load_or_compile(&input_bnf, &output_bnf)
+        } else {
+            CompiledGrammars::compile(&input_bnf, &output_bnf)
+        };
+        compiled.map_err(|(kind, error)| {
+            let path = if kind == GrammarKind::Input { input_path } else { output_path };
+            LoadError::Syntax { path: path.to_path_buf(), error }
+        })
+    }
+}
+
+/// キャッシュファイルのヘッダ
+#[derive(Serialize, Deserialize)]
+struct CacheHeader {
+    magic: [u8; 8],
+    version: u32,
+    /// 生成したhensanのバージョン
+    tool_version: String,
+    /// 文法ソースのハッシュ
+    key: u64,
+}
+
+/// コンパイル済み文法のバイナリキャッシュ
+/// 文法ファイルの組の名前と文法ソースのハッシュをキーとして、ディレクトリ内にファイルを保存する
+/// 同じ名前の古いキーのファイルは、新しいキーで保存するときに消す
+pub struct GrammarCache {
+    dir: PathBuf,
+    name: String,
+}
+
+impl GrammarCache {
+    pub fn new(dir: impl Into<PathBuf>, name: &str) -> Self {
+        GrammarCache { dir: dir.into(), name: name.to_string() }
+    }
+
+    /// キャッシュを読み込み、なければ文法をコンパイルしてキャッシュに保存
+    /// キャッシュの読み書きに失敗しても文法のコンパイル結果は返す (書けなければ警告を付ける)
+    /// 文法に誤りがあれば、どちらの文法かとエラーを返す
+    pub fn load_or_compile(&self, input_bnf: &str, output_bnf: &str) -> Result<CompiledGrammars, (GrammarKind, MetaParseError)> {
+        let key = grammar_key(input_bnf, output_bnf);
+
+        if let Some(grammars) = self.load(key) {
+            return Ok(grammars);
+        }
+
+        let mut grammars = CompiledGrammars::compile(input_bnf, output_bnf)?;
+
+        match self.store(key, &grammars) {
+            Ok(()) => self.evict(key),
+            Err(e) => grammars.warnings.push(format!("could not write grammar cache {}: {}", self.path(key).display(), e)),
+        }
+
+        Ok(grammars)
+    }
+
+    /// キーに対応するキャッシュファイルのパス
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{}-{:016x}.bin", self.name, key))
+    }
+
+    /// 同じ名前で key 以外のキャッシュファイル (文法を編集する前のもの) を消す
+    /// 消せなくても次に保存するときにまた試すので、失敗は無視する
+    fn evict(&self, key: u64) {
+        let Ok(entries) = fs::read_dir(&self.dir) else { return };
+        let current = self.path(key);
+        let prefix = format!("{}-", self.name);
+        for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+            let Some(rest) = path.file_name().and_then(|name| name.to_str()?.strip_prefix(&prefix)) else { continue };
+            // 名前が前方一致するだけの別の組 ("a-b" と "a-b-c") は、残りがキーだけでないので除く
+            let stale = rest.strip_suffix(".bin").is_some_and(|key| key.len() == 16 && key.bytes().all(|b| b.is_ascii_hexdigit()));
+            if stale && path != current {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+
+    /// キャッシュを読み込む (存在しない・壊れている・バージョン違いなら None)
+    fn load(&self, key: u64) -> Option<CompiledGrammars> {
+        let bytes = fs::read(self.path(key)).ok()?;
+        let mut reader = bytes.as_slice();
+
+        let header: CacheHeader = bincode::deserialize_from(&mut reader).ok()?;
+        if &header.magic != CACHE_MAGIC
+            || header.version != CACHE_VERSION
+            || header.tool_version != env!("CARGO_PKG_VERSION")
+            || header.key != key
+        {
+            return None;
+        }
+
+        let mut grammars: CompiledGrammars = bincode::deserialize_from(&mut reader).ok()?;
+        // 正規表現はキャッシュに保存していないので作り直す
+        grammars.input.compile_patterns().ok()?;
+        Some(grammars)
+    }
+
+    /// キャッシュを書き込む
+    fn store(&self, key: u64, grammars: &CompiledGrammars) -> io::Result<()> {
+        let header = CacheHeader {
+            magic: *CACHE_MAGIC,
+            version: CACHE_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            key,
+        };
+
+        let mut bytes = bincode::serialize(&header).map_err(io::Error::other)?;
+        bytes.extend(bincode::serialize(grammars).map_err(io::Error::other)?);
+
+        fs::create_dir_all(&self.dir)?;
+        // 途中まで書かれたファイルを読まないよう、一時ファイルから置き換える
+        let path = self.path(key);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, &path)
+    }
+}
+
+/// 文法ソースからキャッシュキーを計算 (FNV-1a 64bit)
+/// std の DefaultHasher は実行ごと・バージョンごとに値が変わりうるため使わない
+fn grammar_key(input_bnf: &str, output_bnf: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    // 入力と出力の境界を区別するため長さも混ぜる
+    for part in [input_bnf.len().to_le_bytes().as_slice(), input_bnf.as_bytes(), output_bnf.as_bytes()] {
+        for byte in part {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    const INPUT: &str = r#"
+        func_decl := ret_type name "(" ")" ";";
+        ret_type  := "void" | "int";
+        name      := "[a-zA-Z_]+";
+    "#;
+    const OUTPUT: &str = r#"func_decl := "fn " name "()" ";";"#;
+
+    #[test]
+    fn test_cache_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("hensan-cache-test-{}", std::process::id()));
+        let cache = GrammarCache::new(&dir, "input-output");
+
+        let compiled = cache.load_or_compile(INPUT, OUTPUT).unwrap();
+        let key = grammar_key(INPUT, OUTPUT);
+        assert!(cache.path(key).exists());
+
+        let loaded = cache.load(key).expect("cache should be readable");
+        assert_eq!(loaded.input.start_rule, compiled.input.start_rule);
+        assert_eq!(loaded.input.rules.len(), 3);
+        assert!(loaded.output.rules.contains_key("func_decl"));
+        // 読み込んだ文法のパターンも使える
+        assert!(Parser::new(&loaded.input, "int main();").parse().is_ok());
+
+        // 別の文法ソースには別のキーが使われる
+        assert_ne!(grammar_key(INPUT, ""), grammar_key("", INPUT));
+        assert!(cache.load(grammar_key(INPUT, "")).is_none());
+
+        // 誤りのある文法はキャッシュせず、どちらの文法かを返す
+        let (kind, _) = cache.load_or_compile(INPUT, ":= \"fn\";").unwrap_err();
+        assert_eq!(kind, GrammarKind::Output);
+        assert!(!cache.path(grammar_key(INPUT, ":= \"fn\";")).exists());
+
+        // 文法を編集したら古いキーのファイルを消す (別の組のファイルは残す)
+        let other = GrammarCache::new(&dir, "input-output-other");
+        other.load_or_compile(INPUT, OUTPUT).unwrap();
+        let edited = format!("{}\n", OUTPUT);
+        let compiled = cache.load_or_compile(INPUT, &edited).unwrap();
+        assert!(compiled.warnings.is_empty());
+        assert!(cache.path(grammar_key(INPUT, &edited)).exists());
+        assert!(!cache.path(key).exists());
+        assert!(other.path(key).exists());
+
+        // キャッシュを書けなくても文法は返し、警告を付ける
+        let blocked = dir.join("blocked");
+        fs::write(&blocked, "").unwrap();
+        let compiled = GrammarCache::new(&blocked, "input-output").load_or_compile(INPUT, OUTPUT).unwrap();
+        assert_eq!(compiled.warnings.len(), 1);
+        assert!(compiled.warnings[0].starts_with("could not write grammar cache"), "{:?}", compiled.warnings);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::meta_parser::GrammarKind;
+use crate::templates::Template;
+
+/// プロジェクト設定ファイルの名前
+pub const CONFIG_FILE: &str = "hensan.toml";
+
+/// `hensan init` で作る設定ファイル ({from}, {to}, {source_ext}, {target_ext} を置き換える)
+const CONFIG_TEMPLATE: &str = r#"# hensan translation pipelines ({from} -> {to})
+# Paths are relative to this file.
+default = "main"
+
+[pipeline.main]
+input = "Grammar/input.bnf"
+sources = ["src/**/*.{source_ext}"]
+out_dir = "gen"
+tests = "tests"
+
+[[pipeline.main.output]]
+grammar = "Grammar/output.bnf"
+ext = "{target_ext}"
+"#;
+
+/// hensan.toml の内容
+/// パスは設定ファイルのあるディレクトリからの相対パスで書き、読み込むときに解決する
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// --pipeline を省略したときのパイプライン
+    pub default: Option<String>,
+    #[serde(default, rename = "pipeline")]
+    pub pipelines: BTreeMap<String, Pipeline>,
+}
+
+/// 名前付きの変換パイプライン
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+    /// 入力BNF
+    pub input: PathBuf,
+    /// 出力BNFと、その出力を書き出す先 (1つの入力を複数の言語に変換できる)
+    #[serde(default, rename = "output")]
+    pub outputs: Vec<Target>,
+    /// 出力の前に AST を書き換えるパス (順に適用する)
+    #[serde(default, rename = "pass")]
+    pub passes: Vec<Pass>,
+    /// 変換するソースの glob ("src/**/*.py" など)
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// 出力先のディレクトリ (出力ごとに上書きできる)
+    pub out_dir: Option<PathBuf>,
+    /// `hensan test` のケースを置くディレクトリ (省略したら tests)
+    pub tests: Option<PathBuf>,
+    /// 開始ルール (--start)
+    pub start: Option<String>,
+    /// エラーから回復するルール (--recover)
+    #[serde(default)]
+    pub recover: Vec<String>,
+    /// 並列に変換するファイル数 (--jobs)
+    pub jobs: Option<usize>,
+}
+
+/// 出力BNFと書き出し先
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    pub grammar: PathBuf,
+    /// 出力ファイルの拡張子 (省略したらソースと同じ名前)
+    pub ext: Option<String>,
+    pub out_dir: Option<PathBuf>,
+}
+
+/// 書き換えパス: AST を output でテキストにし、input でパースし直す
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pass {
+    pub output: PathBuf,
+    pub input: PathBuf,
+}
+
+/// パイプラインでの文法ファイルの使われ方
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrammarRole {
+    pub kind: GrammarKind,
+    /// 出力BNFなら、出力ルールが対応する入力BNF (その前にあるパスの入力BNFか、パイプラインの入力BNF)
+    pub input: Option<PathBuf>,
+}
+
+/// hensan.toml から選んだパイプライン
+#[derive(Debug)]
+pub struct Project {
+    /// hensan.toml のあるディレクトリ
+    pub root: PathBuf,
+    pub name: String,
+    pub pipeline: Pipeline,
+}
+
+impl Config {
+    /// 設定ファイルを読み込み、パスを設定ファイルのディレクトリで解決する
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut config: Config = toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let root = path.parent().unwrap_or(Path::new(""));
+        for pipeline in config.pipelines.values_mut() {
+            pipeline.resolve(root);
+        }
+        Ok(config)
+    }
+
+    /// start_dir から親ディレクトリへ hensan.toml を探す
+    pub fn find(start_dir: &Path) -> Option<PathBuf> {
+        start_dir.ancestors().map(|dir| dir.join(CONFIG_FILE)).find(|path| path.is_file())
+    }
+
+    /// path の文法がパイプラインでどう使われているか (どのパイプラインにもなければ None)
+    /// default のパイプラインを先に調べる
+    pub fn grammar_role(&self, path: &Path) -> Option<GrammarRole> {
+        let default = self.default.as_ref().and_then(|name| self.pipelines.get(name));
+        default.into_iter().chain(self.pipelines.values()).find_map(|pipeline| pipeline.grammar_role(path))
+    }
+
+    /// 名前のパイプラインを選ぶ (None なら default、パイプラインが1つだけならそれ)
+    pub fn select(self, root: &Path, name: Option<&str>) -> Result<Project, String> {
+        let name = match (name, self.default.as_deref()) {
+            (Some(name), _) | (None, Some(name)) => name.to_string(),
+            (None, None) if self.pipelines.len() == 1 => self.pipelines.keys().next().unwrap().clone(),
+            (None, None) if self.pipelines.is_empty() => return Err(format!("no pipelines are defined in {}", CONFIG_FILE)),
+            (None, None) => {
+                let names: Vec<_> = self.pipelines.keys().map(|n| n.as_str()).collect();
+                return Err(format!("choose a pipeline with --pipeline ({}) or set `default` in {}", names.join(", "), CONFIG_FILE));
+            }
+        };
+        let mut pipelines = self.pipelines;
+        let pipeline = pipelines.remove(&name).ok_or_else(|| format!("unknown pipeline '{}' in {}", name, CONFIG_FILE))?;
+        Ok(Project { root: root.to_path_buf(), name, pipeline })
+    }
+}
+
+/// dir にテンプレートから設定ファイル・文法・例のソースを作り、作ったファイルを返す
+/// 例のソースは src/ (変換の対象) と tests/ (期待する出力と組にしたゴールデンテスト) に置く
+/// 既にあるファイルは上書きしない
+pub fn init_project(dir: &Path, template: &Template) -> Result<Vec<PathBuf>, String> {
+    let (source, target) = (template.source, template.target);
+    let config = CONFIG_TEMPLATE
+        .replace("{from}", source.name)
+        .replace("{to}", target.name)
+        .replace("{source_ext}", source.ext)
+        .replace("{target_ext}", target.ext);
+    let files = [
+        (dir.join(CONFIG_FILE), config.as_str()),
+        (dir.join("Grammar/input.bnf"), source.grammar),
+        (dir.join("Grammar/output.bnf"), target.grammar),
+        // コンパイル済み文法のキャッシュはコミットしない
+        (dir.join("Grammar/.gitignore"), "/.cache/\n"),
+        (dir.join(format!("src/example.{}", source.ext)), source.example),
+        (dir.join(format!("tests/example.{}", source.ext)), source.example),
+        (dir.join(format!("tests/example.expected.{}", target.ext)), target.expected),
+    ];
+    if let Some((path, _)) = files.iter().find(|(path, _)| path.exists()) {
+        return Err(format!("{} already exists", path.display()));
+    }
+    for (path, content) in &files {
+        path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, content))
+            .map_err(|e| format!("Error creating {}: {}", path.display(), e))?;
+    }
+    Ok(files.into_iter().map(|(path, _)| path).collect())
+}
+
+/// path の近くの hensan.toml から文法の使われ方を調べる
+/// 設定ファイルがないか読めないとき、どのパイプラインにもないときは None
+pub fn find_grammar_role(path: &Path) -> Option<GrammarRole> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let config = Config::load(&Config::find(path.parent()?)?).ok()?;
+    config.grammar_role(&path)
+}
+
+/// 同じファイルを指すパスか (ファイルがなければパスをそのまま比べる)
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    a == b || canonical(a) == canonical(b)
+}
+
+impl Pipeline {
+    /// path の文法をこのパイプラインでどう使っているか
+    fn grammar_role(&self, path: &Path) -> Option<GrammarRole> {
+        let input = GrammarRole { kind: GrammarKind::Input, input: None };
+        if same_file(path, &self.input) {
+            return Some(input);
+        }
+        // 出力BNFは、その直前の入力BNFでパースした AST を出力する
+        let mut ast_input = &self.input;
+        for pass in &self.passes {
+            if same_file(path, &pass.output) {
+                return Some(GrammarRole { kind: GrammarKind::Output, input: Some(ast_input.clone()) });
+            }
+            if same_file(path, &pass.input) {
+                return Some(input);
+            }
+            ast_input = &pass.input;
+        }
+        self.outputs
+            .iter()
+            .any(|target| same_file(path, &target.grammar))
+            .then(|| GrammarRole { kind: GrammarKind::Output, input: Some(ast_input.clone()) })
+    }
+
+    fn resolve(&mut self, root: &Path) {
+        let resolve = |path: &mut PathBuf| *path = root.join(&*path);
+        resolve(&mut self.input);
+        for target in &mut self.outputs {
+            resolve(&mut target.grammar);
+            target.out_dir.as_mut().map(resolve);
+        }
+        for pass in &mut self.passes {
+            resolve(&mut pass.output);
+            resolve(&mut pass.input);
+        }
+        self.out_dir.as_mut().map(resolve);
+        self.tests.as_mut().map(resolve);
+    }
+
+    /// sources の glob に当たるファイルを、glob の固定部分のディレクトリとそこからの相対パスの組で返す
+    /// (出力先には相対パスを写す。"src/**/*.py" なら src からの相対パス)
+    pub fn source_files(&self, root: &Path) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+        let mut files = Vec::new();
+        for pattern in &self.sources {
+            let (base, rest) = split_glob(pattern);
+            let base_dir = root.join(base);
+            if !base_dir.is_dir() {
+                continue;
+            }
+            let mut paths = Vec::new();
+            walk(&base_dir, Path::new(""), &mut paths)?;
+            for path in paths {
+                let relative = path.to_string_lossy().replace('\\', "/");
+                let entry = (base_dir.clone(), path);
+                if glob_match(rest, &relative) && !files.contains(&entry) {
+                    files.push(entry);
+                }
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// glob をワイルドカードを含まない先頭のディレクトリと残りに分ける
+fn split_glob(pattern: &str) -> (&str, &str) {
+    let wildcard = pattern.find(['*', '?']).unwrap_or(pattern.len());
+    match pattern[..wildcard].rfind('/') {
+        Some(slash) => (&pattern[..slash], &pattern[slash + 1..]),
+        None => ("", pattern),
+    }
+}
+
+/// ディレクトリ以下のファイルを相対パスの順に集める (隠しファイルは除く)
+fn walk(root: &Path, relative: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(root.join(relative))?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = entry.file_name();
+        if name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = relative.join(&name);
+        if entry.file_type()?.is_dir() {
+            walk(root, &path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// '/' 区切りのパスが glob に当たるか
+/// `**` は0個以上のディレクトリ、`*` は '/' 以外の0文字以上、`?` は '/' 以外の1文字
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let patterns: Vec<&str> = pattern.split('/').collect();
+    let segments: Vec<&str> = path.split('/').collect();
+    match_segments(&patterns, &segments)
+}
+
+fn match_segments(patterns: &[&str], segments: &[&str]) -> bool {
+    match patterns.split_first() {
+        None => segments.is_empty(),
+        Some((&"**", rest)) => (0..=segments.len()).any(|skip| match_segments(rest, &segments[skip..])),
+        Some((pattern, rest)) => match segments.split_first() {
+            Some((segment, segments)) => match_segment(pattern.as_bytes(), segment.as_bytes()) && match_segments(rest, segments),
+            None => false,
+        },
+    }
+}
+
+fn match_segment(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| match_segment(rest, &text[skip..])),
+        Some((b'?', rest)) => {
+            // 1文字 (UTF-8 の続きのバイトも読み飛ばす)
+            let len = text.iter().skip(1).take_while(|&&b| b & 0xC0 == 0x80).count() + 1;
+            !text.is_empty() && match_segment(rest, &text[len.min(text.len())..])
+        }
+        Some((c, rest)) => text.first() == Some(c) && match_segment(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::templates;
+
+    const CONFIG: &str = r#"
+        default = "c-to-rust"
+
+        [pipeline.c-to-rust]
+        input = "grammar/c.bnf"
+        sources = ["src/**/*.c", "include/*.h"]
+        out_dir = "gen"
+        recover = ["stmt"]
+
+        [[pipeline.c-to-rust.output]]
+        grammar = "grammar/rust.bnf"
+        ext = "rs"
+
+        [[pipeline.c-to-rust.output]]
+        grammar = "grammar/go.bnf"
+        ext = "go"
+        out_dir = "gen-go"
+
+        [[pipeline.c-to-rust.pass]]
+        output = "grammar/desugar.out.bnf"
+        input = "grammar/desugar.in.bnf"
+
+        [pipeline.fmt]
+        input = "grammar/c.bnf"
+    "#;
+
+    #[test]
+    fn test_load_and_select() {
+        let dir = std::env::temp_dir().join(format!("hensan-config-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src/nested/deeper")).unwrap();
+        fs::write(dir.join(CONFIG_FILE), CONFIG).unwrap();
+
+        // 親ディレクトリの設定ファイルを見つける
+        let path = Config::find(&dir.join("src/nested/deeper")).unwrap();
+        assert_eq!(path, dir.join(CONFIG_FILE));
+
+        let config = Config::load(&path).unwrap();
+        let project = config.select(&dir, None).unwrap();
+        assert_eq!(project.name, "c-to-rust");
+        assert_eq!(project.pipeline.input, dir.join("grammar/c.bnf"));
+        assert_eq!(project.pipeline.outputs[1].out_dir, Some(dir.join("gen-go")));
+        assert_eq!(project.pipeline.passes[0].input, dir.join("grammar/desugar.in.bnf"));
+        assert_eq!(project.pipeline.recover, ["stmt"]);
+
+        // 文法の使われ方 (出力BNFはパスの入力BNFと対になる)
+        let config = Config::load(&path).unwrap();
+        let role = config.grammar_role(&dir.join("grammar/rust.bnf")).unwrap();
+        assert_eq!((role.kind, role.input), (GrammarKind::Output, Some(dir.join("grammar/desugar.in.bnf"))));
+        let role = config.grammar_role(&dir.join("grammar/desugar.out.bnf")).unwrap();
+        assert_eq!((role.kind, role.input), (GrammarKind::Output, Some(dir.join("grammar/c.bnf"))));
+        assert_eq!(config.grammar_role(&dir.join("grammar/desugar.in.bnf")).unwrap().kind, GrammarKind::Input);
+        assert!(config.grammar_role(&dir.join("grammar/other.bnf")).is_none());
+        let role = find_grammar_role(&dir.join("src/../grammar/go.bnf")).unwrap();
+        assert_eq!(role.kind, GrammarKind::Output);
+
+        let err = Config::load(&path).unwrap().select(&dir, Some("nope")).unwrap_err();
+        assert_eq!(err, "unknown pipeline 'nope' in hensan.toml");
+
+        fs::write(dir.join(CONFIG_FILE), "[pipeline.a]\ninput = \"a.bnf\"\ncolor = true\n").unwrap();
+        let err = Config::load(&path).unwrap_err();
+        assert!(err.contains("unknown field `color`"), "{}", err);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_init_project() {
+        let dir = std::env::temp_dir().join(format!("hensan-config-init-{}", std::process::id()));
+        let template = templates::find("json", Some("python")).unwrap();
+        let created = init_project(&dir, &template).unwrap();
+        assert_eq!(created.len(), 7);
+        assert!(dir.join("tests/example.expected.py").is_file());
+        assert_eq!(fs::read_to_string(dir.join("Grammar/.gitignore")).unwrap(), "/.cache/\n");
+
+        let project = Config::load(&dir.join(CONFIG_FILE)).unwrap().select(&dir, None).unwrap();
+        assert_eq!(project.name, "main");
+        assert_eq!(project.pipeline.outputs[0].grammar, dir.join("Grammar/output.bnf"));
+        assert_eq!(project.pipeline.outputs[0].ext.as_deref(), Some("py"));
+        assert!(project.pipeline.outputs[0].grammar.is_file());
+        assert_eq!(project.pipeline.tests, Some(dir.join("tests")));
+
+        // 例のソースは sources の glob に当たる
+        let files = project.pipeline.source_files(&dir).unwrap();
+        assert_eq!(files, [(dir.join("src"), PathBuf::from("example.json"))]);
+
+        // 2回目は何も上書きしない
+        let err = init_project(&dir, &template).unwrap_err();
+        assert_eq!(err, format!("{} already exists", dir.join(CONFIG_FILE).display()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_source_files() {
+        let dir = std::env::temp_dir().join(format!("hensan-config-sources-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src/net")).unwrap();
+        fs::create_dir_all(dir.join("include")).unwrap();
+        for file in ["src/main.c", "src/net/tcp.c", "src/net/tcp.h", "include/api.h", "include/.hidden.h"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+
+        let pipeline: Pipeline = toml::from_str("input = \"c.bnf\"\nsources = [\"src/**/*.c\", \"include/*.h\"]").unwrap();
+        let files: Vec<_> = pipeline
+            .source_files(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|(base, path)| format!("{} {}", base.strip_prefix(&dir).unwrap().display(), path.display()))
+            .collect();
+        assert_eq!(files, ["src main.c", "src net/tcp.c", "include api.h"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("**/*.py", "a.py"));
+        assert!(glob_match("**/*.py", "x/y/a.py"));
+        assert!(glob_match("x/**/test_?.py", "x/test_1.py"));
+        assert!(glob_match("*.py", "日本語.py"));
+        assert!(glob_match("?.py", "é.py"));
+        assert!(!glob_match("*.py", "x/a.py"));
+        assert!(!glob_match("*.py", "a.pyc"));
+        assert_eq!(split_glob("src/**/*.c"), ("src", "**/*.c"));
+        assert_eq!(split_glob("*.c"), ("", "*.c"));
+    }
+}
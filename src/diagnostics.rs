@@ -0,0 +1,449 @@
+use std::fmt::Write;
+
+use serde_json::json;
+
+use crate::ast::Span;
+use crate::line_index::{self, ColumnEncoding, LineIndex};
+use crate::meta_parser::{GrammarExpr, InputGrammar};
+use crate::parser::ParseError;
+
+/// 診断の重大度
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+}
+
+impl Severity {
+    fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// ソース中の範囲に付けるラベル
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    /// 主ラベル (^ で示す) か、補助ラベル (- で示す) か
+    pub primary: bool,
+}
+
+/// rustc 風の診断
+/// 主ラベル・補助ラベル・注記・ヘルプを持つ
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: &str) -> Self {
+        Diagnostic {
+            severity,
+            message: message.to_string(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    /// 主ラベルを追加
+    pub fn with_primary(mut self, span: Span, message: &str) -> Self {
+        self.labels.push(Label { span, message: message.to_string(), primary: true });
+        self
+    }
+
+    /// 補助ラベルを追加
+    pub fn with_secondary(mut self, span: Span, message: &str) -> Self {
+        self.labels.push(Label { span, message: message.to_string(), primary: false });
+        self
+    }
+
+    /// 注記を追加
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    /// ヘルプを設定
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = Some(help.to_string());
+        self
+    }
+
+    /// パースエラーから診断を作る
+    /// 期待されたものは、SAME_INDENT やパターンのような内部の名前ではなく、利用者向けの言葉にする
+    pub fn from_parse_error(err: &ParseError, grammar: &InputGrammar, source: &str) -> Self {
+        let position = err.position.min(source.len());
+        let expected = describe_expected(&err.expected, grammar);
+        let (found, found_len) = describe_found(&source[position..]);
+
+        // 入力BNFの @expect があればそのメッセージを使う
+        let (message, label) = match (&err.message, expected.is_empty()) {
+            (Some(message), _) => (message.clone(), format!("found {}", found)),
+            (None, true) => (format!("unexpected {}", found), "unexpected here".to_string()),
+            (None, false) => (format!("expected {}, found {}", expected, found), format!("expected {}", expected)),
+        };
+        let mut diagnostic = Diagnostic::new(Severity::Error, &message)
+            .with_primary(Span { start: position, end: position + found_len }, &label);
+
+        // インデントされた行なら、そのブロックを始めた行を示す
+        if let Some(header) = block_header(source, position) {
+            diagnostic = diagnostic.with_secondary(header, "block started here");
+        }
+        if !err.context_rule.is_empty() {
+            diagnostic = diagnostic.with_note(&format!("while parsing `{}`", err.context_rule));
+        }
+
+        let expects = |token: &str| err.expected.iter().any(|e| e == token);
+        if expects("INDENT") {
+            diagnostic = diagnostic.with_help("indent the lines of the block further than the line that starts it");
+        } else if expects("SAME_INDENT") || expects("DEDENT") {
+            diagnostic = diagnostic.with_help("check that this line is indented like the other lines of its block");
+        }
+        diagnostic
+    }
+}
+
+/// 期待されたものの一覧を文にする
+fn describe_expected(expected: &[String], grammar: &InputGrammar) -> String {
+    let mut items: Vec<String> = Vec::new();
+    for item in expected {
+        let described = match item.as_str() {
+            "NEWLINE" => "end of line".to_string(),
+            "INDENT" => "an indented block".to_string(),
+            "DEDENT" => "the end of the indented block".to_string(),
+            "SAME_INDENT" => "a line at the same indentation".to_string(),
+            other => {
+                if let Some(pattern) = other.strip_prefix("pattern /").and_then(|p| p.strip_suffix('/')) {
+                    // パターンだけのルール (name := "[a-z]+"; など) はルール名で呼ぶ
+                    match grammar.order.iter().find(|name| matches!(&grammar.rules[*name].expr, GrammarExpr::Pattern(p) if p == pattern)) {
+                        Some(name) => format!("`{}`", name),
+                        None => format!("text matching /{}/", pattern),
+                    }
+                } else if let Some(literal) = other.strip_prefix('"').and_then(|l| l.strip_suffix('"')) {
+                    format!("`{}`", literal)
+                } else {
+                    other.to_string()
+                }
+            }
+        };
+        if !items.contains(&described) {
+            items.push(described);
+        }
+    }
+
+    match items.split_last() {
+        None => String::new(),
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+    }
+}
+
+/// エラー位置で見つかったものと、その長さ (バイト)
+fn describe_found(rest: &str) -> (String, usize) {
+    let line = rest.lines().next().unwrap_or("");
+    if rest.is_empty() {
+        ("end of input".to_string(), 0)
+    } else if line.trim().is_empty() {
+        ("end of line".to_string(), 0)
+    } else {
+        // 識別子や数字はひとまとまりに、それ以外は1文字を取り出す
+        let first = line.chars().next().unwrap();
+        let length = if first.is_alphanumeric() || first == '_' {
+            line.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(line.len())
+        } else {
+            first.len_utf8()
+        };
+        (format!("`{}`", &line[..length]), length)
+    }
+}
+
+/// 位置の行がインデントされていれば、そのブロックを始めた行 (より浅い直前の行) の範囲
+fn block_header(source: &str, position: usize) -> Option<Span> {
+    let line_start = source[..position].rfind('\n').map_or(0, |i| i + 1);
+    let indent = line_index::indent_width(&source[line_start..]);
+    if indent == 0 || line_start == 0 {
+        return None;
+    }
+
+    let mut end = line_start - 1;
+    loop {
+        let start = source[..end].rfind('\n').map_or(0, |i| i + 1);
+        let line = &source[start..end];
+        if !line.trim().is_empty() && line_index::indent_width(line) < indent {
+            let content = start + indent_of_bytes(line);
+            return Some(Span { start: content, end: start + line.trim_end().len() });
+        }
+        if start == 0 {
+            return None;
+        }
+        end = start - 1;
+    }
+}
+
+fn indent_of_bytes(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+/// 診断をテキストや JSON にする
+pub struct Renderer<'a> {
+    source_name: &'a str,
+    source: &'a str,
+    line_index: LineIndex,
+    color: bool,
+}
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+impl<'a> Renderer<'a> {
+    pub fn new(source_name: &'a str, source: &'a str) -> Self {
+        Renderer { source_name, source, line_index: LineIndex::new(source), color: false }
+    }
+
+    /// ANSI カラーで色を付けるか
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    fn paint(&self, text: &str, color: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// rustc 風のテキストにする
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let severity_color = match diagnostic.severity {
+            Severity::Error => RED,
+        };
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}{}",
+            self.paint(diagnostic.severity.as_str(), severity_color),
+            self.paint(&format!(": {}", diagnostic.message), BOLD)
+        );
+
+        // ラベルを行ごとにまとめる
+        let mut labels: Vec<(usize, usize, &Label)> = diagnostic
+            .labels
+            .iter()
+            .map(|label| {
+                let (line, column) = self.line_col(label.span.start);
+                (line, column, label)
+            })
+            .collect();
+        labels.sort_by_key(|(line, column, _)| (*line, *column));
+
+        let width = labels.iter().map(|(line, _, _)| line.to_string().len()).max().unwrap_or(1);
+        let gutter = self.paint(&format!("{} |", " ".repeat(width)), BLUE);
+
+        let primary = labels.iter().find(|(_, _, label)| label.primary).or(labels.first());
+        match primary {
+            Some((line, column, _)) => {
+                let _ = writeln!(out, "{}{} {}:{}:{}", " ".repeat(width), self.paint("-->", BLUE), self.source_name, line, column);
+            }
+            None => {
+                let _ = writeln!(out, "{}{} {}", " ".repeat(width), self.paint("-->", BLUE), self.source_name);
+            }
+        }
+
+        if !labels.is_empty() {
+            let _ = writeln!(out, "{}", gutter);
+        }
+        let mut previous_line = None;
+        for (line, _, label) in &labels {
+            if previous_line != Some(*line) {
+                if previous_line.is_some_and(|previous| line - previous > 1) {
+                    let _ = writeln!(out, "{}", self.paint("...", BLUE));
+                }
+                let text = self.line_index.line_text(self.source, line - 1);
+                let _ = writeln!(
+                    out,
+                    "{} {}",
+                    self.paint(&format!("{:>width$} |", line, width = width), BLUE),
+                    line_index::expand_tabs(text)
+                );
+                previous_line = Some(*line);
+            }
+
+            // 下線は同じ行の中だけに引き、タブや全角文字の表示幅に合わせる
+            let line_start = self.line_index.line_start(line - 1);
+            let text = self.line_index.line_text(self.source, line - 1);
+            let end = label.span.end.min(line_start + text.len()).max(label.span.start);
+            let indent = line_index::display_width(&self.source[line_start..label.span.start]);
+            let length = (line_index::display_width(&self.source[line_start..end]) - indent).max(1);
+            let (mark, color) = if label.primary { ("^", severity_color) } else { ("-", BLUE) };
+            let underline = format!("{} {}", mark.repeat(length), label.message);
+            let _ = writeln!(out, "{} {}{}", gutter, " ".repeat(indent), self.paint(underline.trim_end(), color));
+        }
+
+        if !diagnostic.notes.is_empty() || diagnostic.help.is_some() {
+            let _ = writeln!(out, "{}", gutter);
+        }
+        for note in &diagnostic.notes {
+            let _ = writeln!(out, "{} {} {}", " ".repeat(width), self.paint("= note:", BOLD), note);
+        }
+        if let Some(help) = &diagnostic.help {
+            let _ = writeln!(out, "{} {} {}", " ".repeat(width), self.paint("= help:", BOLD), help);
+        }
+        out
+    }
+
+    /// エディタ向けに1行の JSON にする
+    /// 行・列は1始まりで、列は文字単位 (utf16_column は LSP と同じ UTF-16 単位)
+    pub fn render_json(&self, diagnostic: &Diagnostic) -> String {
+        let labels: Vec<_> = diagnostic
+            .labels
+            .iter()
+            .map(|label| {
+                let (line, column) = self.line_col(label.span.start);
+                let (end_line, end_column) = self.line_col(label.span.end);
+                let utf16_column = |offset| self.line_index.line_col(self.source, offset, ColumnEncoding::Utf16).1 + 1;
+                json!({
+                    "start": label.span.start,
+                    "end": label.span.end,
+                    "line": line,
+                    "column": column,
+                    "end_line": end_line,
+                    "end_column": end_column,
+                    "utf16_column": utf16_column(label.span.start),
+                    "end_utf16_column": utf16_column(label.span.end),
+                    "message": label.message,
+                    "primary": label.primary,
+                })
+            })
+            .collect();
+        json!({
+            "severity": diagnostic.severity.as_str(),
+            "message": diagnostic.message,
+            "file": self.source_name,
+            "labels": labels,
+            "notes": diagnostic.notes,
+            "help": diagnostic.help,
+        })
+        .to_string()
+    }
+
+    /// バイトオフセットを行・列にする (1始まり、列は文字単位)
+    fn line_col(&self, position: usize) -> (usize, usize) {
+        let (line, column) = self.line_index.line_col(self.source, position, ColumnEncoding::Char);
+        (line + 1, column + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta_parser::MetaParser;
+    use crate::parser::Parser;
+
+    const GRAMMAR: &str = r#"
+        block   := stmt+;
+        stmt    := SAME_INDENT (if_stmt | call) NEWLINE?;
+        if_stmt := "if" name ":" NEWLINE INDENT block DEDENT;
+        call    := name "(" ")";
+        name    := "[a-z]+";
+    "#;
+
+    #[test]
+    fn test_parse_error_diagnostic() {
+        let grammar = MetaParser::new(GRAMMAR).parse_input_grammar().unwrap();
+        let source = "if x:\n    f()\n    g(1)\n";
+        let err = Parser::new(&grammar, source).parse().unwrap_err();
+        let diagnostic = Diagnostic::from_parse_error(&err, &grammar, source);
+
+        let text = Renderer::new("test.py", source).render(&diagnostic);
+        assert_eq!(
+            text,
+            "\
+error: expected `)`, found `1`
+ --> test.py:3:7
+  |
+1 | if x:
+  | ----- block started here
+...
+3 |     g(1)
+  |       ^ expected `)`
+  |
+  = note: while parsing `call`
+"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&Renderer::new("test.py", source).render_json(&diagnostic)).unwrap();
+        assert_eq!(json["labels"][0]["line"], 3);
+        assert_eq!(json["labels"][1]["message"], "block started here");
+    }
+
+    #[test]
+    fn test_grammar_labels_and_messages() {
+        let grammar = MetaParser::new(
+            r#"
+            if_stmt := "if" name @label("condition") ":" @expect("expected ':' after the condition");
+            name    := "[a-z]+";
+            "#,
+        )
+        .parse_input_grammar().unwrap();
+        let diagnose = |source: &str| {
+            let err = Parser::new(&grammar, source).parse().unwrap_err();
+            Diagnostic::from_parse_error(&err, &grammar, source)
+        };
+
+        // 注釈の付いた式の最初で失敗すれば、パターンの代わりにラベルを使う
+        assert_eq!(diagnose("if 1:").message, "expected condition, found `1`");
+        let missing_colon = diagnose("if x");
+        assert_eq!(missing_colon.message, "expected ':' after the condition");
+        assert_eq!(missing_colon.labels[0].message, "found end of input");
+    }
+
+    #[test]
+    fn test_wide_characters_and_tabs() {
+        let grammar = MetaParser::new(
+            r#"
+            call := name "(" str ")";
+            str  := ["[^"]*"];
+            name := "[a-z]+";
+            "#,
+        )
+        .parse_input_grammar().unwrap();
+        let source = "f(\t\"日本語\" x)";
+        let err = Parser::new(&grammar, source).parse().unwrap_err();
+        // 列は文字単位で数える
+        assert_eq!((err.line, err.column), (1, 10));
+
+        // タブは展開し、全角文字は2桁として下線を引く
+        let text = Renderer::new("test.py", source).render(&Diagnostic::from_parse_error(&err, &grammar, source));
+        assert!(text.contains("1 | f(      \"日本語\" x)\n  |                  ^ expected `)`\n"), "{}", text);
+        assert!(err.to_string().contains(" 1 | f(      \"日本語\" x)\n                      ^\n"), "{}", err);
+    }
+
+    #[test]
+    fn test_block_header_with_tabs() {
+        // "  \t" はパーサーと同じく8桁なので、8スペースの行と同じ深さ
+        let source = "if x:\n        if y:\n  \t?\n";
+        let header = block_header(source, source.find('?').unwrap()).unwrap();
+        assert_eq!(&source[header.start..header.end], "if x:");
+    }
+
+    #[test]
+    fn test_describe_expected() {
+        let grammar = MetaParser::new(GRAMMAR).parse_input_grammar().unwrap();
+        let expected: Vec<String> = ["SAME_INDENT", "pattern /[a-z]+/", "\"if\"", "pattern /[0-9]+/"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            describe_expected(&expected, &grammar),
+            "a line at the same indentation, `name`, `if` or text matching /[0-9]+/"
+        );
+    }
+}
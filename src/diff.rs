@@ -0,0 +1,134 @@
+use std::fmt::Write;
+
+/// 変更のまわりに表示する行数
+const CONTEXT_LINES: usize = 3;
+
+/// 行ごとの編集操作
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    /// 両方にある行 (old の位置, new の位置)
+    Equal(usize, usize),
+    /// old にだけある行
+    Delete(usize),
+    /// new にだけある行
+    Insert(usize),
+}
+
+/// old から new への unified diff を作る (差がなければ空文字列)
+/// 行は最長共通部分列で対応付ける
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = diff_lines(&old_lines, &new_lines);
+    if edits.iter().all(|edit| matches!(edit, Edit::Equal(..))) {
+        return String::new();
+    }
+
+    let mut text = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (start, end) in hunks(&edits) {
+        let hunk = &edits[start..end];
+        // 空の範囲の開始行は、その直前の行番号にする
+        let (old_start, new_start) = position(&edits[..start]);
+        let old_count = hunk.iter().filter(|edit| !matches!(edit, Edit::Insert(_))).count();
+        let new_count = hunk.iter().filter(|edit| !matches!(edit, Edit::Delete(_))).count();
+        let _ = writeln!(
+            text,
+            "@@ -{} +{} @@",
+            range(old_start, old_count),
+            range(new_start, new_count)
+        );
+        for edit in hunk {
+            let (prefix, line) = match *edit {
+                Edit::Equal(i, _) => (' ', old_lines[i]),
+                Edit::Delete(i) => ('-', old_lines[i]),
+                Edit::Insert(j) => ('+', new_lines[j]),
+            };
+            text.push(prefix);
+            text.push_str(line);
+            if !line.ends_with('\n') {
+                text.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    text
+}
+
+/// 最長共通部分列から編集操作の列を作る
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    // lcs[i][j]: old[i..] と new[j..] の最長共通部分列の長さ
+    let mut lcs = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut edits = Vec::with_capacity(old.len() + new.len());
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push(Edit::Equal(i, j));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] > lcs[i + 1][j]) {
+            edits.push(Edit::Insert(j));
+            j += 1;
+        } else {
+            edits.push(Edit::Delete(i));
+            i += 1;
+        }
+    }
+    edits
+}
+
+/// 変更のある範囲を前後の文脈ごと切り出す (近い範囲はまとめる)
+fn hunks(edits: &[Edit]) -> Vec<(usize, usize)> {
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (index, _) in edits.iter().enumerate().filter(|(_, edit)| !matches!(edit, Edit::Equal(..))) {
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let end = (index + 1 + CONTEXT_LINES).min(edits.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    hunks
+}
+
+/// 編集操作の列を通り過ぎた後の (old, new) の行数
+fn position(edits: &[Edit]) -> (usize, usize) {
+    edits.iter().fold((0, 0), |(old, new), edit| match edit {
+        Edit::Equal(..) => (old + 1, new + 1),
+        Edit::Delete(_) => (old + 1, new),
+        Edit::Insert(_) => (old, new + 1),
+    })
+}
+
+/// hunk ヘッダの範囲 ("3,4" の形。1行なら行数を省く)
+fn range(before: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", before),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{}", before + 1, count),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "old", "new"), "");
+
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n";
+        assert_eq!(
+            unified_diff(old, new, "expected", "actual"),
+            "--- expected\n+++ actual\n@@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n@@ -8,3 +8,4 @@\n 8\n 9\n 10\n+11\n"
+        );
+
+        // 空のファイルと末尾に改行のない行
+        assert_eq!(unified_diff("", "x", "a", "b"), "--- a\n+++ b\n@@ -0,0 +1 @@\n+x\n\\ No newline at end of file\n");
+    }
+}
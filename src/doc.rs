@@ -0,0 +1,452 @@
+use std::fmt::Write;
+
+use crate::meta_parser::{GrammarExpr, InputGrammar, OutputGrammar};
+
+/// 要素間の水平方向の余白
+const GAP: f64 = 10.0;
+/// 分岐・ループの曲線の半径
+const ARC: f64 = 10.0;
+/// ノードの高さの半分
+const HALF_HEIGHT: f64 = 11.0;
+/// 1文字あたりの幅 (等幅フォント 13px)
+const CHAR_WIDTH: f64 = 8.0;
+
+/// 文法ドキュメント生成器
+/// 入力BNFの各ルールを鉄道線路図 (SVG) にし、対応する出力ルールと並べた HTML を生成する
+pub struct DocGenerator<'a> {
+    input: &'a InputGrammar,
+    output: Option<&'a OutputGrammar>,
+    title: String,
+}
+
+/// 図の要素の大きさ (ベースラインより上・下の高さ)
+#[derive(Debug, Clone, Copy)]
+struct Size {
+    width: f64,
+    up: f64,
+    down: f64,
+}
+
+impl<'a> DocGenerator<'a> {
+    pub fn new(input: &'a InputGrammar) -> Self {
+        DocGenerator {
+            input,
+            output: None,
+            title: "Grammar".to_string(),
+        }
+    }
+
+    /// 対応する出力BNFを設定 (ルールごとに並べて表示する)
+    pub fn with_output(mut self, output: &'a OutputGrammar) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// ページのタイトルを設定
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    /// 自己完結した HTML ページを生成
+    pub fn generate(&self) -> String {
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        let _ = writeln!(html, "<title>{}</title>", escape(&self.title));
+        html.push_str("<style>\n");
+        html.push_str(STYLE);
+        html.push_str("</style>\n</head>\n<body>\n");
+        let _ = writeln!(html, "<h1>{}</h1>", escape(&self.title));
+
+        // 目次
+        html.push_str("<nav><ul>\n");
+        for name in &self.input.order {
+            let _ = writeln!(html, "<li><a href=\"#rule-{0}\">{0}</a></li>", escape(name));
+        }
+        html.push_str("</ul></nav>\n");
+
+        for name in &self.input.order {
+            let Some(rule) = self.input.rules.get(name) else { continue };
+            let _ = writeln!(html, "<section class=\"rule\" id=\"rule-{}\">", escape(name));
+            let _ = writeln!(
+                html,
+                "<h2>{}{}</h2>",
+                escape(name),
+                if *name == self.input.start_rule { " <small>(start)</small>" } else { "" }
+            );
+            html.push_str("<div class=\"columns\">\n<div class=\"input\">\n");
+            html.push_str(&self.diagram(&rule.expr));
+            let _ = writeln!(html, "<pre>{}</pre>", escape(&rule.to_string()));
+            html.push_str("</div>\n");
+
+            if let Some(output) = self.output {
+                html.push_str("<div class=\"output\">\n<h3>output</h3>\n");
+                match output.rules.get(name) {
+                    Some(out_rule) => {
+                        let _ = writeln!(html, "<pre>{}</pre>", escape(&out_rule.to_string()));
+                    }
+                    None => html.push_str("<p class=\"missing\">No output rule: the matched text is emitted as is.</p>\n"),
+                }
+                html.push_str("</div>\n");
+            }
+            html.push_str("</div>\n</section>\n");
+        }
+
+        // 入力ルールに対応しない出力ルール
+        if let Some(output) = self.output {
+            let orphans: Vec<&String> = output.order.iter().filter(|n| !self.input.rules.contains_key(*n)).collect();
+            if !orphans.is_empty() {
+                html.push_str("<section class=\"rule\">\n<h2>Output rules without an input rule</h2>\n");
+                for name in orphans {
+                    let _ = writeln!(html, "<pre>{}</pre>", escape(&output.rules[name].to_string()));
+                }
+                html.push_str("</section>\n");
+            }
+        }
+
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    /// ルール1つ分の SVG
+    fn diagram(&self, expr: &GrammarExpr) -> String {
+        let size = measure(expr);
+        // 両端の開始・終了マーク
+        let width = size.width + 2.0 * GAP + 20.0;
+        let height = size.up + size.down + 20.0;
+        let y = size.up + 10.0;
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            "<svg class=\"railroad\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+            width, height, width, height
+        );
+        // 開始・終了マーク
+        let _ = writeln!(svg, "<path d=\"M 5 {} v 20 M 10 {} v 20\"/>", y - 10.0, y - 10.0);
+        let _ = writeln!(svg, "<path d=\"M 10 {} h {}\"/>", y, GAP);
+        self.render(expr, 10.0 + GAP, y, size.width, &mut svg);
+        let end = 10.0 + GAP + size.width;
+        let _ = writeln!(svg, "<path d=\"M {} {} h {}\"/>", end, y, GAP);
+        let _ = writeln!(
+            svg,
+            "<path d=\"M {} {} v 20 M {} {} v 20\"/>",
+            end + GAP,
+            y - 10.0,
+            end + GAP + 5.0,
+            y - 10.0
+        );
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// 式を描画 (x: 左端, y: ベースライン, width: 割り当てられた幅)
+    /// 割り当てられた幅が式の幅より大きい場合は右側を線で埋める
+    fn render(&self, expr: &GrammarExpr, x: f64, y: f64, width: f64, svg: &mut String) {
+        let size = measure(expr);
+        match expr {
+            GrammarExpr::Literal(lit) => node(svg, x, y, size.width, &format!("\"{}\"", lit), "literal", None),
+            GrammarExpr::Pattern(pattern) => node(svg, x, y, size.width, &format!("/{}/", pattern), "pattern", None),
+            GrammarExpr::RuleRef(name) => {
+                let link = self.input.rules.contains_key(name).then(|| format!("#rule-{}", name));
+                node(svg, x, y, size.width, name, "rule", link.as_deref())
+            }
+            GrammarExpr::Indent => node(svg, x, y, size.width, "INDENT", "special", None),
+            GrammarExpr::Dedent => node(svg, x, y, size.width, "DEDENT", "special", None),
+            GrammarExpr::Newline => node(svg, x, y, size.width, "NEWLINE", "special", None),
+            GrammarExpr::SameIndent => node(svg, x, y, size.width, "SAME_INDENT", "special", None),
+            GrammarExpr::Group(inner) | GrammarExpr::Annotated { inner, .. } => self.render(inner, x, y, size.width, svg),
+
+            GrammarExpr::Sequence(items) => {
+                let mut cx = x;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        line(svg, cx, y, GAP);
+                        cx += GAP;
+                    }
+                    let item_width = measure(item).width;
+                    self.render(item, cx, y, item_width, svg);
+                    cx += item_width;
+                }
+            }
+
+            GrammarExpr::Choice(choices) => {
+                let inner_width = size.width - 4.0 * ARC;
+                let mut alt_y = y;
+                for (i, choice) in choices.iter().enumerate() {
+                    let alt = measure(choice);
+                    if i == 0 {
+                        line(svg, x, y, 2.0 * ARC);
+                        self.render(choice, x + 2.0 * ARC, y, inner_width, svg);
+                        line(svg, x + 2.0 * ARC + inner_width, y, 2.0 * ARC);
+                    } else {
+                        let prev = measure(&choices[i - 1]);
+                        alt_y += prev.down + GAP + alt.up;
+                        self.branch(svg, x, y, alt_y, size.width);
+                        self.render(choice, x + 2.0 * ARC, alt_y, inner_width, svg);
+                    }
+                }
+            }
+
+            GrammarExpr::Optional(inner) => {
+                // 上の経路は何もしない (スキップ)、下の経路が本体
+                let inner_size = measure(inner);
+                let inner_y = y + HALF_HEIGHT + GAP + inner_size.up;
+                line(svg, x, y, size.width);
+                self.branch(svg, x, y, inner_y, size.width);
+                self.render(inner, x + 2.0 * ARC, inner_y, size.width - 4.0 * ARC, svg);
+            }
+
+            GrammarExpr::OneOrMore(inner) => {
+                self.render_loop(inner, x, y, size.width, svg);
+            }
+
+            GrammarExpr::ZeroOrMore(inner) => {
+                // スキップ経路の下に、ループ付きの本体を置く
+                let loop_size = loop_size(measure(inner));
+                let loop_y = y + HALF_HEIGHT + GAP + loop_size.up;
+                line(svg, x, y, size.width);
+                self.branch(svg, x, y, loop_y, size.width);
+                self.render_loop(inner, x + 2.0 * ARC, loop_y, size.width - 4.0 * ARC, svg);
+            }
+        }
+
+        // 余った幅を線で埋める
+        if width > size.width {
+            line(svg, x + size.width, y, width - size.width);
+        }
+    }
+
+    /// 1回以上の繰り返し (本体の下に戻りの経路を描く)
+    fn render_loop(&self, inner: &GrammarExpr, x: f64, y: f64, width: f64, svg: &mut String) {
+        let inner_size = measure(inner);
+        let inner_width = width - 2.0 * ARC;
+        line(svg, x, y, ARC);
+        self.render(inner, x + ARC, y, inner_width, svg);
+        line(svg, x + ARC + inner_width, y, ARC);
+
+        let left = x + ARC;
+        let right = x + ARC + inner_width;
+        let back_y = y + inner_size.down + GAP;
+        let _ = writeln!(
+            svg,
+            "<path d=\"M {r} {y} Q {r2} {y} {r2} {y1} L {r2} {b1} Q {r2} {b} {r} {b} L {l} {b} Q {l2} {b} {l2} {b1} L {l2} {y1} Q {l2} {y} {l} {y}\"/>",
+            r = right,
+            r2 = right + ARC,
+            l = left,
+            l2 = left - ARC,
+            y = y,
+            y1 = y + ARC,
+            b = back_y,
+            b1 = back_y - ARC,
+        );
+    }
+
+    /// ベースラインから下の経路へ分岐し、右端で合流する曲線
+    fn branch(&self, svg: &mut String, x: f64, y: f64, alt_y: f64, width: f64) {
+        let right = x + width;
+        let _ = writeln!(
+            svg,
+            "<path d=\"M {x} {y} Q {x1} {y} {x1} {y1} L {x1} {a1} Q {x1} {a} {x2} {a}\"/>",
+            x = x,
+            x1 = x + ARC,
+            x2 = x + 2.0 * ARC,
+            y = y,
+            y1 = y + ARC,
+            a = alt_y,
+            a1 = alt_y - ARC,
+        );
+        let _ = writeln!(
+            svg,
+            "<path d=\"M {r2} {a} Q {r1} {a} {r1} {a1} L {r1} {y1} Q {r1} {y} {r} {y}\"/>",
+            r = right,
+            r1 = right - ARC,
+            r2 = right - 2.0 * ARC,
+            y = y,
+            y1 = y + ARC,
+            a = alt_y,
+            a1 = alt_y - ARC,
+        );
+    }
+}
+
+/// 式の大きさを計算
+fn measure(expr: &GrammarExpr) -> Size {
+    match expr {
+        GrammarExpr::Literal(lit) => node_size(&format!("\"{}\"", lit)),
+        GrammarExpr::Pattern(pattern) => node_size(&format!("/{}/", pattern)),
+        GrammarExpr::RuleRef(name) => node_size(name),
+        GrammarExpr::Indent => node_size("INDENT"),
+        GrammarExpr::Dedent => node_size("DEDENT"),
+        GrammarExpr::Newline => node_size("NEWLINE"),
+        GrammarExpr::SameIndent => node_size("SAME_INDENT"),
+        GrammarExpr::Group(inner) | GrammarExpr::Annotated { inner, .. } => measure(inner),
+        GrammarExpr::Sequence(items) => {
+            let mut size = Size { width: 0.0, up: HALF_HEIGHT, down: HALF_HEIGHT };
+            for (i, item) in items.iter().enumerate() {
+                let s = measure(item);
+                size.width += s.width + if i > 0 { GAP } else { 0.0 };
+                size.up = size.up.max(s.up);
+                size.down = size.down.max(s.down);
+            }
+            size
+        }
+        GrammarExpr::Choice(choices) => {
+            let sizes: Vec<Size> = choices.iter().map(measure).collect();
+            let width = sizes.iter().map(|s| s.width).fold(0.0, f64::max) + 4.0 * ARC;
+            // 2番目以降の選択肢は最初の選択肢の下に積む
+            let down = sizes.first().map_or(HALF_HEIGHT, |s| s.down)
+                + sizes.iter().skip(1).map(|s| GAP + s.up + s.down).sum::<f64>();
+            Size {
+                width,
+                up: sizes.first().map_or(HALF_HEIGHT, |s| s.up),
+                down,
+            }
+        }
+        GrammarExpr::Optional(inner) => {
+            let s = measure(inner);
+            Size {
+                width: s.width + 4.0 * ARC,
+                up: HALF_HEIGHT,
+                down: HALF_HEIGHT + GAP + s.up + s.down,
+            }
+        }
+        GrammarExpr::OneOrMore(inner) => loop_size(measure(inner)),
+        GrammarExpr::ZeroOrMore(inner) => {
+            let s = loop_size(measure(inner));
+            Size {
+                width: s.width + 4.0 * ARC,
+                up: HALF_HEIGHT,
+                down: HALF_HEIGHT + GAP + s.up + s.down,
+            }
+        }
+    }
+}
+
+/// 1回以上の繰り返しの大きさ
+fn loop_size(inner: Size) -> Size {
+    Size {
+        width: inner.width + 2.0 * ARC,
+        up: inner.up,
+        down: inner.down + GAP,
+    }
+}
+
+fn node_size(text: &str) -> Size {
+    Size {
+        width: text_width(text) + 20.0,
+        up: HALF_HEIGHT,
+        down: HALF_HEIGHT,
+    }
+}
+
+/// テキストの表示幅 (全角文字は2文字分)
+fn text_width(text: &str) -> f64 {
+    text.chars().map(|c| if c.is_ascii() { 1.0 } else { 2.0 }).sum::<f64>() * CHAR_WIDTH
+}
+
+/// 水平線
+fn line(svg: &mut String, x: f64, y: f64, width: f64) {
+    if width > 0.0 {
+        let _ = writeln!(svg, "<path d=\"M {} {} h {}\"/>", x, y, width);
+    }
+}
+
+/// 終端・非終端などのノード
+fn node(svg: &mut String, x: f64, y: f64, width: f64, text: &str, class: &str, link: Option<&str>) {
+    if let Some(href) = link {
+        let _ = write!(svg, "<a href=\"{}\">", escape(href));
+    }
+    let _ = write!(svg, "<g class=\"{}\">", class);
+    match class {
+        // 特殊トークンは六角形
+        "special" => {
+            let _ = write!(
+                svg,
+                "<polygon points=\"{},{} {},{} {},{} {},{} {},{} {},{}\"/>",
+                x, y,
+                x + 6.0, y - HALF_HEIGHT,
+                x + width - 6.0, y - HALF_HEIGHT,
+                x + width, y,
+                x + width - 6.0, y + HALF_HEIGHT,
+                x + 6.0, y + HALF_HEIGHT,
+            );
+        }
+        _ => {
+            // 終端記号は角丸、非終端記号は四角
+            let radius = if class == "rule" { 0.0 } else { HALF_HEIGHT };
+            let _ = write!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\"/>",
+                x,
+                y - HALF_HEIGHT,
+                width,
+                2.0 * HALF_HEIGHT,
+                radius
+            );
+        }
+    }
+    let _ = write!(svg, "<text x=\"{}\" y=\"{}\">{}</text></g>", x + width / 2.0, y + 4.0, escape(text));
+    if link.is_some() {
+        svg.push_str("</a>");
+    }
+    svg.push('\n');
+}
+
+/// HTML エスケープ
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const STYLE: &str = r#"body { font-family: sans-serif; margin: 2em; color: #222; }
+nav ul { columns: 4; list-style: none; padding: 0; font-family: monospace; }
+section.rule { border-top: 1px solid #ccc; padding: 0.5em 0; }
+h2 small { color: #888; font-weight: normal; }
+.columns { display: flex; gap: 2em; align-items: flex-start; }
+.input { flex: 3; overflow-x: auto; }
+.output { flex: 2; }
+.output h3 { margin: 0; font-size: 0.9em; color: #666; }
+pre { background: #f6f6f6; padding: 0.5em; white-space: pre-wrap; }
+p.missing { color: #a60; font-style: italic; }
+svg.railroad path { stroke: #333; stroke-width: 2; fill: none; }
+svg.railroad rect, svg.railroad polygon { stroke: #333; stroke-width: 2; }
+svg.railroad text { font: 13px monospace; text-anchor: middle; }
+svg.railroad .literal rect { fill: #e8f4e8; }
+svg.railroad .pattern rect { fill: #fdf3dc; }
+svg.railroad .rule rect { fill: #e4ecf7; }
+svg.railroad .special polygon { fill: #f0e4f7; }
+svg.railroad a:hover rect { fill: #c9daf2; }
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta_parser::MetaParser;
+
+    #[test]
+    fn test_generate_html() {
+        let input = MetaParser::new(
+            r#"
+            block := stmt+;
+            stmt  := SAME_INDENT ("pass" | call) NEWLINE?;
+            call  := name "(" (name ("," name)*)? ")";
+            name  := "[a-z]+";
+            "#,
+        )
+        .parse_input_grammar().unwrap();
+        let output = MetaParser::new(r#"call := name "();"; extra := "x";"#).parse_output_grammar().unwrap();
+
+        let html = DocGenerator::new(&input).with_output(&output).with_title("demo").generate();
+        assert!(html.contains("<section class=\"rule\" id=\"rule-call\">"));
+        assert!(html.contains("<a href=\"#rule-name\">"));
+        assert!(html.contains("<g class=\"special\">"));
+        assert!(html.contains("call := name &quot;();&quot;;"));
+        // 出力ルールのない入力ルールと、入力ルールのない出力ルールを報告する
+        assert!(html.contains("No output rule"));
+        assert!(html.contains("extra := &quot;x&quot;;"));
+        assert_eq!(html.matches("<svg").count(), 4);
+    }
+}
@@ -0,0 +1,722 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::meta_parser::{GrammarExpr, InputGrammar};
+
+/// エクスポート形式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// W3C (XML仕様) スタイルのEBNF
+    Ebnf,
+    /// RFC 5234 ABNF
+    Abnf,
+    /// pest の .pest 文法
+    Pest,
+    /// tree-sitter の grammar.js
+    TreeSitter,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<ExportFormat> {
+        match name {
+            "ebnf" => Some(ExportFormat::Ebnf),
+            "abnf" => Some(ExportFormat::Abnf),
+            "pest" => Some(ExportFormat::Pest),
+            "tree-sitter" => Some(ExportFormat::TreeSitter),
+            _ => None,
+        }
+    }
+
+    /// 出力ファイル名
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ExportFormat::Ebnf => "grammar.ebnf",
+            ExportFormat::Abnf => "grammar.abnf",
+            ExportFormat::Pest => "grammar.pest",
+            ExportFormat::TreeSitter => "grammar.js",
+        }
+    }
+}
+
+/// エクスポート結果
+#[derive(Debug)]
+pub struct Export {
+    /// 文法本体
+    pub text: String,
+    /// 追加のファイル (パス, 内容) (例: tree-sitter の外部スキャナのスタブ)
+    pub extra_files: Vec<(String, String)>,
+    /// 変換できなかった・近似した構文の報告
+    pub warnings: Vec<String>,
+}
+
+/// 入力BNFを他のパーサー形式に変換
+/// name: tree-sitter の言語名などに使う文法名
+pub fn export_grammar(grammar: &InputGrammar, format: ExportFormat, name: &str) -> Export {
+    let mut exporter = Exporter {
+        format,
+        warnings: Vec::new(),
+        layout: BTreeSet::new(),
+        current_rule: String::new(),
+    };
+    exporter.export(grammar, name)
+}
+
+/// 正規表現の中間表現 (変換可能な部分集合)
+#[derive(Debug, Clone, PartialEq)]
+enum Regex {
+    /// 1文字
+    Char(char),
+    /// 文字クラス [...] (否定, 範囲のリスト)
+    Class(bool, Vec<(char, char)>),
+    /// 改行以外の任意の1文字 (.)
+    Any,
+    Sequence(Vec<Regex>),
+    ZeroOrMore(Box<Regex>),
+    OneOrMore(Box<Regex>),
+    Optional(Box<Regex>),
+}
+
+/// レイアウトトークン (INDENT / DEDENT / NEWLINE / SAME_INDENT)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Layout {
+    Indent,
+    Dedent,
+    Newline,
+    SameIndent,
+}
+
+impl Layout {
+    fn bnf_name(&self) -> &'static str {
+        match self {
+            Layout::Indent => "INDENT",
+            Layout::Dedent => "DEDENT",
+            Layout::Newline => "NEWLINE",
+            Layout::SameIndent => "SAME_INDENT",
+        }
+    }
+
+    fn tree_sitter_name(&self) -> &'static str {
+        match self {
+            Layout::Indent => "_indent",
+            Layout::Dedent => "_dedent",
+            Layout::Newline => "_newline",
+            Layout::SameIndent => "_same_indent",
+        }
+    }
+}
+
+struct Exporter {
+    format: ExportFormat,
+    warnings: Vec<String>,
+    /// 使われたレイアウトトークン
+    layout: BTreeSet<Layout>,
+    /// 変換中のルール名 (警告用)
+    current_rule: String,
+}
+
+impl Exporter {
+    fn export(&mut self, grammar: &InputGrammar, name: &str) -> Export {
+        // 開始ルールを先頭に、残りは定義順に並べる
+        let mut names: Vec<&String> = vec![&grammar.start_rule];
+        names.extend(grammar.order.iter().filter(|n| **n != grammar.start_rule));
+
+        let mut rules = Vec::new();
+        for rule_name in names {
+            let Some(rule) = grammar.rules.get(rule_name) else { continue };
+            self.current_rule = rule_name.clone();
+            let body = self.expr(&rule.expr);
+            rules.push((rule_name.as_str(), body));
+        }
+
+        let mut extra_files = Vec::new();
+        let text = match self.format {
+            ExportFormat::Ebnf => self.finish_ebnf(&rules),
+            ExportFormat::Abnf => self.finish_abnf(&rules),
+            ExportFormat::Pest => self.finish_pest(&rules),
+            ExportFormat::TreeSitter => {
+                if !self.layout.is_empty() {
+                    extra_files.push(("src/scanner.c".to_string(), self.tree_sitter_scanner(name)));
+                }
+                self.finish_tree_sitter(&rules, name)
+            }
+        };
+
+        Export {
+            text,
+            extra_files,
+            warnings: std::mem::take(&mut self.warnings),
+        }
+    }
+
+    fn warn(&mut self, message: String) {
+        let message = format!("{}: {}", self.current_rule, message);
+        if !self.warnings.contains(&message) {
+            self.warnings.push(message);
+        }
+    }
+
+    /// 式を変換
+    fn expr(&mut self, expr: &GrammarExpr) -> String {
+        match expr {
+            GrammarExpr::Literal(lit) => self.literal(lit),
+            GrammarExpr::Pattern(pattern) => self.pattern(pattern),
+            GrammarExpr::RuleRef(name) => self.rule_ref(name),
+            GrammarExpr::Sequence(items) => {
+                let parts: Vec<String> = items.iter().map(|i| self.expr(i)).collect();
+                match self.format {
+                    ExportFormat::Ebnf | ExportFormat::Abnf => parts.join(" "),
+                    ExportFormat::Pest => parts.join(" ~ "),
+                    ExportFormat::TreeSitter => format!("seq({})", parts.join(", ")),
+                }
+            }
+            GrammarExpr::Choice(choices) => {
+                let parts: Vec<String> = choices.iter().map(|c| self.expr(c)).collect();
+                match self.format {
+                    ExportFormat::Ebnf | ExportFormat::Pest => parts.join(" | "),
+                    ExportFormat::Abnf => parts.join(" / "),
+                    ExportFormat::TreeSitter => format!("choice({})", parts.join(", ")),
+                }
+            }
+            GrammarExpr::ZeroOrMore(inner) => self.repeat(inner, "*"),
+            GrammarExpr::OneOrMore(inner) => self.repeat(inner, "+"),
+            GrammarExpr::Optional(inner) => self.repeat(inner, "?"),
+            GrammarExpr::Group(inner) => match self.format {
+                ExportFormat::TreeSitter => self.expr(inner),
+                _ => format!("({})", self.expr(inner)),
+            },
+            GrammarExpr::Indent => self.layout_token(Layout::Indent),
+            GrammarExpr::Dedent => self.layout_token(Layout::Dedent),
+            GrammarExpr::Newline => self.layout_token(Layout::Newline),
+            GrammarExpr::SameIndent => self.layout_token(Layout::SameIndent),
+            // エラー表示の注釈は他の形式にはない
+            GrammarExpr::Annotated { inner, .. } => self.expr(inner),
+        }
+    }
+
+    /// 後置・前置演算子のオペランドとして使えるよう、必要なら括弧で囲む
+    fn grouped(&mut self, expr: &GrammarExpr) -> String {
+        let text = self.expr(expr);
+        let needs_parens = matches!(expr, GrammarExpr::Sequence(_) | GrammarExpr::Choice(_))
+            && self.format != ExportFormat::TreeSitter;
+        if needs_parens {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    fn repeat(&mut self, inner: &GrammarExpr, op: &str) -> String {
+        let operand = self.grouped(inner);
+        match self.format {
+            ExportFormat::Ebnf | ExportFormat::Pest => format!("{}{}", operand, op),
+            ExportFormat::Abnf => match op {
+                "*" => format!("*{}", operand),
+                "+" => format!("1*{}", operand),
+                _ => format!("[{}]", operand),
+            },
+            ExportFormat::TreeSitter => match op {
+                "*" => format!("repeat({})", operand),
+                "+" => format!("repeat1({})", operand),
+                _ => format!("optional({})", operand),
+            },
+        }
+    }
+
+    fn rule_ref(&self, name: &str) -> String {
+        match self.format {
+            ExportFormat::Ebnf | ExportFormat::Pest => name.to_string(),
+            ExportFormat::Abnf => abnf_rule_name(name),
+            ExportFormat::TreeSitter => format!("$.{}", name),
+        }
+    }
+
+    fn literal(&mut self, lit: &str) -> String {
+        match self.format {
+            ExportFormat::Ebnf => {
+                if lit.contains('"') {
+                    if lit.contains('\'') {
+                        // W3C EBNFのリテラルは引用符をエスケープできない
+                        lit.chars().map(|c| format!("#x{:X}", c as u32)).collect::<Vec<_>>().join(" ")
+                    } else {
+                        format!("'{}'", lit)
+                    }
+                } else {
+                    format!("\"{}\"", lit)
+                }
+            }
+            ExportFormat::Abnf => {
+                // ABNFの "..." は大文字小文字を区別しないため、RFC 7405 の %s を使う
+                if lit.chars().all(|c| (' '..='~').contains(&c) && c != '"') {
+                    format!("%s\"{}\"", lit)
+                } else {
+                    let codes: Vec<String> = lit.chars().map(|c| format!("{:X}", c as u32)).collect();
+                    format!("%x{}", codes.join("."))
+                }
+            }
+            ExportFormat::Pest | ExportFormat::TreeSitter => quote_string(lit),
+        }
+    }
+
+    fn pattern(&mut self, pattern: &str) -> String {
+        if self.format == ExportFormat::TreeSitter {
+            // tree-sitter は正規表現をそのまま扱える
+            return format!("/{}/", pattern.replace('/', "\\/"));
+        }
+
+        match parse_regex(pattern) {
+            Ok(regex) => self.regex(&regex),
+            Err(reason) => {
+                self.warn(format!("pattern /{}/ cannot be converted ({})", pattern, reason));
+                match self.format {
+                    ExportFormat::Ebnf => format!("/* unsupported: {} */", pattern),
+                    ExportFormat::Abnf => format!("<unsupported pattern {}>", pattern.replace('>', "")),
+                    _ => format!("/* unsupported: {} */ ANY", pattern),
+                }
+            }
+        }
+    }
+
+    fn regex(&mut self, regex: &Regex) -> String {
+        match regex {
+            Regex::Char(c) => self.literal(&c.to_string()),
+            Regex::Any => match self.format {
+                ExportFormat::Ebnf => "[^#xA]".to_string(),
+                ExportFormat::Abnf => "(%x0-9 / %xB-10FFFF)".to_string(),
+                _ => "(!\"\\n\" ~ ANY)".to_string(),
+            },
+            Regex::Class(negated, ranges) => self.class(*negated, ranges),
+            Regex::Sequence(items) => {
+                let parts: Vec<String> = items.iter().map(|i| self.regex(i)).collect();
+                match self.format {
+                    ExportFormat::Pest => format!("({})", parts.join(" ~ ")),
+                    _ => format!("({})", parts.join(" ")),
+                }
+            }
+            Regex::ZeroOrMore(inner) => self.regex_repeat(inner, "*"),
+            Regex::OneOrMore(inner) => self.regex_repeat(inner, "+"),
+            Regex::Optional(inner) => self.regex_repeat(inner, "?"),
+        }
+    }
+
+    fn regex_repeat(&mut self, inner: &Regex, op: &str) -> String {
+        let operand = self.regex(inner);
+        match self.format {
+            ExportFormat::Abnf => match op {
+                "*" => format!("*{}", operand),
+                "+" => format!("1*{}", operand),
+                _ => format!("[{}]", operand),
+            },
+            _ => format!("{}{}", operand, op),
+        }
+    }
+
+    fn class(&mut self, negated: bool, ranges: &[(char, char)]) -> String {
+        match self.format {
+            ExportFormat::Ebnf => {
+                let items: String = ranges
+                    .iter()
+                    .map(|(lo, hi)| {
+                        if lo == hi {
+                            format!("#x{:X}", *lo as u32)
+                        } else {
+                            format!("#x{:X}-#x{:X}", *lo as u32, *hi as u32)
+                        }
+                    })
+                    .collect();
+                format!("[{}{}]", if negated { "^" } else { "" }, items)
+            }
+            ExportFormat::Abnf => {
+                if negated {
+                    self.warn("negated character classes have no ABNF equivalent; exported without the negation".to_string());
+                }
+                let items: Vec<String> = ranges
+                    .iter()
+                    .map(|(lo, hi)| {
+                        if lo == hi {
+                            format!("%x{:X}", *lo as u32)
+                        } else {
+                            format!("%x{:X}-{:X}", *lo as u32, *hi as u32)
+                        }
+                    })
+                    .collect();
+                format!("({})", items.join(" / "))
+            }
+            _ => {
+                let items: Vec<String> = ranges
+                    .iter()
+                    .map(|(lo, hi)| {
+                        if lo == hi {
+                            quote_string(&lo.to_string())
+                        } else {
+                            format!("{}..{}", quote_char(*lo), quote_char(*hi))
+                        }
+                    })
+                    .collect();
+                if negated {
+                    format!("(!({}) ~ ANY)", items.join(" | "))
+                } else {
+                    format!("({})", items.join(" | "))
+                }
+            }
+        }
+    }
+
+    fn layout_token(&mut self, token: Layout) -> String {
+        self.layout.insert(token);
+        match self.format {
+            ExportFormat::Ebnf => token.bnf_name().to_string(),
+            ExportFormat::Abnf => match token {
+                Layout::Newline => "NEWLINE".to_string(),
+                _ => format!("<{}>", token.bnf_name()),
+            },
+            ExportFormat::Pest => match token {
+                Layout::Newline => "hensan_newline".to_string(),
+                Layout::Indent => "hensan_indent".to_string(),
+                Layout::Dedent => "hensan_dedent".to_string(),
+                Layout::SameIndent => "hensan_same_indent".to_string(),
+            },
+            ExportFormat::TreeSitter => format!("$.{}", token.tree_sitter_name()),
+        }
+    }
+
+    fn finish_ebnf(&mut self, rules: &[(&str, String)]) -> String {
+        let mut out = String::new();
+        out.push_str("/* Exported from hensan input BNF (W3C EBNF notation).\n");
+        out.push_str("   Spaces and tabs between tokens are skipped implicitly. */\n\n");
+        let width = rules.iter().map(|(n, _)| n.len()).max().unwrap_or(0);
+        for (name, body) in rules {
+            let _ = writeln!(out, "{:width$} ::= {}", name, body, width = width);
+        }
+
+        if !self.layout.is_empty() {
+            out.push_str("\n/* Layout tokens produced by an indentation-aware lexer:\n");
+            for token in &self.layout {
+                let _ = writeln!(out, "   {}", token.bnf_name());
+            }
+            out.push_str("*/\n");
+            let used: Vec<&str> = self.layout.iter().map(|t| t.bnf_name()).collect();
+            self.current_rule = "<grammar>".to_string();
+            self.warn(format!("layout tokens {} are left as external terminals", used.join(", ")));
+        }
+        out
+    }
+
+    fn finish_abnf(&mut self, rules: &[(&str, String)]) -> String {
+        let mut out = String::new();
+        out.push_str("; Exported from hensan input BNF (RFC 5234 ABNF, RFC 7405 %s strings).\n");
+        out.push_str("; Spaces and tabs between tokens are skipped implicitly.\n\n");
+        let names: Vec<String> = rules.iter().map(|(n, _)| abnf_rule_name(n)).collect();
+        let width = names.iter().map(|n| n.len()).max().unwrap_or(0);
+        for (name, (_, body)) in names.iter().zip(rules) {
+            let _ = writeln!(out, "{:width$} = {}", name, body, width = width);
+        }
+
+        if self.layout.contains(&Layout::Newline) {
+            out.push_str("\nNEWLINE = 1*(*WSP (CRLF / LF))\n");
+        }
+        let prose: Vec<&str> = self
+            .layout
+            .iter()
+            .filter(|t| **t != Layout::Newline)
+            .map(|t| t.bnf_name())
+            .collect();
+        if !prose.is_empty() {
+            self.current_rule = "<grammar>".to_string();
+            self.warn(format!("layout tokens {} have no ABNF equivalent; exported as prose values", prose.join(", ")));
+        }
+        out
+    }
+
+    fn finish_pest(&mut self, rules: &[(&str, String)]) -> String {
+        let mut out = String::new();
+        out.push_str("// Exported from hensan input BNF.\n\n");
+        out.push_str("WHITESPACE = _{ \" \" | \"\\t\" }\n\n");
+        for (name, body) in rules {
+            let _ = writeln!(out, "{} = {{ {} }}", name, body);
+        }
+
+        if !self.layout.is_empty() {
+            // インデントは pest のスタック (PUSH / PEEK_ALL / DROP) で近似する
+            out.push_str("\n// Indentation tracking with the pest stack\n");
+            for token in &self.layout {
+                let line = match token {
+                    Layout::Newline => "hensan_newline = _{ NEWLINE ~ (\" \"* ~ NEWLINE)* }",
+                    Layout::Indent => "hensan_indent = _{ PEEK_ALL ~ PUSH(\" \"+) }",
+                    Layout::Dedent => "hensan_dedent = _{ DROP }",
+                    Layout::SameIndent => "hensan_same_indent = _{ PEEK_ALL }",
+                };
+                out.push_str(line);
+                out.push('\n');
+            }
+            if self.layout.contains(&Layout::Indent) || self.layout.contains(&Layout::SameIndent) {
+                self.current_rule = "<grammar>".to_string();
+                self.warn(
+                    "INDENT/SAME_INDENT are approximated with PUSH/PEEK_ALL; rules using them must be made atomic (@{ }) so WHITESPACE does not consume indentation".to_string(),
+                );
+            }
+        }
+        out
+    }
+
+    fn finish_tree_sitter(&mut self, rules: &[(&str, String)], name: &str) -> String {
+        let mut out = String::new();
+        out.push_str("// Exported from hensan input BNF.\n\n");
+        out.push_str("module.exports = grammar({\n");
+        let _ = writeln!(out, "  name: {},", quote_string(name));
+
+        if !self.layout.is_empty() {
+            out.push_str("\n  // Provided by the external scanner in src/scanner.c\n");
+            out.push_str("  externals: $ => [\n");
+            for token in &self.layout {
+                let _ = writeln!(out, "    $.{},", token.tree_sitter_name());
+            }
+            out.push_str("  ],\n");
+            self.current_rule = "<grammar>".to_string();
+            self.warn("layout tokens require an external scanner; src/scanner.c contains a stub to complete".to_string());
+        }
+
+        out.push_str("\n  extras: $ => [/[ \\t]/],\n\n");
+        out.push_str("  rules: {\n");
+        for (name, body) in rules {
+            let _ = writeln!(out, "    {}: $ => {},", name, body);
+        }
+        out.push_str("  },\n");
+        out.push_str("});\n");
+        out
+    }
+
+    /// tree-sitter の外部スキャナのスタブ
+    fn tree_sitter_scanner(&self, name: &str) -> String {
+        let mut out = String::new();
+        out.push_str("#include \"tree_sitter/parser.h\"\n\n");
+        out.push_str("// Token order must match `externals` in grammar.js.\n");
+        out.push_str("enum TokenType {\n");
+        for token in &self.layout {
+            let _ = writeln!(out, "  {},", token.bnf_name());
+        }
+        out.push_str("};\n\n");
+        let _ = writeln!(out, "void *tree_sitter_{}_external_scanner_create(void) {{ return NULL; }}\n", name);
+        let _ = writeln!(out, "void tree_sitter_{}_external_scanner_destroy(void *payload) {{}}\n", name);
+        let _ = writeln!(
+            out,
+            "unsigned tree_sitter_{}_external_scanner_serialize(void *payload, char *buffer) {{ return 0; }}\n",
+            name
+        );
+        let _ = writeln!(
+            out,
+            "void tree_sitter_{}_external_scanner_deserialize(void *payload, const char *buffer, unsigned length) {{}}\n",
+            name
+        );
+        let _ = writeln!(
+            out,
+            "bool tree_sitter_{}_external_scanner_scan(void *payload, TSLexer *lexer, const bool *valid_symbols) {{",
+            name
+        );
+        out.push_str("  // TODO: track an indentation stack like hensan's Parser (indent_stack / pending_dedents)\n");
+        out.push_str("  return false;\n");
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// ABNFのルール名 (アンダースコアは使えないのでハイフンに置き換える)
+fn abnf_rule_name(name: &str) -> String {
+    name.replace('_', "-")
+}
+
+/// JavaScript / pest の文字列リテラルとして引用
+fn quote_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// pest の文字リテラルとして引用
+fn quote_char(c: char) -> String {
+    match c {
+        '\'' => "'\\''".to_string(),
+        '\\' => "'\\\\'".to_string(),
+        '\n' => "'\\n'".to_string(),
+        '\t' => "'\\t'".to_string(),
+        _ => format!("'{}'", c),
+    }
+}
+
+/// 変換可能な部分集合の正規表現をパース
+/// (文字, 文字クラス, `.`, `* + ?` の連続のみ。選択やグループ、回数指定は非対応)
+fn parse_regex(pattern: &str) -> Result<Regex, String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut pos = 0;
+    let mut items = Vec::new();
+
+    while pos < chars.len() {
+        let atom = match chars[pos] {
+            '[' => {
+                pos += 1;
+                parse_class(&chars, &mut pos)?
+            }
+            '\\' => {
+                pos += 1;
+                let c = *chars.get(pos).ok_or("trailing backslash")?;
+                pos += 1;
+                escape_atom(c)?
+            }
+            '.' => {
+                pos += 1;
+                Regex::Any
+            }
+            '(' | ')' | '|' => return Err("groups and alternation are not supported".to_string()),
+            '{' => return Err("counted repetition is not supported".to_string()),
+            '^' | '$' => return Err("anchors are not supported".to_string()),
+            '*' | '+' | '?' => return Err("quantifier without operand".to_string()),
+            c => {
+                pos += 1;
+                Regex::Char(c)
+            }
+        };
+
+        let atom = match chars.get(pos) {
+            Some('*') => Regex::ZeroOrMore(Box::new(atom)),
+            Some('+') => Regex::OneOrMore(Box::new(atom)),
+            Some('?') => Regex::Optional(Box::new(atom)),
+            _ => {
+                items.push(atom);
+                continue;
+            }
+        };
+        pos += 1;
+        if matches!(chars.get(pos), Some('?') | Some('+')) {
+            return Err("lazy and possessive quantifiers are not supported".to_string());
+        }
+        items.push(atom);
+    }
+
+    if items.len() == 1 {
+        Ok(items.pop().unwrap())
+    } else {
+        Ok(Regex::Sequence(items))
+    }
+}
+
+/// 文字クラスをパース (開き括弧の直後から)
+fn parse_class(chars: &[char], pos: &mut usize) -> Result<Regex, String> {
+    let negated = chars.get(*pos) == Some(&'^');
+    if negated {
+        *pos += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let c = *chars.get(*pos).ok_or("unclosed character class")?;
+        *pos += 1;
+        if c == ']' && !first {
+            break;
+        }
+        first = false;
+
+        let lo = if c == '\\' {
+            let e = *chars.get(*pos).ok_or("trailing backslash")?;
+            *pos += 1;
+            match escape_atom(e)? {
+                Regex::Char(c) => c,
+                Regex::Class(false, class_ranges) => {
+                    ranges.extend(class_ranges);
+                    continue;
+                }
+                _ => return Err(format!("escape \\{} is not supported in a class", e)),
+            }
+        } else {
+            c
+        };
+
+        if chars.get(*pos) == Some(&'-') && chars.get(*pos + 1).is_some_and(|c| *c != ']') {
+            let hi = chars[*pos + 1];
+            *pos += 2;
+            ranges.push((lo, hi));
+        } else {
+            ranges.push((lo, lo));
+        }
+    }
+
+    Ok(Regex::Class(negated, ranges))
+}
+
+/// バックスラッシュエスケープを変換
+fn escape_atom(c: char) -> Result<Regex, String> {
+    match c {
+        'd' => Ok(Regex::Class(false, vec![('0', '9')])),
+        'w' => Ok(Regex::Class(false, vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')])),
+        's' => Ok(Regex::Class(false, vec![(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r')])),
+        'n' => Ok(Regex::Char('\n')),
+        't' => Ok(Regex::Char('\t')),
+        'r' => Ok(Regex::Char('\r')),
+        'D' | 'W' | 'S' | 'b' | 'B' => Err(format!("\\{} is not supported", c)),
+        c if c.is_alphanumeric() => Err(format!("\\{} is not supported", c)),
+        c => Ok(Regex::Char(c)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta_parser::MetaParser;
+
+    const GRAMMAR: &str = r#"
+        block := stmt+;
+        stmt  := SAME_INDENT "if" name ":" NEWLINE INDENT block DEDENT | name ("," name)*;
+        name  := "[a-zA-Z_]+";
+        num   := "(0|[1-9][0-9]*)";
+    "#;
+
+    fn export(format: ExportFormat) -> Export {
+        let grammar = MetaParser::new(GRAMMAR).parse_input_grammar().unwrap();
+        export_grammar(&grammar, format, "demo")
+    }
+
+    #[test]
+    fn test_export_ebnf() {
+        let result = export(ExportFormat::Ebnf);
+        assert!(result.text.contains("block ::= stmt+"));
+        assert!(result.text.contains("name  ::= [#x61-#x7A#x41-#x5A#x5F]+"));
+        assert!(result.text.contains("(\",\" name)*"));
+        assert!(result.warnings.iter().any(|w| w.starts_with("num:")));
+    }
+
+    #[test]
+    fn test_export_abnf() {
+        let result = export(ExportFormat::Abnf);
+        assert!(result.text.contains("block = 1*stmt"));
+        assert!(result.text.contains("%s\"if\""));
+        assert!(result.text.contains("<INDENT>"));
+        assert!(result.text.contains("NEWLINE = "));
+    }
+
+    #[test]
+    fn test_export_pest() {
+        let result = export(ExportFormat::Pest);
+        assert!(result.text.contains("name = { ('a'..'z' | 'A'..'Z' | \"_\")+ }"));
+        assert!(result.text.contains("hensan_indent = _{ PEEK_ALL ~ PUSH(\" \"+) }"));
+    }
+
+    #[test]
+    fn test_export_tree_sitter() {
+        let result = export(ExportFormat::TreeSitter);
+        assert!(result.text.contains("block: $ => repeat1($.stmt),"));
+        assert!(result.text.contains("num: $ => /(0|[1-9][0-9]*)/,"));
+        assert!(result.text.contains("$._indent,"));
+        assert_eq!(result.extra_files[0].0, "src/scanner.c");
+        // tree-sitter は正規表現をそのまま扱えるので num の警告は出ない
+        assert!(!result.warnings.iter().any(|w| w.starts_with("num:")));
+    }
+}
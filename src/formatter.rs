@@ -0,0 +1,244 @@
+use crate::meta_parser::InputGrammar;
+use crate::parser::{ParseError, Parser};
+use crate::unparser::{UnparseError, Unparser};
+
+/// 整形のエラー
+#[derive(Debug)]
+pub enum FormatError {
+    /// ソースのパースに失敗した
+    Parse(ParseError),
+    /// 入力BNFに逆パースできない構文がある
+    Unparse(UnparseError),
+}
+
+/// ソースフォーマッター
+/// 入力BNFでパースしたASTを逆パースし、正規化されたスタイルで再出力する
+/// (コメントはパース前に取り除き、整形後に元の位置へ戻す)
+pub struct Formatter<'a> {
+    grammar: &'a InputGrammar,
+    /// インデント1段あたりのスペース数
+    indent_width: usize,
+    /// 行コメントの開始記号 (例: "#", "//")
+    comment_marker: String,
+}
+
+/// ソースから取り出したコメント
+#[derive(Debug, Default)]
+struct Comments {
+    /// コード行のインデックスごとの、その行の前に置く行コメント
+    /// (直前に空行があったかどうかと、コメント本文)
+    leading: Vec<Vec<(bool, String)>>,
+    /// コード行のインデックスごとの行末コメント
+    trailing: Vec<Option<String>>,
+    /// コード行のインデックスごとの、直前に空行があったか
+    blank_before: Vec<bool>,
+    /// 最後のコード行より後ろにある行コメント
+    tail: Vec<(bool, String)>,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(grammar: &'a InputGrammar) -> Self {
+        Formatter {
+            grammar,
+            indent_width: 4,
+            comment_marker: "#".to_string(),
+        }
+    }
+
+    /// インデント幅を設定
+    pub fn with_indent_width(mut self, width: usize) -> Self {
+        self.indent_width = width;
+        self
+    }
+
+    /// 行コメントの開始記号を設定
+    pub fn with_comment_marker(mut self, marker: &str) -> Self {
+        self.comment_marker = marker.to_string();
+        self
+    }
+
+    /// ソースコードを整形
+    #[allow(clippy::result_large_err)]
+    pub fn format(&self, source: &str) -> Result<String, FormatError> {
+        let (stripped, comments) = self.extract_comments(source);
+
+        // パーサーは先頭の空行を読み飛ばせないため、最初のコード行から始める
+        let skipped_lines = stripped.chars().take_while(|c| *c == '\n').count();
+
+        // コメントを除いたソースをパース (行・列はコメント除去前と一致する)
+        let ast = Parser::new(self.grammar, &stripped[skipped_lines..])
+            .parse()
+            .map_err(|mut err| {
+                err.line += skipped_lines;
+                err.position += skipped_lines;
+                FormatError::Parse(err)
+            })?;
+
+        let code = Unparser::new(self.grammar)
+            .with_indent_width(self.indent_width)
+            .unparse(&ast)
+            .map_err(FormatError::Unparse)?;
+
+        Ok(self.attach_comments(&code, &comments))
+    }
+
+    /// コメントを取り除いたソースと、取り出したコメントを返す
+    fn extract_comments(&self, source: &str) -> (String, Comments) {
+        let mut stripped = String::with_capacity(source.len());
+        let mut comments = Comments::default();
+        let mut pending: Vec<(bool, String)> = Vec::new();
+        let mut blank = false;
+
+        for line in source.lines() {
+            let (code, comment) = split_comment(line, &self.comment_marker);
+            let comment = comment.map(|c| c.trim_end().to_string());
+
+            if code.trim().is_empty() {
+                // 連続する空行は1行にまとめて、次の行に記録する
+                match comment {
+                    Some(c) => {
+                        pending.push((blank, c));
+                        blank = false;
+                    }
+                    None => blank = true,
+                }
+                stripped.push('\n');
+                continue;
+            }
+
+            comments.leading.push(std::mem::take(&mut pending));
+            comments.trailing.push(comment);
+            comments.blank_before.push(blank);
+            blank = false;
+
+            stripped.push_str(code.trim_end());
+            stripped.push('\n');
+        }
+
+        comments.tail = pending;
+        (stripped, comments)
+    }
+
+    /// 整形済みのコードにコメントと空行を戻す
+    /// 整形はコード行の分割・結合をしないため、n番目のコード行同士が対応する
+    fn attach_comments(&self, code: &str, comments: &Comments) -> String {
+        let mut result = String::new();
+        let code_lines: Vec<&str> = code.lines().filter(|l| !l.trim().is_empty()).collect();
+
+        for (i, line) in code_lines.iter().enumerate() {
+            let indent: String = line.chars().take_while(|c| *c == ' ').collect();
+            for (blank, comment) in comments.leading.get(i).into_iter().flatten() {
+                push_blank_line(&mut result, *blank);
+                result.push_str(&indent);
+                result.push_str(comment);
+                result.push('\n');
+            }
+
+            push_blank_line(&mut result, comments.blank_before.get(i).copied().unwrap_or(false));
+
+            result.push_str(line);
+            if let Some(Some(comment)) = comments.trailing.get(i) {
+                result.push_str("  ");
+                result.push_str(comment);
+            }
+            result.push('\n');
+        }
+
+        // 対応するコード行が見つからなかったコメントは末尾にまとめる
+        let orphaned_leading = comments.leading.iter().skip(code_lines.len()).flatten();
+        let orphaned_trailing = comments
+            .trailing
+            .iter()
+            .skip(code_lines.len())
+            .flatten()
+            .map(|c| (false, c.clone()));
+        for (blank, comment) in orphaned_leading.cloned().chain(orphaned_trailing).chain(comments.tail.iter().cloned()) {
+            push_blank_line(&mut result, blank);
+            result.push_str(&comment);
+            result.push('\n');
+        }
+
+        result
+    }
+}
+
+/// 空行を1行追加 (ファイル先頭には置かない)
+fn push_blank_line(result: &mut String, blank: bool) {
+    if blank && !result.is_empty() {
+        result.push('\n');
+    }
+}
+
+/// 行をコードとコメントに分割 (文字列リテラル内のコメント記号は無視)
+fn split_comment<'s>(line: &'s str, marker: &str) -> (&'s str, Option<&'s str>) {
+    if marker.is_empty() {
+        return (line, None);
+    }
+
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (i, ch) in line.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == q {
+                quote = None;
+            }
+        } else if ch == '"' || ch == '\'' {
+            quote = Some(ch);
+        } else if line[i..].starts_with(marker) {
+            return (&line[..i], Some(&line[i..]));
+        }
+    }
+
+    (line, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta_parser::MetaParser;
+
+    const GRAMMAR: &str = r#"
+        program   := func_decl* toplevel?;
+        toplevel  := stmt+;
+        func_decl := "def" name "(" ")" ":" NEWLINE INDENT block DEDENT;
+        block     := stmt+;
+        stmt      := SAME_INDENT (if_stmt | call_stmt);
+        if_stmt   := "if" lhs "==" rhs ":" NEWLINE INDENT block DEDENT;
+        call_stmt := name "(" args? ")" NEWLINE?;
+        args      := arg ("," arg)*;
+        arg       := string | number | name;
+        lhs       := name;
+        rhs       := number;
+        string    := [["][^"]*["]];
+        number    := "[0-9]+";
+        name      := "[a-zA-Z_]+";
+    "#;
+
+    fn format(source: &str) -> String {
+        let grammar = MetaParser::new(GRAMMAR).parse_input_grammar().unwrap();
+        Formatter::new(&grammar).format(source).expect("format failed")
+    }
+
+    #[test]
+    fn test_format_normalizes_spacing_and_indent() {
+        let source = "def check():\n  if x ==    0:\n        print( x,1 )\n\n\n\ncheck()\n";
+        assert_eq!(
+            format(source),
+            "def check():\n    if x == 0:\n        print(x, 1)\n\ncheck()\n"
+        );
+    }
+
+    #[test]
+    fn test_format_preserves_comments() {
+        let source = "# header\ndef f():\n    # inside\n    g(\"a # b\")   # trailing\n\nf()\n# end\n";
+        assert_eq!(
+            format(source),
+            "# header\ndef f():\n    # inside\n    g(\"a # b\")  # trailing\n\nf()\n# end\n"
+        );
+    }
+}
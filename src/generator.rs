@@ -1,4 +1,6 @@
-use crate::ast::ASTNode;
+use std::fmt::Write;
+
+use crate::ast::{ASTNode, Span};
 use crate::meta_parser::{OutputExpr, OutputGrammar};
 
 /// コード生成器
@@ -7,6 +9,104 @@ pub struct Generator<'a> {
     grammar: &'a OutputGrammar,
 }
 
+/// 出力の1区間を生成した出力ルールと AST ノード
+#[derive(Debug, Clone)]
+pub struct GenerationSpan {
+    /// 出力中の範囲 (バイトオフセット)
+    pub start: usize,
+    pub end: usize,
+    /// 出力したルール (出力ルールがなかった場合も AST のルール名)
+    pub rule: String,
+    /// 出力した OutputExpr の種類 ("Literal", "Match", "Join", "Fallback"、パースエラーの印は "Error")
+    pub expr: &'static str,
+    /// 出力のもとになった AST ノードの名前と値
+    pub node: String,
+    pub node_value: String,
+    /// AST ノードの入力ソース中の範囲
+    pub source: Option<Span>,
+}
+
+/// 生成トレース
+#[derive(Debug, Default)]
+pub struct GenerationTrace {
+    /// 出力の区間 (出力順)
+    pub spans: Vec<GenerationSpan>,
+    /// 出力ルールがなく、AST をそのまま出力した箇所や、出力しなかったパースエラー
+    pub warnings: Vec<String>,
+}
+
+/// 生成中の出力 (トレースが有効なら区間も記録する)
+struct Output {
+    text: String,
+    trace: Option<GenerationTrace>,
+}
+
+impl Output {
+    /// 出力を追加し、トレースが有効なら区間を記録
+    fn emit(&mut self, text: &str, rule: &str, expr: &'static str, ast: &ASTNode) {
+        let start = self.text.len();
+        self.text.push_str(text);
+        if let Some(trace) = &mut self.trace {
+            if !text.is_empty() {
+                trace.spans.push(GenerationSpan {
+                    start,
+                    end: self.text.len(),
+                    rule: rule.to_string(),
+                    expr,
+                    node: ast.name.clone(),
+                    node_value: ast.value.clone(),
+                    source: ast.span,
+                });
+            }
+        }
+    }
+
+    fn warn(&mut self, message: String) {
+        if let Some(trace) = &mut self.trace {
+            trace.warnings.push(message);
+        }
+    }
+
+    /// 巻き戻し用の位置
+    fn checkpoint(&self) -> (usize, usize, usize) {
+        let (spans, warnings) = self.trace.as_ref().map_or((0, 0), |t| (t.spans.len(), t.warnings.len()));
+        (self.text.len(), spans, warnings)
+    }
+
+    /// 出力を巻き戻す (生成してから捨てた部分をトレースにも残さない)
+    fn rollback(&mut self, (len, spans, warnings): (usize, usize, usize)) {
+        self.text.truncate(len);
+        if let Some(trace) = &mut self.trace {
+            trace.spans.truncate(spans);
+            trace.warnings.truncate(warnings);
+        }
+    }
+}
+
+impl GenerationTrace {
+    /// 区間ごとに1行の一覧にする
+    pub fn to_text(&self, output: &str) -> String {
+        let mut text = String::new();
+        for span in &self.spans {
+            let _ = writeln!(
+                text,
+                "{:>5}..{:<5} {:<16} {:<8} {}{} {:?}",
+                span.start,
+                span.end,
+                span.rule,
+                span.expr,
+                span.node,
+                if span.node_value.is_empty() { String::new() } else { format!("={:?}", span.node_value.trim()) },
+                &output[span.start..span.end]
+            );
+        }
+        for warning in &self.warnings {
+            let _ = writeln!(text, "Warning: {}", warning);
+        }
+        text
+    }
+}
+
 impl<'a> Generator<'a> {
     pub fn new(grammar: &'a OutputGrammar) -> Self {
         Generator { grammar }
@@ -14,28 +114,58 @@ impl<'a> Generator<'a> {
 
     /// ASTから出力コードを生成
     pub fn generate(&self, ast: &ASTNode) -> String {
+        let mut output = Output { text: String::new(), trace: None };
         // 最初の呼び出しはコンテキストなし
-        self.generate_rule(&ast.name, ast, "")
+        self.generate_rule(&ast.name, ast, "", &mut output);
+        output.text
+    }
+
+    /// ASTから出力コードを生成し、どの出力ルールがどこを出力したかを記録する
+    pub fn generate_traced(&self, ast: &ASTNode) -> (String, GenerationTrace) {
+        let mut output = Output { text: String::new(), trace: Some(GenerationTrace::default()) };
+        self.generate_rule(&ast.name, ast, "", &mut output);
+        (output.text, output.trace.unwrap_or_default())
     }
 
     /// 指定したルールに基づいて生成
     /// context: このルールを呼び出した親ルール名
-    fn generate_rule(&self, rule_name: &str, ast: &ASTNode, context: &str) -> String {
+    fn generate_rule(&self, rule_name: &str, ast: &ASTNode, context: &str, output: &mut Output) {
+        // パースエラーから回復したノードは、出力BNFに error ルールがあればそれで出力し、なければ既定の印を出力する
+        // (出力言語のコメントの書き方は分からないので、コメントにするなら error ルールで書く)
+        if let Some(skipped) = ast.error_text() {
+            match ast.get_child("error") {
+                Some(error) if self.grammar.rules.contains_key("error") => self.generate_rule("error", error, rule_name, output),
+                _ => {
+                    output.warn(format!(
+                        "Parse error in '{}': emitted a marker for the skipped {:?} (define an `error` rule in the output grammar to emit it differently)",
+                        rule_name,
+                        skipped.trim()
+                    ));
+                    output.emit(&format!("<<parse error in '{}': {:?}>>", rule_name, skipped.trim()), rule_name, "Error", ast);
+                }
+            }
+            return;
+        }
+
         if let Some(rule) = self.grammar.rules.get(rule_name) {
-            self.generate_expr(&rule.expr, ast, rule_name, context)
+            self.generate_expr(&rule.expr, ast, rule_name, context, output);
         } else {
             // 出力ルールが見つからない場合は、ASTの値をそのまま返す
             if !ast.value.is_empty() {
-                ast.value.clone()
+                output.warn(format!(
+                    "No output rule for '{}': emitted the matched text {:?} as is",
+                    rule_name,
+                    ast.value.trim()
+                ));
+                output.emit(&ast.value, rule_name, "Fallback", ast);
             } else {
+                output.warn(format!("No output rule for '{}': generated its children in input order", rule_name));
                 // 子ノードを再帰的に処理
-                let mut result = String::new();
-                for (_, children) in &ast.children {
+                for children in ast.children.values() {
                     for child in children {
-                        result.push_str(&self.generate_rule(&child.name, child, rule_name));
+                        self.generate_rule(&child.name, child, rule_name, output);
                     }
                 }
-                result
             }
         }
     }
@@ -43,101 +173,206 @@ impl<'a> Generator<'a> {
     /// 式に基づいて生成
     /// current_rule: 現在処理中のルール名
     /// context: このルールを呼び出した親ルール名
-    fn generate_expr(&self, expr: &OutputExpr, ast: &ASTNode, current_rule: &str, context: &str) -> String {
+    fn generate_expr(&self, expr: &OutputExpr, ast: &ASTNode, current_rule: &str, context: &str, output: &mut Output) {
         match expr {
             OutputExpr::Literal(lit) => {
                 // エスケープシーケンスを処理
-                lit.replace("\\n", "\n")
+                let text = lit.replace("\\n", "\n")
                    .replace("\\t", "\t")
-                   .replace("\\r", "\r")
+                   .replace("\\r", "\r");
+                output.emit(&text, current_rule, "Literal", ast);
             }
 
             OutputExpr::RuleRef(name) => {
                 // ASTから対応する子ノードを検索
                 if let Some(child) = ast.get_child(name) {
                     // 子ルールを呼ぶ時は、現在のルール名をコンテキストとして渡す
-                    self.generate_rule(name, child, current_rule)
+                    self.generate_rule(name, child, current_rule, output)
                 } else if &ast.name == name {
                     // 現在のノード自体がそのルールの場合
-                    self.generate_rule(name, ast, current_rule)
+                    self.generate_rule(name, ast, current_rule, output)
                 } else if !ast.value.is_empty() && ast.children.is_empty() {
                     // 葉ノードの場合のみフォールバック
                     // (例: call_arg := name; で call_arg が name の値を直接持つ場合)
-                    self.generate_rule(name, ast, current_rule)
-                } else {
-                    // 子ノードが存在しない場合は空文字を返す
-                    String::new()
+                    self.generate_rule(name, ast, current_rule, output)
                 }
+                // 子ノードが存在しない場合は何も出力しない
             }
 
             OutputExpr::Sequence(items) => {
-                let mut result = String::new();
                 for item in items {
-                    result.push_str(&self.generate_expr(item, ast, current_rule, context));
+                    self.generate_expr(item, ast, current_rule, context, output);
                 }
-                result
             }
 
             OutputExpr::Optional(inner) => {
                 // 対応する子ノードが存在するかチェック
-                let inner_result = self.generate_expr(inner, ast, current_rule, context);
-                if inner_result.trim().is_empty() {
-                    String::new()
-                } else {
-                    inner_result
+                let checkpoint = output.checkpoint();
+                self.generate_expr(inner, ast, current_rule, context, output);
+                if output.text[checkpoint.0..].trim().is_empty() {
+                    output.rollback(checkpoint);
                 }
             }
 
             OutputExpr::Join { rule, separator } => {
                 // 指定されたルールの全ての子ノードをセパレータで結合
-                let children = ast.get_children(rule);
-                let parts: Vec<String> = children
-                    .iter()
-                    .map(|child| self.generate_rule(rule, child, current_rule))
-                    .collect();
                 // セパレータのエスケープシーケンスを処理
                 let sep = separator
                     .replace("\\n", "\n")
                     .replace("\\t", "\t")
                     .replace("\\r", "\r");
-                parts.join(&sep)
+                for (i, child) in ast.get_children(rule).iter().enumerate() {
+                    if i > 0 {
+                        output.emit(&sep, current_rule, "Join", ast);
+                    }
+                    self.generate_rule(rule, child, current_rule, output);
+                }
             }
 
             OutputExpr::Match { cases, default } => {
                 // @valueに基づいてマッチング
                 let value = &ast.value;
-                for (pattern, replacement) in cases {
-                    if value == pattern {
-                        return replacement.clone();
-                    }
-                }
-                // デフォルトケース
-                if default == "@value" {
-                    value.clone()
-                } else {
-                    default.clone()
-                }
+                let text = cases
+                    .iter()
+                    .find(|(pattern, _)| value == pattern)
+                    .map(|(_, replacement)| replacement)
+                    // デフォルトケース
+                    .unwrap_or(if default == "@value" { value } else { default });
+                output.emit(text, current_rule, "Match", ast);
             }
 
             OutputExpr::ContextIf { context_value, then_expr, else_expr } => {
                 // コンテキスト（親ルール名）に基づいて条件分岐
                 if context == context_value {
-                    self.generate_expr(then_expr, ast, current_rule, context)
+                    self.generate_expr(then_expr, ast, current_rule, context, output)
                 } else {
-                    self.generate_expr(else_expr, ast, current_rule, context)
+                    self.generate_expr(else_expr, ast, current_rule, context, output)
                 }
             }
 
             OutputExpr::Choice(alternatives) => {
                 // 各選択肢を試して、最初に成功したものを返す
                 for alt in alternatives {
-                    let result = self.generate_expr(alt, ast, current_rule, context);
-                    if !result.is_empty() {
-                        return result;
+                    let checkpoint = output.checkpoint();
+                    self.generate_expr(alt, ast, current_rule, context, output);
+                    if output.text.len() > checkpoint.0 {
+                        return;
                     }
+                    output.rollback(checkpoint);
                 }
-                String::new()
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta_parser::MetaParser;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_generation_trace() {
+        let input = MetaParser::new(
+            r#"
+            func_decl := ret_type name "(" arg? ")" ";";
+            arg       := type name;
+            ret_type  := "void" | "int";
+            type      := "int" | "float";
+            name      := "[a-z]+";
+            "#,
+        )
+        .parse_input_grammar().unwrap();
+        let output = MetaParser::new(
+            r#"
+            func_decl := "fn " name "(" arg? ")" " -> " ret_type ";";
+            arg       := name ": " type;
+            ret_type  := match @value { "int" => "i32", _ => @value };
+            "#,
+        )
+        .parse_output_grammar().unwrap();
+
+        let ast = Parser::new(&input, "int f(float x);").parse().unwrap();
+        let generator = Generator::new(&output);
+        let (text, trace) = generator.generate_traced(&ast);
+        assert_eq!(text, generator.generate(&ast));
+        assert_eq!(text, "fn f(x: float) -> i32;");
+
+        // 区間は出力全体を隙間なく覆う
+        assert_eq!(trace.spans.first().unwrap().start, 0);
+        assert_eq!(trace.spans.last().unwrap().end, text.len());
+        assert!(trace.spans.windows(2).all(|w| w[0].end == w[1].start));
+
+        let ret = trace.spans.iter().find(|s| &text[s.start..s.end] == "i32").unwrap();
+        assert_eq!((ret.rule.as_str(), ret.expr, ret.node.as_str()), ("ret_type", "Match", "ret_type"));
+
+        // name と type には出力ルールがない
+        let fallback = trace.spans.iter().find(|s| s.rule == "type").unwrap();
+        assert_eq!(fallback.expr, "Fallback");
+        assert_eq!(trace.warnings.len(), 3);
+        assert!(trace.warnings.contains(&"No output rule for 'type': emitted the matched text \"float\" as is".to_string()));
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let input = MetaParser::new(
+            r#"
+            program := stmt+;
+            stmt    := SAME_INDENT (if_stmt | call) NEWLINE?;
+            if_stmt := "if" name ":" NEWLINE INDENT program DEDENT;
+            call    := name "(" ")";
+            name    := "[a-z]+";
+            "#,
+        )
+        .parse_input_grammar().unwrap();
+        let output = MetaParser::new(
+            r#"
+            program := stmt join "\n";
+            stmt    := if_stmt | call;
+            if_stmt := "if (" name ") { " program " }";
+            call    := name "();";
+            "#,
+        )
+        .parse_output_grammar().unwrap();
+
+        let source = "f(\nif x:\n    ??\n    g()\nif y\n    h()\nk()\n";
+        let mut parser = Parser::new(&input, source).with_recovery(&["stmt".to_string()]);
+        let ast = parser.parse().unwrap();
+        let lines: Vec<usize> = parser.errors().iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![1, 3, 5]);
+
+        // error ルールがなければ、読み飛ばしたところに既定の印を出力する
+        let (text, trace) = Generator::new(&output).generate_traced(&ast);
+        assert_eq!(
+            text,
+            "<<parse error in 'stmt': \"f(\">>\nif (x) { <<parse error in 'stmt': \"??\">>\ng(); }\n<<parse error in 'stmt': \"if y\\n    h()\">>\nk();"
+        );
+        let skipped: Vec<&String> = trace.warnings.iter().filter(|w| w.starts_with("Parse error")).collect();
+        assert_eq!(skipped.len(), 3);
+        assert_eq!(
+            skipped[1],
+            "Parse error in 'stmt': emitted a marker for the skipped \"??\" (define an `error` rule in the output grammar to emit it differently)"
+        );
+        let marker = trace.spans.iter().find(|s| s.expr == "Error").unwrap();
+        assert_eq!(&text[marker.start..marker.end], "<<parse error in 'stmt': \"f(\">>");
+
+        // error ルールがあれば、読み飛ばしたテキストをそれで出力する (h() のブロックも一緒に読み飛ばす)
+        let with_error = MetaParser::new(
+            r#"
+            program := stmt join "\n";
+            stmt    := if_stmt | call;
+            if_stmt := "if (" name ") { " program " }";
+            call    := name "();";
+            error   := match @value { "??" => "/* ?? */", _ => @value };
+            "#,
+        )
+        .parse_output_grammar().unwrap();
+        assert_eq!(
+            Generator::new(&with_error).generate(&ast),
+            "f(\nif (x) { /* ?? */\ng(); }\nif y\n    h()\nk();"
+        );
+
+        // 同期ルールを指定しなければ最初のエラーで止まる
+        assert_eq!(Parser::new(&input, source).parse().unwrap_err().line, 1);
+    }
+}
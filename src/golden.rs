@@ -0,0 +1,305 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::batch;
+use crate::diff;
+use crate::generator::Generator;
+use crate::meta_parser::{InputGrammar, OutputGrammar};
+use crate::parser::Parser;
+use crate::rewrite::{self, RewritePass};
+
+/// 期待するパースエラーを書いたファイルの拡張子 (case.expected.err)
+pub const ERROR_EXT: &str = "err";
+
+/// 1つの期待ファイルと照らし合わせた結果
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    /// 期待と違った (unified diff か、その理由)
+    Failed(String),
+    /// --bless で期待ファイルを書いた
+    Blessed,
+}
+
+/// 1つの期待ファイルの結果
+#[derive(Debug)]
+pub struct Check {
+    /// 期待ファイル
+    pub expected: PathBuf,
+    pub outcome: Outcome,
+}
+
+/// 1つのケース (ソースファイル) の結果
+#[derive(Debug)]
+pub struct CaseResult {
+    pub path: PathBuf,
+    pub checks: Vec<Check>,
+}
+
+impl CaseResult {
+    pub fn failed(&self) -> bool {
+        self.checks.iter().any(|check| matches!(check.outcome, Outcome::Failed(_)))
+    }
+}
+
+/// 出力BNFと、その出力の期待ファイルの拡張子 (None ならソースと同じ)
+pub struct GoldenTarget<'a> {
+    pub grammar: &'a OutputGrammar,
+    pub ext: Option<String>,
+}
+
+/// ゴールデンテスト
+/// case.py を変換した結果を case.expected.rs と、パースエラーを case.expected.err と比べる
+pub struct GoldenTester<'a> {
+    input: &'a InputGrammar,
+    targets: Vec<GoldenTarget<'a>>,
+    start_rule: Option<String>,
+    recovery_rules: Vec<String>,
+    passes: &'a [RewritePass],
+    /// 比べる代わりに期待ファイルを書き換える
+    bless: bool,
+}
+
+/// ソースの変換結果
+enum Actual {
+    /// 出力ごとの変換結果 (ファイルに書き出すときと同じく末尾に改行を付ける)
+    Outputs(Vec<String>),
+    /// パースエラー (回復したものも含めて全て)
+    Error(String),
+}
+
+impl<'a> GoldenTester<'a> {
+    pub fn new(input: &'a InputGrammar, targets: Vec<GoldenTarget<'a>>) -> Self {
+        GoldenTester { input, targets, start_rule: None, recovery_rules: Vec::new(), passes: &[], bless: false }
+    }
+
+    /// 開始ルール (Parser::with_start_rule と同じ)
+    pub fn with_start_rule(mut self, rule: Option<&str>) -> Self {
+        self.start_rule = rule.map(|rule| rule.to_string());
+        self
+    }
+
+    /// 指定したルールでエラーから回復する (Parser::with_recovery と同じ)
+    pub fn with_recovery(mut self, rules: &[String]) -> Self {
+        self.recovery_rules = rules.to_vec();
+        self
+    }
+
+    /// 出力の前に AST を書き換えるパス
+    pub fn with_passes(mut self, passes: &'a [RewritePass]) -> Self {
+        self.passes = passes;
+        self
+    }
+
+    /// 期待と違ったら、比べる代わりに期待ファイルを書き換える
+    pub fn with_bless(mut self, bless: bool) -> Self {
+        self.bless = bless;
+        self
+    }
+
+    /// dir 以下のケースを全て調べる
+    pub fn run_dir(&self, dir: &Path, exts: &[String]) -> io::Result<Vec<CaseResult>> {
+        Ok(discover(dir, exts)?.iter().map(|path| self.run_case(&dir.join(path))).collect())
+    }
+
+    /// 1つのケースを変換して期待ファイルと比べる
+    pub fn run_case(&self, path: &Path) -> CaseResult {
+        let mut result = CaseResult { path: path.to_path_buf(), checks: Vec::new() };
+        let error_path = expected_path(path, ERROR_EXT);
+        let actual = match fs::read_to_string(path) {
+            Ok(source) => self.translate(&source),
+            Err(e) => {
+                let outcome = Outcome::Failed(format!("Error reading {}: {}", path.display(), e));
+                result.checks.push(Check { expected: path.to_path_buf(), outcome });
+                return result;
+            }
+        };
+
+        let output_paths: Vec<PathBuf> = self.targets.iter().map(|target| self.output_path(path, target)).collect();
+        match actual {
+            Actual::Error(error) => {
+                // 変換できたときの期待ファイルが残っていれば、それとは食い違っている
+                let stale: Vec<_> = output_paths.iter().filter(|path| path.exists()).collect();
+                let outcome = if self.bless {
+                    match stale.iter().try_for_each(fs::remove_file) {
+                        Ok(()) => self.bless(&error_path, &error),
+                        Err(e) => Outcome::Failed(format!("Error removing stale expectations of {}: {}", path.display(), e)),
+                    }
+                } else if !error_path.exists() && !stale.is_empty() {
+                    Outcome::Failed(format!("expected {} but parsing failed:\n{}", stale[0].display(), error))
+                } else {
+                    compare(&error_path, &error)
+                };
+                result.checks.push(Check { expected: error_path, outcome });
+            }
+            Actual::Outputs(outputs) => {
+                if error_path.exists() && !self.bless {
+                    let outcome = Outcome::Failed(format!("expected a parse error ({}) but parsing succeeded", error_path.display()));
+                    result.checks.push(Check { expected: error_path, outcome });
+                    return result;
+                }
+                if error_path.exists() {
+                    if let Err(e) = fs::remove_file(&error_path) {
+                        let outcome = Outcome::Failed(format!("Error removing {}: {}", error_path.display(), e));
+                        result.checks.push(Check { expected: error_path, outcome });
+                        return result;
+                    }
+                }
+                for (expected, output) in output_paths.into_iter().zip(outputs) {
+                    let outcome = if self.bless { self.bless(&expected, &output) } else { compare(&expected, &output) };
+                    result.checks.push(Check { expected, outcome });
+                }
+            }
+        }
+        result
+    }
+
+    /// ソースをパースし、出力ごとに変換する
+    fn translate(&self, source: &str) -> Actual {
+        let mut parser = Parser::new(self.input, source).with_recovery(&self.recovery_rules);
+        if let Some(rule) = &self.start_rule {
+            parser = parser.with_start_rule(rule);
+        }
+        let parsed = parser.parse();
+        let mut errors: Vec<String> = parser.errors().iter().map(|err| err.to_string()).collect();
+        let ast = match parsed {
+            Ok(ast) if errors.is_empty() => ast,
+            Ok(_) => return Actual::Error(errors.join("\n")),
+            Err(err) => {
+                errors.push(err.to_string());
+                return Actual::Error(errors.join("\n"));
+            }
+        };
+        // 書き換えパスの失敗もエラーとして期待できるようにする
+        let ast = match rewrite::apply_passes(self.passes, ast) {
+            Ok(ast) => ast,
+            Err(diagnostic) => return Actual::Error(format!("{}\n", diagnostic)),
+        };
+        Actual::Outputs(self.targets.iter().map(|target| format!("{}\n", Generator::new(target.grammar).generate(&ast))).collect())
+    }
+
+    /// 出力の期待ファイル (出力の拡張子がなければソースの拡張子)
+    fn output_path(&self, path: &Path, target: &GoldenTarget) -> PathBuf {
+        let ext = target.ext.clone().unwrap_or_else(|| path.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default());
+        expected_path(path, &ext)
+    }
+
+    /// 期待ファイルを書き換える (同じ内容なら書かない)
+    fn bless(&self, expected: &Path, actual: &str) -> Outcome {
+        if fs::read_to_string(expected).is_ok_and(|text| text == actual) {
+            return Outcome::Passed;
+        }
+        match fs::write(expected, actual) {
+            Ok(()) => Outcome::Blessed,
+            Err(e) => Outcome::Failed(format!("Error writing {}: {}", expected.display(), e)),
+        }
+    }
+}
+
+/// 期待ファイルと比べる
+fn compare(expected: &Path, actual: &str) -> Outcome {
+    match fs::read_to_string(expected) {
+        Ok(text) if text == actual => Outcome::Passed,
+        Ok(text) => Outcome::Failed(diff::unified_diff(&text, actual, &expected.display().to_string(), "actual")),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Outcome::Failed(format!("{} does not exist (run with --bless to create it)", expected.display()))
+        }
+        Err(e) => Outcome::Failed(format!("Error reading {}: {}", expected.display(), e)),
+    }
+}
+
+/// dir 以下のケースのソースを相対パスの順に集める (期待ファイルは除く)
+pub fn discover(dir: &Path, exts: &[String]) -> io::Result<Vec<PathBuf>> {
+    let paths = batch::source_files(dir, Path::new(""), exts)?;
+    Ok(paths
+        .into_iter()
+        .filter(|path| !path.file_name().is_some_and(|name| name.to_string_lossy().contains(".expected.")))
+        .collect())
+}
+
+/// ケースの期待ファイルのパス (case.py → case.expected.EXT)
+pub fn expected_path(path: &Path, ext: &str) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!("{}.expected.{}", stem, ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CompiledGrammars;
+
+    const INPUT: &str = r#"
+        program := call+;
+        call    := name "(" ")" NEWLINE?;
+        name    := "[a-z]+";
+    "#;
+    const OUTPUT: &str = r#"
+        program := call join "\n";
+        call    := name "();";
+        name    := match @value { _ => @value };
+    "#;
+
+    #[test]
+    fn test_run_dir_and_bless() {
+        let dir = std::env::temp_dir().join(format!("hensan-golden-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("ok.src"), "f()\ng()\n").unwrap();
+        fs::write(dir.join("ok.expected.rs"), "f();\nh();\n").unwrap();
+        fs::write(dir.join("bad.src"), "f(\n").unwrap();
+        fs::write(dir.join("new.src"), "k()\n").unwrap();
+
+        let grammars = CompiledGrammars::compile(INPUT, OUTPUT).unwrap();
+        let targets = || vec![GoldenTarget { grammar: &grammars.output, ext: Some("rs".to_string()) }];
+        let tester = GoldenTester::new(&grammars.input, targets());
+
+        let results = tester.run_dir(&dir, &[]).unwrap();
+        let names: Vec<_> = results.iter().map(|r| r.path.file_name().unwrap().to_string_lossy().to_string()).collect();
+        assert_eq!(names, ["bad.src", "new.src", "ok.src"]);
+        assert!(results.iter().all(|r| r.failed()));
+        let Outcome::Failed(diff) = &results[2].checks[0].outcome else { panic!() };
+        assert!(diff.contains("-h();\n+g();\n"), "{}", diff);
+
+        // --bless で全ての期待ファイルを書き、その後は全て通る
+        let blessed = GoldenTester::new(&grammars.input, targets()).with_bless(true).run_dir(&dir, &[]).unwrap();
+        assert!(blessed.iter().all(|r| r.checks[0].outcome == Outcome::Blessed));
+        assert!(fs::read_to_string(dir.join("bad.expected.err")).unwrap().starts_with("Parse error at line 1"));
+        assert_eq!(fs::read_to_string(dir.join("ok.expected.rs")).unwrap(), "f();\ng();\n");
+        assert!(tester.run_dir(&dir, &[]).unwrap().iter().all(|r| !r.failed()));
+
+        // 直ったケースは出力の期待ファイルに置き換わる
+        fs::write(dir.join("bad.src"), "f()\n").unwrap();
+        assert!(tester.run_case(&dir.join("bad.src")).failed());
+        GoldenTester::new(&grammars.input, targets()).with_bless(true).run_case(&dir.join("bad.src"));
+        assert!(!dir.join("bad.expected.err").exists());
+        assert!(!tester.run_case(&dir.join("bad.src")).failed());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_repository_examples() {
+        // test/ の例をリポジトリの grammar/ の文法で変換し、期待ファイルと比べる
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let input_bnf = fs::read_to_string(root.join("grammar/input.bnf")).unwrap();
+        let output_bnf = fs::read_to_string(root.join("grammar/output.bnf")).unwrap();
+        let grammars = CompiledGrammars::compile(&input_bnf, &output_bnf).unwrap();
+        let tester = GoldenTester::new(&grammars.input, vec![GoldenTarget { grammar: &grammars.output, ext: Some("rs".to_string()) }]);
+
+        let results = tester.run_dir(&root.join("test"), &["py".to_string()]).unwrap();
+        assert!(!results.is_empty());
+        for result in results {
+            for check in result.checks {
+                if let Outcome::Failed(message) = check.outcome {
+                    panic!("{}: {}", check.expected.display(), message);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_expected_path() {
+        assert_eq!(expected_path(Path::new("t/case.py"), "rs"), Path::new("t/case.expected.rs"));
+        assert_eq!(expected_path(Path::new("t/case.py"), ERROR_EXT), Path::new("t/case.expected.err"));
+    }
+}
@@ -0,0 +1,370 @@
+use std::fmt;
+
+use crate::meta_parser::{GrammarExpr, GrammarKind, MetaParseError, MetaParser, OutputExpr};
+
+/// BNF 文法ファイルのフォーマッター
+/// ルールごとに MetaParser でパースし、`:=` を揃えた正規の形で再出力する
+/// (コメントはルールの前・行末に残す。ルール本体の中のコメントはルールの前に移す)
+pub struct GrammarFormatter {
+    kind: GrammarKind,
+    /// 1行がこれより長くなるルールは選択肢を1行ずつに折り返す
+    max_width: usize,
+}
+
+/// 整形できなかった位置と理由
+#[derive(Debug)]
+pub struct GrammarFormatError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for GrammarFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// 行コメント (直前に空行があったかどうかと、コメント本文)
+type Comment = (bool, String);
+
+/// ソースから切り出したルール1つ分
+#[derive(Debug, Default)]
+struct RuleChunk {
+    /// ルールの前のコメント
+    leading: Vec<Comment>,
+    /// ルールの直前に空行があったか
+    blank_before: bool,
+    /// コメントを除いたルール本体 (末尾の ';' を含む)
+    code: String,
+    /// ';' と同じ行にあるコメント
+    trailing: Option<String>,
+    /// ルールの開始行 (1始まり)
+    line: usize,
+}
+
+/// パースしたルール
+enum Rule {
+    Input(String, GrammarExpr),
+    Output(String, OutputExpr),
+    /// @start ディレクティブ
+    Start(String),
+}
+
+impl Rule {
+    /// `:=` を揃えるときに使う名前 (ディレクティブは揃えない)
+    fn name(&self) -> &str {
+        match self {
+            Rule::Input(name, _) | Rule::Output(name, _) => name,
+            Rule::Start(_) => "",
+        }
+    }
+}
+
+impl GrammarFormatter {
+    pub fn new(kind: GrammarKind) -> Self {
+        GrammarFormatter { kind, max_width: 80 }
+    }
+
+    /// 折り返す行の長さを設定
+    pub fn with_max_width(mut self, width: usize) -> Self {
+        self.max_width = width;
+        self
+    }
+
+    /// 文法ファイルを整形
+    pub fn format(&self, source: &str) -> Result<String, GrammarFormatError> {
+        let (chunks, tail) = split_rules(source)?;
+        let rules: Vec<Rule> = chunks.iter().map(|chunk| self.parse_rule(chunk)).collect::<Result<_, _>>()?;
+
+        // 空行で区切られたルールのまとまりごとに `:=` の位置を揃える
+        let starts_group = |i: usize| {
+            i == 0 || chunks[i].blank_before || chunks[i].leading.iter().any(|(blank, _)| *blank)
+        };
+        let mut name_widths = vec![0; chunks.len()];
+        let mut group_start = 0;
+        for i in 0..=chunks.len() {
+            if i == chunks.len() || (i > 0 && starts_group(i)) {
+                let width = rules[group_start..i].iter().map(|r| r.name().chars().count()).max().unwrap_or(0);
+                name_widths[group_start..i].fill(width);
+                group_start = i;
+            }
+        }
+
+        let mut result = String::new();
+        for i in 0..chunks.len() {
+            let name_width = name_widths[i];
+
+            let chunk = &chunks[i];
+            for (blank, comment) in &chunk.leading {
+                push_blank_line(&mut result, *blank);
+                result.push_str(comment);
+                result.push('\n');
+            }
+            push_blank_line(&mut result, chunk.blank_before);
+
+            result.push_str(&self.rule_text(&rules[i], name_width, chunk.trailing.as_deref()));
+            if let Some(comment) = &chunk.trailing {
+                result.push(' ');
+                result.push_str(comment);
+            }
+            result.push('\n');
+        }
+
+        for (blank, comment) in &tail {
+            push_blank_line(&mut result, *blank);
+            result.push_str(comment);
+            result.push('\n');
+        }
+
+        Ok(result)
+    }
+
+    /// ルール1つを MetaParser でパース
+    fn parse_rule(&self, chunk: &RuleChunk) -> Result<Rule, GrammarFormatError> {
+        let error = |message: &str| GrammarFormatError { line: chunk.line, message: message.to_string() };
+        // @start は開始ルールの定義を確かめられないため、ここで読む
+        if let Some(rest) = chunk.code.trim().strip_prefix("@start") {
+            let name = rest.trim_end_matches(';').trim();
+            if self.kind != GrammarKind::Input || name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(error("Invalid @start directive"));
+            }
+            return Ok(Rule::Start(name.to_string()));
+        }
+
+        let mut parser = MetaParser::new(&chunk.code);
+        let syntax_error = |e: MetaParseError| GrammarFormatError { line: chunk.line + e.line - 1, message: e.message };
+
+        let rule = match self.kind {
+            GrammarKind::Input => {
+                let grammar = parser.parse_input_grammar().map_err(syntax_error)?;
+                let name = grammar.order.first().ok_or_else(|| error("Expected a rule name"))?;
+                Rule::Input(name.clone(), grammar.rules[name].expr.clone())
+            }
+            GrammarKind::Output => {
+                let grammar = parser.parse_output_grammar().map_err(syntax_error)?;
+                let name = grammar.order.first().ok_or_else(|| error("Expected a rule name"))?;
+                Rule::Output(name.clone(), grammar.rules[name].expr.clone())
+            }
+        };
+        Ok(rule)
+    }
+
+    /// ルールを整形 (name_width: `:=` を揃えるためのルール名の幅)
+    fn rule_text(&self, rule: &Rule, name_width: usize, trailing: Option<&str>) -> String {
+        if let Rule::Start(name) = rule {
+            return format!("@start {};", name);
+        }
+        let head = format!("{:width$} := ", rule.name(), width = name_width);
+        let comment_width = trailing.map_or(0, |c| c.chars().count() + 1);
+        // 選択肢を折り返すときの `|` の位置
+        let continuation = format!("\n{}  | ", " ".repeat(name_width));
+
+        let body = match rule {
+            Rule::Input(_, GrammarExpr::Choice(choices)) => {
+                let line = format!("{}{};", head, GrammarExpr::Choice(choices.clone()));
+                if line.chars().count() + comment_width <= self.max_width {
+                    return line;
+                }
+                choices.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(&continuation)
+            }
+            Rule::Input(_, expr) => expr.to_string(),
+            Rule::Output(_, OutputExpr::Match { cases, default }) => match_text(cases, default),
+            Rule::Output(_, OutputExpr::Choice(choices)) => {
+                let line = format!("{}{};", head, OutputExpr::Choice(choices.clone()));
+                if line.chars().count() + comment_width <= self.max_width {
+                    return line;
+                }
+                choices.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(&continuation)
+            }
+            Rule::Output(_, expr) => expr.to_string(),
+            Rule::Start(_) => unreachable!(),
+        };
+
+        format!("{}{};", head, body)
+    }
+}
+
+/// match 式を1行1腕で整形 (`=>` の位置を揃える)
+fn match_text(cases: &[(String, String)], default: &str) -> String {
+    let mut arms: Vec<(String, String)> = cases
+        .iter()
+        .map(|(pattern, replacement)| (format!("\"{}\"", pattern), format!("\"{}\"", replacement)))
+        .collect();
+    // default が空なのは `_` の腕が省略された場合
+    if !default.is_empty() {
+        let replacement = if default == "@value" { default.to_string() } else { format!("\"{}\"", default) };
+        arms.push(("_".to_string(), replacement));
+    }
+    if arms.is_empty() {
+        return "match @value { }".to_string();
+    }
+
+    let width = arms.iter().map(|(pattern, _)| pattern.chars().count()).max().unwrap_or(0);
+    let lines: Vec<String> = arms
+        .iter()
+        .map(|(pattern, replacement)| format!("    {:width$} => {}", pattern, replacement, width = width))
+        .collect();
+    format!("match @value {{\n{}\n}}", lines.join(",\n"))
+}
+
+/// 空行を1行追加 (ファイル先頭には置かない)
+fn push_blank_line(result: &mut String, blank: bool) {
+    if blank && !result.is_empty() {
+        result.push('\n');
+    }
+}
+
+/// ソースをルールごとに切り出す (最後のルールより後ろのコメントも返す)
+fn split_rules(source: &str) -> Result<(Vec<RuleChunk>, Vec<Comment>), GrammarFormatError> {
+    let mut chunks: Vec<RuleChunk> = Vec::new();
+    let mut current = RuleChunk::default();
+    let mut pending: Vec<Comment> = Vec::new();
+    let mut blank = false;
+    // 現在の行にコードかコメントがあったか
+    let mut line_has_content = false;
+    // 直前のルールの ';' と同じ行にいるか
+    let mut after_rule = false;
+    let mut line = 1;
+
+    let mut chars = source.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        if ch == '\n' {
+            if !current.code.is_empty() {
+                current.code.push(' ');
+            }
+            if !line_has_content && (!chunks.is_empty() || !pending.is_empty()) {
+                blank = true;
+            }
+            line_has_content = false;
+            after_rule = false;
+            line += 1;
+            continue;
+        }
+        if ch.is_whitespace() {
+            if !current.code.is_empty() {
+                current.code.push(ch);
+            }
+            continue;
+        }
+        line_has_content = true;
+
+        if ch == '/' && source[i..].starts_with("//") {
+            let end = source[i..].find('\n').map_or(source.len(), |n| i + n);
+            let comment = source[i..end].trim_end().to_string();
+            while chars.peek().is_some_and(|&(j, _)| j < end) {
+                chars.next();
+            }
+            if after_rule {
+                if let Some(rule) = chunks.last_mut() {
+                    rule.trailing = Some(comment);
+                }
+            } else {
+                // ルール本体の中のコメントもルールの前に置く
+                pending.push((blank, comment));
+                blank = false;
+            }
+            continue;
+        }
+
+        if current.code.is_empty() {
+            current.blank_before = blank;
+            current.line = line;
+            current.leading = std::mem::take(&mut pending);
+            blank = false;
+        }
+        after_rule = false;
+
+        match ch {
+            '"' => {
+                current.code.push(ch);
+                let mut closed = false;
+                for (_, c) in chars.by_ref() {
+                    current.code.push(c);
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    if c == '"' {
+                        closed = true;
+                        break;
+                    }
+                }
+                if !closed {
+                    return Err(GrammarFormatError { line, message: "Unclosed string literal".to_string() });
+                }
+            }
+            '[' => {
+                current.code.push(ch);
+                let mut depth = 1;
+                for (_, c) in chars.by_ref() {
+                    current.code.push(c);
+                    match c {
+                        '[' => depth += 1,
+                        ']' => depth -= 1,
+                        '\n' => line += 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                if depth > 0 {
+                    return Err(GrammarFormatError { line, message: "Unclosed pattern".to_string() });
+                }
+            }
+            ';' => {
+                current.code.push(ch);
+                // 本体の中のコメントはルールの前のコメントの後ろに並べる
+                current.leading.append(&mut pending);
+                chunks.push(std::mem::take(&mut current));
+                after_rule = true;
+            }
+            _ => current.code.push(ch),
+        }
+    }
+
+    if !current.code.trim().is_empty() {
+        return Err(GrammarFormatError { line: current.line, message: "Expected ';' at end of rule".to_string() });
+    }
+    Ok((chunks, pending))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_input_grammar() {
+        let source = "// header\n@start  program ;\nprogram := stmt+;  // entry\nstmt:=SAME_INDENT (call|pass) NEWLINE?;\n\n\n\
+                      // names\nname:=\"[a-z]+\" ; pass := \"pass\";\ncall := name \"(\" // args\n  name? \")\";\n";
+        let formatted = GrammarFormatter::new(GrammarKind::Input).format(source).unwrap();
+        assert_eq!(
+            formatted,
+            "// header\n@start program;\nprogram := stmt+; // entry\nstmt    := SAME_INDENT (call | pass) NEWLINE?;\n\n\
+             // names\nname := \"[a-z]+\";\npass := \"pass\";\n// args\ncall := name \"(\" name? \")\";\n"
+        );
+        // 整形済みの文法は変わらない
+        assert_eq!(GrammarFormatter::new(GrammarKind::Input).format(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_format_output_grammar() {
+        let source = "op := match @value { \" ==\" => \" == \", \" <\" => \" < \", _ => @value };\n\
+                      stmt := if_stmt | for_stmt | while_stmt | call_stmt | let_stmt | return_stmt;\n";
+        let formatted = GrammarFormatter::new(GrammarKind::Output).with_max_width(40).format(source).unwrap();
+        assert_eq!(
+            formatted,
+            "op   := match @value {\n    \" ==\" => \" == \",\n    \" <\"  => \" < \",\n    _     => @value\n};\n\
+             stmt := if_stmt\n      | for_stmt\n      | while_stmt\n      | call_stmt\n      | let_stmt\n      | return_stmt;\n"
+        );
+        assert_eq!(
+            GrammarFormatter::new(GrammarKind::Output).with_max_width(40).format(&formatted).unwrap(),
+            formatted
+        );
+    }
+
+    #[test]
+    fn test_format_error() {
+        let err = GrammarFormatter::new(GrammarKind::Input).format("a := \"x\";\nb := \"y\"\n").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+}
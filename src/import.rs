@@ -0,0 +1,1073 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::meta_parser::{GrammarExpr, InputGrammar, MetaParser};
+
+/// インポート元の形式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    /// ISO/IEC 14977 EBNF
+    Ebnf,
+    /// RFC 5234 ABNF
+    Abnf,
+    /// pest の .pest 文法
+    Pest,
+}
+
+impl ImportFormat {
+    pub fn from_name(name: &str) -> Option<ImportFormat> {
+        match name {
+            "ebnf" => Some(ImportFormat::Ebnf),
+            "abnf" => Some(ImportFormat::Abnf),
+            "pest" => Some(ImportFormat::Pest),
+            _ => None,
+        }
+    }
+}
+
+/// インポート結果
+#[derive(Debug)]
+pub struct Import {
+    /// 変換した入力文法
+    pub grammar: InputGrammar,
+    /// hensan の入力BNF形式のテキスト
+    pub bnf: String,
+    /// hensan で表現できず、近似・省略した構文の報告
+    pub warnings: Vec<String>,
+}
+
+/// インポート元の文法の構文エラー
+#[derive(Debug, Clone)]
+pub struct ImportError {
+    /// 行番号 (1-indexed)
+    pub line: usize,
+    /// 列番号 (1-indexed)
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Syntax error at line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// 他形式の文法を hensan の入力BNFに変換
+pub fn import_grammar(source: &str, format: ImportFormat) -> Result<Import, ImportError> {
+    let mut importer = Importer::new(source);
+    match format {
+        ImportFormat::Ebnf => importer.parse_ebnf()?,
+        ImportFormat::Abnf => importer.parse_abnf()?,
+        ImportFormat::Pest => importer.parse_pest()?,
+    }
+    importer.add_core_rules();
+
+    let bnf = importer.to_bnf();
+    // 出力したBNFを読み直すことで、.bnf ファイルと同じ文法になることを保証する
+    let grammar = MetaParser::new(&bnf).parse_input_grammar().map_err(|e| ImportError {
+        line: e.line,
+        column: e.column,
+        message: format!("generated BNF is invalid: {}", e.message),
+    })?;
+
+    let warnings = importer
+        .warnings
+        .iter()
+        .map(|(rule, message)| format!("{}: {}", rule, message))
+        .collect();
+
+    Ok(Import { grammar, bnf, warnings })
+}
+
+/// hensan の特殊トークン名 (ルール名としては使えない)
+const RESERVED_NAMES: &[&str] = &["INDENT", "DEDENT", "NEWLINE", "SAME_INDENT"];
+
+struct Importer {
+    chars: Vec<char>,
+    pos: usize,
+    /// 変換済みのルール (定義順)
+    rules: Vec<(String, GrammarExpr)>,
+    /// (ルール名, メッセージ)
+    warnings: Vec<(String, String)>,
+    /// 変換中のルール名
+    current_rule: String,
+    /// 参照されたルール名 (コアルールの補完用)
+    referenced: HashSet<String>,
+    /// 既定で定義されるルール (名前 → 定義)
+    core_rules: HashMap<&'static str, GrammarExpr>,
+}
+
+impl Importer {
+    fn new(source: &str) -> Self {
+        Importer {
+            chars: source.chars().collect(),
+            pos: 0,
+            rules: Vec::new(),
+            warnings: Vec::new(),
+            current_rule: String::new(),
+            referenced: HashSet::new(),
+            core_rules: HashMap::new(),
+        }
+    }
+
+    // ---- 共通 ----
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.peek_at(i) == Some(c))
+    }
+
+    fn error(&self, message: &str) -> ImportError {
+        let before = &self.chars[..self.pos.min(self.chars.len())];
+        let line = before.iter().filter(|c| **c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|c| **c != '\n').count() + 1;
+        ImportError {
+            line,
+            column,
+            message: message.to_string(),
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), ImportError> {
+        if self.starts_with(s) {
+            self.pos += s.chars().count();
+            Ok(())
+        } else {
+            let found = self.peek().map_or("end of input".to_string(), |c| format!("'{}'", c));
+            Err(self.error(&format!("expected '{}', found {}", s, found)))
+        }
+    }
+
+    fn warn(&mut self, message: &str) {
+        let entry = (self.current_rule.clone(), message.to_string());
+        if !self.warnings.contains(&entry) {
+            self.warnings.push(entry);
+        }
+    }
+
+    /// 区切り文字で囲まれた文字列を読む (開始文字の直後から、終了文字は消費する)
+    fn read_until(&mut self, end: char, escapes: bool) -> Result<String, ImportError> {
+        let mut result = String::new();
+        loop {
+            let c = self.peek().ok_or_else(|| self.error(&format!("unterminated string, expected '{}'", end)))?;
+            self.pos += 1;
+            if c == end {
+                return Ok(result);
+            }
+            if escapes && c == '\\' {
+                let e = self.peek().ok_or_else(|| self.error("unterminated escape"))?;
+                self.pos += 1;
+                result.push(match e {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '0' => '\0',
+                    other => other,
+                });
+            } else {
+                result.push(c);
+            }
+        }
+    }
+
+    fn read_name(&mut self, extra: &[char]) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || extra.contains(&c)) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn read_number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            None
+        } else {
+            self.chars[start..self.pos].iter().collect::<String>().parse().ok()
+        }
+    }
+
+    /// ルールを追加 (同名のルールは選択肢として追記する)
+    fn define(&mut self, name: String, expr: GrammarExpr) {
+        if let Some((_, existing)) = self.rules.iter_mut().find(|(n, _)| *n == name) {
+            // 省略された構文だけの選択肢は追加しない
+            if is_empty(&expr) {
+                return;
+            }
+            let mut choices = match std::mem::replace(existing, GrammarExpr::Sequence(Vec::new())) {
+                GrammarExpr::Choice(c) => c,
+                other => vec![other],
+            };
+            match expr {
+                GrammarExpr::Choice(c) => choices.extend(c),
+                other => choices.push(other),
+            }
+            *existing = GrammarExpr::Choice(choices);
+        } else {
+            self.rules.push((name, expr));
+        }
+    }
+
+    fn rule_ref(&mut self, name: String) -> GrammarExpr {
+        self.referenced.insert(name.clone());
+        GrammarExpr::RuleRef(name)
+    }
+
+    /// 参照されているが定義されていない既定ルールを追加
+    fn add_core_rules(&mut self) {
+        let mut names: Vec<&String> = self.referenced.iter().collect();
+        names.sort();
+        let missing: Vec<(String, GrammarExpr)> = names
+            .into_iter()
+            .filter(|n| !self.rules.iter().any(|(r, _)| r == *n))
+            .filter_map(|n| self.core_rules.get(n.as_str()).map(|e| (n.clone(), e.clone())))
+            .collect();
+        self.rules.extend(missing);
+    }
+
+    /// hensan の入力BNFとして出力
+    fn to_bnf(&self) -> String {
+        let mut out = String::new();
+        let width = self.rules.iter().map(|(n, _)| n.len()).max().unwrap_or(0);
+        for (name, expr) in &self.rules {
+            for (rule, message) in &self.warnings {
+                if rule == name {
+                    out.push_str(&format!("// unsupported: {}\n", message));
+                }
+            }
+            out.push_str(&format!("{:width$} := {};\n", name, expr, width = width));
+        }
+        out
+    }
+
+    // ---- ISO/IEC 14977 EBNF ----
+
+    fn skip_ebnf_space(&mut self) {
+        loop {
+            while self.peek().is_some_and(|c| c.is_whitespace()) {
+                self.pos += 1;
+            }
+            if self.starts_with("(*") {
+                while self.pos < self.chars.len() && !self.starts_with("*)") {
+                    self.pos += 1;
+                }
+                self.pos += 2;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse_ebnf(&mut self) -> Result<(), ImportError> {
+        loop {
+            self.skip_ebnf_space();
+            if self.peek().is_none() {
+                return Ok(());
+            }
+            let name = self.ebnf_meta_identifier()?;
+            self.current_rule = name.clone();
+            self.skip_ebnf_space();
+            self.expect("=")?;
+            let expr = self.ebnf_definitions()?;
+            self.skip_ebnf_space();
+            match self.peek() {
+                Some(';') | Some('.') => self.pos += 1,
+                _ => return Err(self.error("expected ';' or '.' at end of rule")),
+            }
+            self.define(name, expr);
+        }
+    }
+
+    /// メタ識別子 (ISOでは空白を含められるので '_' でつなぐ)
+    fn ebnf_meta_identifier(&mut self) -> Result<String, ImportError> {
+        if !self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            return Err(self.error("expected a meta identifier"));
+        }
+        let mut words = vec![self.read_name(&['-'])];
+        loop {
+            let save = self.pos;
+            while self.peek().is_some_and(|c| c == ' ' || c == '\t') {
+                self.pos += 1;
+            }
+            if self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                words.push(self.read_name(&['-']));
+            } else {
+                self.pos = save;
+                break;
+            }
+        }
+        Ok(rule_name(&words.join("_")))
+    }
+
+    fn ebnf_definitions(&mut self) -> Result<GrammarExpr, ImportError> {
+        let mut choices = vec![self.ebnf_single_definition()?];
+        loop {
+            self.skip_ebnf_space();
+            // '/' と '!' は '|' の代替表記 (ただし "(/" "/)" "(:" ":)" は除く)
+            match self.peek() {
+                Some('|') => self.pos += 1,
+                Some('/') | Some('!') if self.peek_at(1) != Some(')') => self.pos += 1,
+                _ => break,
+            }
+            choices.push(self.ebnf_single_definition()?);
+        }
+        Ok(choice(choices))
+    }
+
+    fn ebnf_single_definition(&mut self) -> Result<GrammarExpr, ImportError> {
+        let mut items = vec![self.ebnf_term()?];
+        loop {
+            self.skip_ebnf_space();
+            if self.peek() == Some(',') {
+                self.pos += 1;
+                items.push(self.ebnf_term()?);
+            } else {
+                break;
+            }
+        }
+        Ok(sequence(items))
+    }
+
+    fn ebnf_term(&mut self) -> Result<GrammarExpr, ImportError> {
+        let factor = self.ebnf_factor()?;
+        self.skip_ebnf_space();
+        if self.peek() == Some('-') {
+            self.pos += 1;
+            self.ebnf_factor()?;
+            self.warn("exceptions (a - b) are not supported; the exception was dropped");
+        }
+        Ok(factor)
+    }
+
+    fn ebnf_factor(&mut self) -> Result<GrammarExpr, ImportError> {
+        self.skip_ebnf_space();
+        let save = self.pos;
+        if let Some(count) = self.read_number() {
+            self.skip_ebnf_space();
+            if self.peek() == Some('*') {
+                self.pos += 1;
+                let primary = self.ebnf_primary()?;
+                return Ok(sequence(vec![primary; count]));
+            }
+            self.pos = save;
+        }
+        self.ebnf_primary()
+    }
+
+    fn ebnf_primary(&mut self) -> Result<GrammarExpr, ImportError> {
+        self.skip_ebnf_space();
+        let Some(c) = self.peek() else {
+            return Ok(empty());
+        };
+
+        if self.starts_with("(/") || c == '[' {
+            let close = if c == '[' { "]" } else { "/)" };
+            self.pos += if c == '[' { 1 } else { 2 };
+            let inner = self.ebnf_definitions()?;
+            self.skip_ebnf_space();
+            self.expect(close)?;
+            return Ok(postfix(inner, '?'));
+        }
+        if self.starts_with("(:") || c == '{' {
+            let close = if c == '{' { "}" } else { ":)" };
+            self.pos += if c == '{' { 1 } else { 2 };
+            let inner = self.ebnf_definitions()?;
+            self.skip_ebnf_space();
+            self.expect(close)?;
+            // 拡張記法 { a }- は1回以上
+            if self.peek() == Some('-') && close == "}" {
+                self.pos += 1;
+                return Ok(postfix(inner, '+'));
+            }
+            return Ok(postfix(inner, '*'));
+        }
+
+        match c {
+            '(' => {
+                self.pos += 1;
+                let inner = self.ebnf_definitions()?;
+                self.skip_ebnf_space();
+                self.expect(")")?;
+                Ok(group(inner))
+            }
+            '\'' | '"' => {
+                self.pos += 1;
+                let text = self.read_until(c, false)?;
+                Ok(literal(&text))
+            }
+            '?' => {
+                self.pos += 1;
+                let text = self.read_until('?', false)?;
+                self.warn(&format!("special sequence ? {} ? has no equivalent; it was dropped", text.trim()));
+                Ok(empty())
+            }
+            c if c.is_ascii_alphabetic() => {
+                let name = self.ebnf_meta_identifier()?;
+                Ok(self.rule_ref(name))
+            }
+            // 空の定義 (例: a = | b ;)
+            _ => Ok(empty()),
+        }
+    }
+
+    // ---- RFC 5234 ABNF ----
+
+    fn parse_abnf(&mut self) -> Result<(), ImportError> {
+        self.core_rules = abnf_core_rules();
+        loop {
+            self.skip_abnf_space(true);
+            if self.peek().is_none() {
+                return Ok(());
+            }
+            if !self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                return Err(self.error("expected a rule name"));
+            }
+            let name = rule_name(&self.read_name(&['-']).to_ascii_lowercase());
+            self.current_rule = name.clone();
+            self.skip_abnf_space(false);
+            self.expect("=")?;
+            // =/ は既存ルールへの選択肢の追加 (define が同名ルールを連結する)
+            if self.peek() == Some('/') {
+                self.pos += 1;
+            }
+            let expr = self.abnf_alternation()?;
+            self.define(name, expr);
+        }
+    }
+
+    /// 空白とコメントを読み飛ばす
+    /// newlines が false のときは、継続行 (空白で始まる行) の改行だけを読み飛ばす
+    fn skip_abnf_space(&mut self, newlines: bool) {
+        loop {
+            match self.peek() {
+                Some(' ') | Some('\t') | Some('\r') => self.pos += 1,
+                Some(';') => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                Some('\n') => {
+                    let continued = matches!(self.peek_at(1), Some(' ') | Some('\t'));
+                    if newlines || continued || self.next_line_is_blank() {
+                        self.pos += 1;
+                    } else {
+                        return;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// 次の行が空行かコメントだけの行か
+    fn next_line_is_blank(&self) -> bool {
+        let mut i = self.pos + 1;
+        while let Some(c) = self.chars.get(i) {
+            match c {
+                ' ' | '\t' | '\r' => i += 1,
+                '\n' | ';' => return true,
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    fn abnf_alternation(&mut self) -> Result<GrammarExpr, ImportError> {
+        let mut choices = vec![self.abnf_concatenation()?];
+        loop {
+            self.skip_abnf_space(false);
+            if self.peek() == Some('/') {
+                self.pos += 1;
+                choices.push(self.abnf_concatenation()?);
+            } else {
+                break;
+            }
+        }
+        Ok(choice(choices))
+    }
+
+    fn abnf_concatenation(&mut self) -> Result<GrammarExpr, ImportError> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_abnf_space(false);
+            match self.peek() {
+                Some(c) if c.is_ascii_alphanumeric() || matches!(c, '*' | '(' | '[' | '"' | '%' | '<') => {
+                    items.push(self.abnf_repetition()?);
+                }
+                _ => break,
+            }
+        }
+        if items.is_empty() {
+            return Err(self.error("expected an element"));
+        }
+        Ok(sequence(items))
+    }
+
+    fn abnf_repetition(&mut self) -> Result<GrammarExpr, ImportError> {
+        let min = self.read_number();
+        let (min, max) = if self.peek() == Some('*') {
+            self.pos += 1;
+            (min.unwrap_or(0), self.read_number())
+        } else if let Some(n) = min {
+            (n, Some(n))
+        } else {
+            let element = self.abnf_element()?;
+            return Ok(element);
+        };
+        let element = self.abnf_element()?;
+        Ok(repeat(element, min, max))
+    }
+
+    fn abnf_element(&mut self) -> Result<GrammarExpr, ImportError> {
+        let c = self.peek().ok_or_else(|| self.error("expected an element"))?;
+        match c {
+            '(' | '[' => {
+                self.pos += 1;
+                let inner = self.abnf_alternation()?;
+                self.skip_abnf_space(false);
+                if c == '(' {
+                    self.expect(")")?;
+                    Ok(group(inner))
+                } else {
+                    self.expect("]")?;
+                    Ok(postfix(inner, '?'))
+                }
+            }
+            '"' => {
+                // ABNFの文字列は大文字小文字を区別しない
+                self.pos += 1;
+                let text = self.read_until('"', false)?;
+                Ok(case_insensitive(&text))
+            }
+            '%' => {
+                self.pos += 1;
+                match self.peek() {
+                    Some('s') | Some('S') if self.peek_at(1) == Some('"') => {
+                        self.pos += 2;
+                        let text = self.read_until('"', false)?;
+                        Ok(literal(&text))
+                    }
+                    Some('i') | Some('I') if self.peek_at(1) == Some('"') => {
+                        self.pos += 2;
+                        let text = self.read_until('"', false)?;
+                        Ok(case_insensitive(&text))
+                    }
+                    _ => self.abnf_num_val(),
+                }
+            }
+            '<' => {
+                self.pos += 1;
+                let text = self.read_until('>', false)?;
+                self.warn(&format!("prose value <{}> has no equivalent; it was dropped", text));
+                Ok(empty())
+            }
+            _ => {
+                let name = rule_name(&self.read_name(&['-']).to_ascii_lowercase());
+                // hensan はトークン間のスペース・タブを暗黙に読み飛ばす
+                // (空の規則を繰り返すとパーサーが停止しないため、参照ごと取り除く)
+                if matches!(name.as_str(), "sp" | "htab" | "wsp" | "lwsp") {
+                    self.warn(&format!("{} is dropped because spaces and tabs are skipped implicitly", name.to_ascii_uppercase()));
+                    return Ok(empty());
+                }
+                Ok(self.rule_ref(name))
+            }
+        }
+    }
+
+    /// 数値指定の文字 (%x41, %x41-5A, %d13.10 など)
+    fn abnf_num_val(&mut self) -> Result<GrammarExpr, ImportError> {
+        let radix = match self.peek().map(|c| c.to_ascii_lowercase()) {
+            Some('x') => 16,
+            Some('d') => 10,
+            Some('b') => 2,
+            _ => return Err(self.error("expected 'x', 'd' or 'b' after '%'")),
+        };
+        self.pos += 1;
+
+        let first = self.abnf_digits(radix)?;
+        if self.peek() == Some('-') {
+            self.pos += 1;
+            let last = self.abnf_digits(radix)?;
+            return Ok(pattern(&format!("[{}-{}]", class_char(first), class_char(last))));
+        }
+
+        let mut text = String::from(first);
+        while self.peek() == Some('.') {
+            self.pos += 1;
+            text.push(self.abnf_digits(radix)?);
+        }
+        Ok(literal(&text))
+    }
+
+    fn abnf_digits(&mut self, radix: u32) -> Result<char, ImportError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_digit(radix)) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        u32::from_str_radix(&digits, radix)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error("invalid numeric value"))
+    }
+
+    // ---- pest ----
+
+    fn skip_pest_space(&mut self) {
+        loop {
+            while self.peek().is_some_and(|c| c.is_whitespace()) {
+                self.pos += 1;
+            }
+            if self.starts_with("//") {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+            } else if self.starts_with("/*") {
+                while self.pos < self.chars.len() && !self.starts_with("*/") {
+                    self.pos += 1;
+                }
+                self.pos += 2;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse_pest(&mut self) -> Result<(), ImportError> {
+        loop {
+            self.skip_pest_space();
+            if self.peek().is_none() {
+                return Ok(());
+            }
+            if !self.peek().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') {
+                return Err(self.error("expected a rule name"));
+            }
+            let raw_name = self.read_name(&[]);
+            self.current_rule = rule_name(&raw_name);
+            self.skip_pest_space();
+            self.expect("=")?;
+            self.skip_pest_space();
+
+            let modifier = self.peek().filter(|c| matches!(c, '_' | '@' | '$' | '!'));
+            if modifier.is_some() {
+                self.pos += 1;
+                self.skip_pest_space();
+            }
+            self.expect("{")?;
+            let expr = self.pest_choice()?;
+            self.skip_pest_space();
+            self.expect("}")?;
+
+            // hensan は空白 (スペース・タブ) を暗黙に読み飛ばす
+            if raw_name == "WHITESPACE" {
+                continue;
+            }
+            if raw_name == "COMMENT" {
+                self.warn("implicit COMMENT rules are not supported; the rule was dropped");
+                continue;
+            }
+            match modifier {
+                Some('@') | Some('$') => {
+                    self.warn("atomic rules are not supported; whitespace is still skipped between tokens")
+                }
+                Some('_') => self.warn("silent rules are not supported; the rule produces an AST node"),
+                _ => {}
+            }
+            let name = self.current_rule.clone();
+            self.define(name, expr);
+        }
+    }
+
+    fn pest_choice(&mut self) -> Result<GrammarExpr, ImportError> {
+        self.skip_pest_space();
+        // 先頭の '|' は許可されている
+        if self.peek() == Some('|') {
+            self.pos += 1;
+        }
+        let mut choices = vec![self.pest_sequence()?];
+        loop {
+            self.skip_pest_space();
+            if self.peek() == Some('|') {
+                self.pos += 1;
+                choices.push(self.pest_sequence()?);
+            } else {
+                break;
+            }
+        }
+        Ok(choice(choices))
+    }
+
+    fn pest_sequence(&mut self) -> Result<GrammarExpr, ImportError> {
+        let mut items = vec![self.pest_term()?];
+        loop {
+            self.skip_pest_space();
+            if self.peek() == Some('~') {
+                self.pos += 1;
+                items.push(self.pest_term()?);
+            } else {
+                break;
+            }
+        }
+        Ok(sequence(items))
+    }
+
+    fn pest_term(&mut self) -> Result<GrammarExpr, ImportError> {
+        self.skip_pest_space();
+        let predicate = self.peek().filter(|c| matches!(c, '&' | '!'));
+        if let Some(p) = predicate {
+            self.pos += 1;
+            self.pest_term()?;
+            self.warn(&format!("lookahead predicates ({}) are not supported; the predicate was dropped", p));
+            return Ok(empty());
+        }
+
+        let mut expr = self.pest_primary()?;
+        loop {
+            self.skip_pest_space();
+            match self.peek() {
+                Some(op @ ('*' | '+' | '?')) => {
+                    self.pos += 1;
+                    expr = postfix(expr, op);
+                }
+                Some('{') => {
+                    self.pos += 1;
+                    self.skip_pest_space();
+                    let min = self.read_number();
+                    self.skip_pest_space();
+                    let max = if self.peek() == Some(',') {
+                        self.pos += 1;
+                        self.skip_pest_space();
+                        self.read_number()
+                    } else {
+                        min
+                    };
+                    self.skip_pest_space();
+                    self.expect("}")?;
+                    expr = repeat(expr, min.unwrap_or(0), max);
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn pest_primary(&mut self) -> Result<GrammarExpr, ImportError> {
+        self.skip_pest_space();
+        let c = self.peek().ok_or_else(|| self.error("expected an expression"))?;
+        match c {
+            '(' => {
+                self.pos += 1;
+                let inner = self.pest_choice()?;
+                self.skip_pest_space();
+                self.expect(")")?;
+                Ok(group(inner))
+            }
+            '"' => {
+                self.pos += 1;
+                let text = self.read_until('"', true)?;
+                Ok(literal(&text))
+            }
+            '^' => {
+                self.pos += 1;
+                self.expect("\"")?;
+                let text = self.read_until('"', true)?;
+                Ok(case_insensitive(&text))
+            }
+            '\'' => {
+                self.pos += 1;
+                let lo = self.read_until('\'', true)?;
+                self.skip_pest_space();
+                if self.starts_with("..") {
+                    self.pos += 2;
+                    self.skip_pest_space();
+                    self.expect("'")?;
+                    let hi = self.read_until('\'', true)?;
+                    let (Some(lo), Some(hi)) = (lo.chars().next(), hi.chars().next()) else {
+                        return Err(self.error("empty character in range"));
+                    };
+                    Ok(pattern(&format!("[{}-{}]", class_char(lo), class_char(hi))))
+                } else {
+                    Ok(literal(&lo))
+                }
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let name = self.read_name(&[]);
+                self.pest_builtin(&name)
+            }
+            _ => Err(self.error(&format!("unexpected '{}'", c))),
+        }
+    }
+
+    /// pest の組み込みルール
+    fn pest_builtin(&mut self, name: &str) -> Result<GrammarExpr, ImportError> {
+        let class = |s: &str| Ok(pattern(s));
+        match name {
+            "ANY" => class("[\\x{0}-\\x{10FFFF}]"),
+            "ASCII_DIGIT" => class("[0-9]"),
+            "ASCII_NONZERO_DIGIT" => class("[1-9]"),
+            "ASCII_BIN_DIGIT" => class("[01]"),
+            "ASCII_OCT_DIGIT" => class("[0-7]"),
+            "ASCII_HEX_DIGIT" => class("[0-9a-fA-F]"),
+            "ASCII_ALPHA_LOWER" => class("[a-z]"),
+            "ASCII_ALPHA_UPPER" => class("[A-Z]"),
+            "ASCII_ALPHA" => class("[a-zA-Z]"),
+            "ASCII_ALPHANUMERIC" => class("[a-zA-Z0-9]"),
+            "ASCII" => class("[\\x{0}-\\x{7F}]"),
+            "NEWLINE" => Ok(GrammarExpr::Newline),
+            // 入力の先頭・末尾は暗黙に扱われる
+            "SOI" | "EOI" => Ok(empty()),
+            "PUSH" => {
+                self.skip_pest_space();
+                self.expect("(")?;
+                let inner = self.pest_choice()?;
+                self.skip_pest_space();
+                self.expect(")")?;
+                self.warn("the pest stack (PUSH) is not supported; the pushed expression is matched plainly");
+                Ok(group(inner))
+            }
+            "POP" | "POP_ALL" | "PEEK" | "PEEK_ALL" | "DROP" => {
+                self.warn(&format!("the pest stack ({}) is not supported; it was dropped", name));
+                Ok(empty())
+            }
+            _ => {
+                let name = rule_name(name);
+                Ok(self.rule_ref(name))
+            }
+        }
+    }
+}
+
+/// ABNF のコアルール (RFC 5234 Appendix B)
+fn abnf_core_rules() -> HashMap<&'static str, GrammarExpr> {
+    let mut rules = HashMap::new();
+    rules.insert("alpha", pattern("[A-Za-z]"));
+    rules.insert("bit", pattern("[01]"));
+    rules.insert("char", pattern("[\\x{1}-\\x{7F}]"));
+    rules.insert("cr", pattern("\\r"));
+    rules.insert("crlf", GrammarExpr::Newline);
+    rules.insert("ctl", pattern("[\\x{0}-\\x{1F}\\x{7F}]"));
+    rules.insert("digit", pattern("[0-9]"));
+    rules.insert("dquote", pattern("\\x22"));
+    rules.insert("hexdig", pattern("[0-9A-Fa-f]"));
+    rules.insert("lf", GrammarExpr::Newline);
+    rules.insert("octet", pattern("[\\x{0}-\\x{FF}]"));
+    rules.insert("vchar", pattern("[\\x{21}-\\x{7E}]"));
+    rules
+}
+
+/// ルール名を hensan で使える識別子に変換
+fn rule_name(name: &str) -> String {
+    let mut result: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if result.starts_with(|c: char| c.is_ascii_digit()) {
+        result.insert(0, '_');
+    }
+    if RESERVED_NAMES.contains(&result.as_str()) {
+        result.push('_');
+    }
+    result
+}
+
+fn empty() -> GrammarExpr {
+    GrammarExpr::Sequence(Vec::new())
+}
+
+fn is_empty(expr: &GrammarExpr) -> bool {
+    matches!(expr, GrammarExpr::Sequence(items) if items.is_empty())
+}
+
+fn sequence(items: Vec<GrammarExpr>) -> GrammarExpr {
+    let mut items: Vec<GrammarExpr> = items.into_iter().filter(|i| !is_empty(i)).collect();
+    if items.len() == 1 {
+        items.pop().unwrap()
+    } else {
+        GrammarExpr::Sequence(items)
+    }
+}
+
+fn choice(mut choices: Vec<GrammarExpr>) -> GrammarExpr {
+    if choices.len() == 1 {
+        choices.pop().unwrap()
+    } else {
+        GrammarExpr::Choice(choices)
+    }
+}
+
+/// 単一の要素でなければグループ化
+fn group(expr: GrammarExpr) -> GrammarExpr {
+    if is_atom(&expr) {
+        expr
+    } else {
+        GrammarExpr::Group(Box::new(expr))
+    }
+}
+
+fn is_atom(expr: &GrammarExpr) -> bool {
+    !matches!(expr, GrammarExpr::Sequence(_) | GrammarExpr::Choice(_))
+}
+
+/// 後置演算子を適用 (hensan の後置演算子は単一要素にしか付けられない)
+fn postfix(expr: GrammarExpr, op: char) -> GrammarExpr {
+    if is_empty(&expr) {
+        return expr;
+    }
+    let operand = match expr {
+        GrammarExpr::ZeroOrMore(_) | GrammarExpr::OneOrMore(_) | GrammarExpr::Optional(_) => {
+            GrammarExpr::Group(Box::new(expr))
+        }
+        other => group(other),
+    };
+    match op {
+        '*' => GrammarExpr::ZeroOrMore(Box::new(operand)),
+        '+' => GrammarExpr::OneOrMore(Box::new(operand)),
+        _ => GrammarExpr::Optional(Box::new(operand)),
+    }
+}
+
+/// 回数指定の繰り返しを展開 (min 回の必須 + 残りは省略可能)
+fn repeat(expr: GrammarExpr, min: usize, max: Option<usize>) -> GrammarExpr {
+    let mut items = vec![expr.clone(); min];
+    match max {
+        None => items.push(postfix(expr, '*')),
+        Some(max) => {
+            for _ in min..max {
+                items.push(postfix(expr.clone(), '?'));
+            }
+        }
+    }
+    sequence(items)
+}
+
+/// リテラルを作成
+/// hensan のリテラルとして書けない文字列 (引用符や正規表現メタ文字を含むもの) はパターンにする
+fn literal(text: &str) -> GrammarExpr {
+    if text.is_empty() {
+        return empty();
+    }
+    let plain = !text.contains('"')
+        && !text.contains('+')
+        && !text.contains('*')
+        && !text.contains('\\')
+        && (!text.starts_with('[') || text == "[")
+        && !text.chars().any(|c| c.is_control());
+    if plain {
+        GrammarExpr::Literal(text.to_string())
+    } else {
+        pattern(&escape_regex(text))
+    }
+}
+
+/// 大文字小文字を区別しないリテラル ([aA][bB] 形式のパターン)
+fn case_insensitive(text: &str) -> GrammarExpr {
+    if !text.chars().any(|c| c.is_ascii_alphabetic()) {
+        return literal(text);
+    }
+    let mut result = String::new();
+    for (i, c) in text.chars().enumerate() {
+        if c.is_ascii_alphabetic() {
+            result.push_str(&format!("[{}{}]", c.to_ascii_lowercase(), c.to_ascii_uppercase()));
+        } else if i == 0 {
+            // 先頭を '[' にしてメタパーサーにパターンとして認識させる
+            result.push_str(&format!("[{}]", class_char(c)));
+        } else {
+            result.push_str(&escape_regex(&c.to_string()));
+        }
+    }
+    pattern(&result)
+}
+
+fn pattern(regex: &str) -> GrammarExpr {
+    GrammarExpr::Pattern(regex.to_string())
+}
+
+/// 正規表現用にエスケープ (引用符は hensan の文字列に書けないので \x22 にする)
+fn escape_regex(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        if c == '"' || c.is_control() {
+            result.push_str(&format!("\\x{{{:X}}}", c as u32));
+        } else {
+            result.push_str(&regex::escape(&c.to_string()));
+        }
+    }
+    result
+}
+
+/// 文字クラス内で使う文字表現
+fn class_char(c: char) -> String {
+    format!("\\x{{{:X}}}", c as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parses(import: &Import, source: &str) -> bool {
+        Parser::new(&import.grammar, source).parse().is_ok()
+    }
+
+    #[test]
+    fn test_import_iso_ebnf() {
+        let source = r#"
+            (* a list of numbers *)
+            list = "[", [ number, { ",", number } ], "]" ;
+            number = digit, { digit } ;
+            digit = "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" ;
+        "#;
+        let import = import_grammar(source, ImportFormat::Ebnf).unwrap();
+        assert_eq!(import.grammar.start_rule, "list");
+        assert!(import.bnf.contains(r#"list   := "[" (number ("," number)*)? "]";"#));
+        assert!(parses(&import, "[1, 22, 333]"));
+        assert!(parses(&import, "[]"));
+        assert!(import.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_import_abnf() {
+        let source = "greeting = %s\"hello\" 1*SP name\r\nname     = ALPHA *(ALPHA / DIGIT / \"-\")\r\n         ; continuation\r\n         / \"anonymous\"\r\nname     =/ <any prose>\r\n";
+        let import = import_grammar(source, ImportFormat::Abnf).unwrap();
+        assert_eq!(import.grammar.start_rule, "greeting");
+        assert!(import.grammar.rules.contains_key("alpha"));
+        assert!(import.grammar.rules.contains_key("digit"));
+        assert!(parses(&import, "hello x1-y"));
+        assert!(parses(&import, "hello ANONYMOUS"));
+        assert!(import.warnings.iter().any(|w| w.starts_with("name: prose value")));
+    }
+
+    #[test]
+    fn test_import_pest() {
+        let source = r#"
+            WHITESPACE = _{ " " }
+            pair  = { key ~ "=" ~ value }
+            key   = @{ ASCII_ALPHA ~ (ASCII_ALPHANUMERIC | "_")* }
+            value = { ^"true" | ^"false" | ('0'..'9'){1,3} | !"x" ~ "\"s\"" }
+        "#;
+        let import = import_grammar(source, ImportFormat::Pest).unwrap();
+        assert_eq!(import.grammar.start_rule, "pair");
+        assert!(!import.grammar.rules.contains_key("WHITESPACE"));
+        assert!(parses(&import, "flag = TRUE"));
+        assert!(parses(&import, "n_1 = 42"));
+        assert!(parses(&import, "s = \"s\""));
+        assert!(!parses(&import, "n = 1234"));
+        assert!(import.warnings.iter().any(|w| w.starts_with("key: atomic rules")));
+        assert!(import.warnings.iter().any(|w| w.starts_with("value: lookahead")));
+    }
+
+    #[test]
+    fn test_import_error_position() {
+        let err = import_grammar("a = \"x\" ;\nb = ;\nc \"y\" ;", ImportFormat::Ebnf).unwrap_err();
+        assert_eq!(err.line, 3);
+    }
+}
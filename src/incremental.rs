@@ -0,0 +1,296 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::ast::{ASTNode, Span};
+use crate::parser::ParseError;
+
+/// ソースへの編集 (バイト範囲 start..end を text で置き換える)
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(start: usize, end: usize, text: &str) -> Self {
+        TextEdit { start, end, text: text.to_string() }
+    }
+
+    /// 2つのテキストの差分を、共通の先頭と末尾を除いた1つの編集として求める
+    pub fn between(old: &str, new: &str) -> Self {
+        let prefix = old
+            .char_indices()
+            .zip(new.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(old.len().min(new.len()), |((i, _), _)| i);
+        let suffix = old[prefix..]
+            .chars()
+            .rev()
+            .zip(new[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum::<usize>();
+        TextEdit::new(prefix, old.len() - suffix, &new[prefix..new.len() - suffix])
+    }
+
+    /// 編集を適用したテキスト
+    /// 範囲がテキストに収まらないか、文字の途中で切れるなら EditError を返す
+    pub fn apply(&self, text: &str) -> Result<String, EditError> {
+        let problem = if self.start > self.end {
+            Some("the start is after the end".to_string())
+        } else if self.end > text.len() {
+            Some(format!("the text is only {} bytes long", text.len()))
+        } else if !text.is_char_boundary(self.start) || !text.is_char_boundary(self.end) {
+            Some("the range splits a character".to_string())
+        } else {
+            None
+        };
+        match problem {
+            Some(problem) => Err(EditError { message: format!("Invalid edit {}..{}: {}", self.start, self.end, problem) }),
+            None => Ok(format!("{}{}{}", &text[..self.start], self.text, &text[self.end..])),
+        }
+    }
+
+    /// 編集範囲より後ろの位置を、編集後の位置にずらす
+    pub fn shift(&self, pos: usize) -> usize {
+        if pos >= self.end {
+            pos - self.end + self.start + self.text.len()
+        } else {
+            pos
+        }
+    }
+
+    /// ノードとその子孫の範囲を編集後の位置にずらす
+    pub fn shift_node(&self, node: &mut ASTNode) {
+        if let Some(span) = &mut node.span {
+            *span = Span { start: self.shift(span.start), end: self.shift(span.end) };
+        }
+        for children in node.children.values_mut() {
+            for child in children {
+                self.shift_node(child);
+            }
+        }
+    }
+}
+
+/// ソースに当てはまらない編集
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditError {
+    pub message: String,
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// 再パースのエラー
+#[derive(Debug)]
+pub enum ReparseError {
+    /// 編集がソースに当てはまらない (パーサーの状態は変わらない)
+    Edit(EditError),
+    /// 編集後のソースのパースに失敗した
+    Parse(ParseError),
+}
+
+impl fmt::Display for ReparseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReparseError::Edit(e) => write!(f, "{}", e),
+            ReparseError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<EditError> for ReparseError {
+    fn from(e: EditError) -> Self {
+        ReparseError::Edit(e)
+    }
+}
+
+impl From<ParseError> for ReparseError {
+    fn from(e: ParseError) -> Self {
+        ReparseError::Parse(e)
+    }
+}
+
+/// 再パースで変わったノード (範囲は編集後のソースでの位置)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangedNode {
+    pub rule: String,
+    pub span: Span,
+}
+
+/// 再パースの結果
+#[derive(Debug)]
+pub struct Reparsed {
+    pub ast: ASTNode,
+    /// 編集前の木に同じものがないノード (外側から、ソース順)
+    pub changed: Vec<ChangedNode>,
+}
+
+/// 編集前後の木を比べ、新しい木のうち変わったノードを求める
+/// 編集範囲にかからず、位置をずらすと内容も範囲も同じノードが前の木にあれば、変わっていないとみなす
+/// (変わっていないノードの子孫は調べない)
+pub fn changed_nodes(old: Option<&ASTNode>, new: &ASTNode, edit: &TextEdit) -> Vec<ChangedNode> {
+    let mut unchanged_candidates = HashMap::new();
+    if let Some(old) = old {
+        collect_outside_edit(old, edit, &mut unchanged_candidates);
+    }
+
+    let mut changed = Vec::new();
+    collect_changed(new, edit, &unchanged_candidates, &mut changed);
+    changed.sort_by_key(|c| (c.span.start, std::cmp::Reverse(c.span.end)));
+    changed
+}
+
+/// 編集範囲にかからない前の木のノードを、編集後の位置で引けるようにする
+/// (編集範囲にかかるノードも、子は編集範囲の外にあるかもしれない)
+/// 値は前の木のノードと、編集より後ろにあるか
+fn collect_outside_edit<'t>(node: &'t ASTNode, edit: &TextEdit, nodes: &mut HashMap<(String, usize, usize), (&'t ASTNode, bool)>) {
+    if let Some(span) = node.span {
+        // 挿入位置で終わるノードは編集より前、挿入位置から始まるノードは編集より後ろ
+        if span.end <= edit.start {
+            nodes.insert((node.name.clone(), span.start, span.end), (node, false));
+        } else if span.start >= edit.end {
+            nodes.insert((node.name.clone(), edit.shift(span.start), edit.shift(span.end)), (node, true));
+        }
+    }
+    for children in node.children.values() {
+        for child in children {
+            collect_outside_edit(child, edit, nodes);
+        }
+    }
+}
+
+fn collect_changed(
+    node: &ASTNode,
+    edit: &TextEdit,
+    unchanged_candidates: &HashMap<(String, usize, usize), (&ASTNode, bool)>,
+    changed: &mut Vec<ChangedNode>,
+) {
+    if let Some(span) = node.span {
+        let old = unchanged_candidates.get(&(node.name.clone(), span.start, span.end));
+        if old.is_some_and(|&(old, after)| same_node(old, node, &|pos| if after { edit.shift(pos) } else { pos })) {
+            return;
+        }
+        changed.push(ChangedNode { rule: node.name.clone(), span });
+    }
+    // 出力を安定させるため子ノードはルール名順に調べる
+    let sorted: BTreeMap<_, _> = node.children.iter().collect();
+    for children in sorted.values() {
+        for child in children.iter() {
+            collect_changed(child, edit, unchanged_candidates, changed);
+        }
+    }
+}
+
+/// 前の木のノードが、位置を shift でずらすと新しい木のノードと同じか
+fn same_node(old: &ASTNode, new: &ASTNode, shift: &dyn Fn(usize) -> usize) -> bool {
+    let shifted = old.span.map(|s| (shift(s.start), shift(s.end)));
+    old.name == new.name
+        && old.value == new.value
+        && shifted == new.span.map(|s| (s.start, s.end))
+        && old.children.len() == new.children.len()
+        && old.children.iter().all(|(name, old_children)| {
+            let new_children = new.get_children(name);
+            old_children.len() == new_children.len()
+                && old_children.iter().zip(new_children).all(|(o, n)| same_node(o, n, shift))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta_parser::MetaParser;
+    use crate::parser::Parser;
+
+    const GRAMMAR: &str = r#"
+        program := func+;
+        func    := "def" name "(" ")" ":" NEWLINE INDENT stmt+ DEDENT;
+        stmt    := SAME_INDENT call NEWLINE;
+        call    := name "(" [[0-9]*] ")";
+        name    := "[a-z_]+";
+    "#;
+
+    fn rules(changed: &[ChangedNode]) -> Vec<&str> {
+        changed.iter().map(|c| c.rule.as_str()).collect()
+    }
+
+    #[test]
+    fn test_text_edit() {
+        let edit = TextEdit::between("f(1)\ng(2)\n", "f(1)\ng(23)\n");
+        assert_eq!(edit, TextEdit::new(8, 8, "3"));
+        assert_eq!(edit.apply("f(1)\ng(2)\n").unwrap(), "f(1)\ng(23)\n");
+        assert_eq!((edit.shift(3), edit.shift(9)), (3, 10));
+
+        let edit = TextEdit::between("# 日本語", "# 日本");
+        assert_eq!(edit, TextEdit::new(8, 11, ""));
+        assert_eq!(TextEdit::between("abc", "abc"), TextEdit::new(3, 3, ""));
+    }
+
+    #[test]
+    fn test_reparse() {
+        let grammar = MetaParser::new(GRAMMAR).parse_input_grammar().unwrap();
+        let source = "def f():\n    a(1)\n    b(2)\ndef g():\n    c(3)\n";
+        let mut parser = Parser::new(&grammar, source).with_incremental();
+        parser.parse().unwrap();
+
+        // 2つ目の文の引数だけを書き換える
+        let edit = TextEdit::new(source.find('2').unwrap(), source.find('2').unwrap() + 1, "42");
+        let reparsed = parser.reparse(&edit).unwrap();
+        let expected = Parser::new(&grammar, &edit.apply(source).unwrap()).parse().unwrap();
+        assert_eq!(reparsed.ast.to_sexp(), expected.to_sexp());
+        assert_eq!(rules(&reparsed.changed), ["program", "func", "stmt", "call"]);
+        assert_eq!(reparsed.changed[3].span, Span { start: 22, end: 27 });
+        // 編集より後ろの関数は位置だけずれて再利用される
+        assert_eq!(reparsed.ast.get_children("func")[1].span, Some(Span { start: 28, end: 46 }));
+        assert!(parser.reused() > 0);
+
+        // 構文エラーを挟んでも、直したら続けて再パースできる
+        // 変わったノードは前回成功した木と比べるので、元に戻しただけなら何も変わっていない
+        let broken = TextEdit::new(0, 3, "deff");
+        assert!(matches!(parser.reparse(&broken), Err(ReparseError::Parse(_))));
+        let fixed = parser.reparse(&TextEdit::new(0, 4, "def")).unwrap();
+        assert_eq!(fixed.ast.to_sexp(), expected.to_sexp());
+        assert!(fixed.changed.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_edit() {
+        let source = "def f():\n    a(\"日本\")\n";
+        let error = |edit: TextEdit| edit.apply(source).unwrap_err().message;
+        assert_eq!(error(TextEdit::new(5, 3, "")), "Invalid edit 5..3: the start is after the end");
+        assert_eq!(error(TextEdit::new(0, 40, "")), "Invalid edit 0..40: the text is only 25 bytes long");
+        assert_eq!(error(TextEdit::new(17, 18, "")), "Invalid edit 17..18: the range splits a character");
+
+        // 当てはまらない編集ではパーサーの状態は変わらない
+        let grammar = MetaParser::new("program := \"[a-z]+\";").parse_input_grammar().unwrap();
+        let mut parser = Parser::new(&grammar, "abc").with_incremental();
+        parser.parse().unwrap();
+        assert!(matches!(parser.reparse(&TextEdit::new(2, 9, "x")), Err(ReparseError::Edit(_))));
+        let reparsed = parser.reparse(&TextEdit::new(3, 3, "d")).unwrap();
+        assert_eq!(reparsed.ast.value, "abcd");
+    }
+
+    #[test]
+    fn test_reparse_indentation() {
+        let grammar = MetaParser::new(GRAMMAR).parse_input_grammar().unwrap();
+        let source = "def f():\n    a()\ndef g():\n    b()\n";
+        let mut parser = Parser::new(&grammar, source).with_incremental();
+        parser.parse().unwrap();
+
+        // 関数 g を f の本体に入れるとブロックの構造が変わる
+        let edit = TextEdit::new(17, 17, "    c()\n");
+        let reparsed = parser.reparse(&edit).unwrap();
+        assert_eq!(reparsed.ast.get_child("func").unwrap().get_children("stmt").len(), 2);
+        assert_eq!(rules(&reparsed.changed), ["program", "func", "stmt", "call", "name"]);
+
+        // 編集前と同じ状態 (インデントなど) から始まらないノードは再利用しない
+        let dedent = parser.reparse(&TextEdit::new(17, 21, "")).unwrap_err();
+        let expected = Parser::new(&grammar, "def f():\n    a()\nc()\ndef g():\n    b()\n").parse().unwrap_err();
+        assert_eq!(dedent.to_string(), expected.to_string());
+    }
+}
@@ -0,0 +1,178 @@
+/// 列の数え方
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnEncoding {
+    /// UTF-8 のバイト数
+    Utf8,
+    /// UTF-16 のコード単位数 (LSP の既定)
+    Utf16,
+    /// 文字 (Unicode スカラー値) 数
+    Char,
+}
+
+/// タブを展開する幅 (表示とインデントの深さで共通)
+pub const TAB_WIDTH: usize = 8;
+
+/// バイトオフセットと行・列を相互に変換するための行頭の一覧
+/// ソースごとに一度だけ作り、行は二分探索で求める
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        LineIndex { line_starts, len: text.len() }
+    }
+
+    /// 行数 (最後の改行の後も1行と数える)
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// オフセットを含む行 (0始まり)
+    pub fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset.min(self.len)) - 1
+    }
+
+    /// 行頭のオフセット (行が範囲外なら末尾)
+    pub fn line_start(&self, line: usize) -> usize {
+        self.line_starts.get(line).copied().unwrap_or(self.len)
+    }
+
+    /// 行の内容 (改行を含まない)
+    pub fn line_text<'t>(&self, text: &'t str, line: usize) -> &'t str {
+        let start = self.line_start(line);
+        let end = self.line_starts.get(line + 1).map_or(self.len, |next| next - 1);
+        text[start..end.max(start)].trim_end_matches('\r')
+    }
+
+    /// バイトオフセットを行・列 (0始まり) に変換
+    pub fn line_col(&self, text: &str, offset: usize, encoding: ColumnEncoding) -> (usize, usize) {
+        let offset = offset.min(self.len);
+        let line = self.line(offset);
+        let before = &text[self.line_start(line)..offset];
+        let column = match encoding {
+            ColumnEncoding::Utf8 => before.len(),
+            ColumnEncoding::Utf16 => before.encode_utf16().count(),
+            ColumnEncoding::Char => before.chars().count(),
+        };
+        (line, column)
+    }
+
+    /// 行・列 (0始まり) をバイトオフセットに変換
+    /// 列が行末を越えていれば行末、文字の途中を指していればその文字の先頭にする
+    pub fn offset(&self, text: &str, line: usize, column: usize, encoding: ColumnEncoding) -> usize {
+        if line >= self.line_count() {
+            return self.len;
+        }
+        let start = self.line_start(line);
+        let mut units = 0;
+        for (i, ch) in self.line_text(text, line).char_indices() {
+            units += match encoding {
+                ColumnEncoding::Utf8 => ch.len_utf8(),
+                ColumnEncoding::Utf16 => ch.len_utf16(),
+                ColumnEncoding::Char => 1,
+            };
+            if units > column {
+                return start + i;
+            }
+        }
+        start + self.line_text(text, line).len()
+    }
+}
+
+/// 行頭からのテキストの端末での表示幅
+/// タブは次のタブ位置まで、全角文字は2桁、結合文字などは0桁と数える
+pub fn display_width(line_prefix: &str) -> usize {
+    line_prefix.chars().fold(0, |width, ch| match ch {
+        '\t' => (width / TAB_WIDTH + 1) * TAB_WIDTH,
+        _ => width + char_width(ch),
+    })
+}
+
+/// 行頭の空白とタブが表すインデントの深さ
+/// タブは次のタブ位置まで進める (パーサーがブロックを決めるのと、診断がブロックを探すのとで共通)
+pub fn indent_width(line: &str) -> usize {
+    display_width(&line[..line.len() - line.trim_start_matches([' ', '\t']).len()])
+}
+
+/// タブを空白に展開した行 (display_width と同じ桁に揃う)
+pub fn expand_tabs(line: &str) -> String {
+    let mut expanded = String::new();
+    let mut width = 0;
+    for ch in line.chars() {
+        if ch == '\t' {
+            let next = (width / TAB_WIDTH + 1) * TAB_WIDTH;
+            expanded.push_str(&" ".repeat(next - width));
+            width = next;
+        } else {
+            expanded.push(ch);
+            width += char_width(ch);
+        }
+    }
+    expanded
+}
+
+/// 1文字の表示幅
+fn char_width(ch: char) -> usize {
+    let code = ch as u32;
+    match code {
+        // 結合文字・ゼロ幅文字・異体字セレクタ
+        0x0300..=0x036F | 0x200B..=0x200F | 0x20D0..=0x20FF | 0xFE00..=0xFE0F | 0xFE20..=0xFE2F => 0,
+        // 東アジアの全角文字 (ハングル字母、CJK、かな、全角形、絵文字など)
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ if ch.is_control() => 0,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_col_encodings() {
+        // 「日本語」は1文字3バイト、UTF-16 では1単位、"😀" は4バイト、UTF-16 では2単位
+        let text = "# 日本語\nx = \"😀\" + y\r\n";
+        let index = LineIndex::new(text);
+        let y = text.find('y').unwrap();
+
+        assert_eq!(index.line_col(text, y, ColumnEncoding::Utf8), (1, 13));
+        assert_eq!(index.line_col(text, y, ColumnEncoding::Utf16), (1, 11));
+        assert_eq!(index.line_col(text, y, ColumnEncoding::Char), (1, 10));
+        for encoding in [ColumnEncoding::Utf8, ColumnEncoding::Utf16, ColumnEncoding::Char] {
+            let (line, column) = index.line_col(text, y, encoding);
+            assert_eq!(index.offset(text, line, column, encoding), y);
+        }
+
+        assert_eq!(index.line_text(text, 0), "# 日本語");
+        assert_eq!(index.line_text(text, 1), "x = \"😀\" + y");
+        assert_eq!(index.line_count(), 3);
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("# 日本語"), 8);
+        assert_eq!(display_width("\tx"), 9);
+        assert_eq!(display_width("ab\tc"), 9);
+        assert_eq!(expand_tabs("ab\tc"), "ab      c");
+        assert_eq!(indent_width("  \tx\ty"), 8);
+        assert_eq!(indent_width("\t  x"), 10);
+        assert_eq!(indent_width("x  "), 0);
+    }
+}
@@ -0,0 +1,700 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::config;
+use crate::line_index::{ColumnEncoding, LineIndex};
+use crate::meta_parser::{GrammarKind, InputGrammar, MetaParseError, MetaParser, OutputGrammar};
+
+/// 入力BNFの特殊トークン
+const LAYOUT_TOKENS: [&str; 4] = ["INDENT", "DEDENT", "NEWLINE", "SAME_INDENT"];
+/// 入力BNFの補完候補に出す組み込み構文
+const INPUT_BUILTINS: [&str; 7] = ["INDENT", "DEDENT", "NEWLINE", "SAME_INDENT", "@start", "@label", "@expect"];
+/// 出力BNFの組み込み構文
+const OUTPUT_BUILTINS: [&str; 7] = ["@value", "@context", "join", "match", "if", "then", "else"];
+
+/// LSP の DiagnosticSeverity
+const SEVERITY_ERROR: u32 = 1;
+const SEVERITY_WARNING: u32 = 2;
+/// LSP の CompletionItemKind
+const KIND_FUNCTION: u32 = 3;
+const KIND_KEYWORD: u32 = 14;
+
+/// .bnf ファイル用の言語サーバー (stdio トランスポート)
+pub struct LanguageServer {
+    /// 開いているドキュメント (URI → 内容)
+    documents: HashMap<String, String>,
+    shutdown: bool,
+    /// 位置の列の単位 (initialize でクライアントと取り決める)
+    encoding: ColumnEncoding,
+}
+
+/// ソース中のルール名の出現
+#[derive(Debug, Clone)]
+struct Symbol {
+    name: String,
+    start: usize,
+    end: usize,
+    /// ルール定義の左辺か
+    definition: bool,
+}
+
+/// パースした文法
+enum Parsed {
+    Input(InputGrammar),
+    Output(OutputGrammar),
+}
+
+impl LanguageServer {
+    pub fn new() -> Self {
+        LanguageServer {
+            documents: HashMap::new(),
+            shutdown: false,
+            encoding: ColumnEncoding::Utf16,
+        }
+    }
+
+    /// exit 通知を受け取るか入力が終わるまでメッセージを処理
+    pub fn run(&mut self, mut reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+        while let Some(message) = read_message(&mut reader)? {
+            if message["method"] == "exit" {
+                break;
+            }
+            for reply in self.handle(&message) {
+                write_message(&mut writer, &reply)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// メッセージ1つを処理し、送り返すメッセージ (応答・通知) を返す
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let id = message.get("id").cloned();
+
+        // shutdown 後は exit 以外を受け付けない
+        if self.shutdown {
+            return match id {
+                Some(id) => vec![json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32600, "message": "Server is shutting down" }
+                })],
+                None => Vec::new(),
+            };
+        }
+
+        let result = match method {
+            "initialize" => {
+                // クライアントが UTF-8 の列を扱えるならそれを使い、そうでなければ既定の UTF-16
+                let encodings = params["capabilities"]["general"]["positionEncodings"].as_array();
+                let utf8 = encodings.is_some_and(|e| e.iter().any(|v| v == "utf-8"));
+                self.encoding = if utf8 { ColumnEncoding::Utf8 } else { ColumnEncoding::Utf16 };
+                json!({
+                    "capabilities": {
+                        "positionEncoding": if utf8 { "utf-8" } else { "utf-16" },
+                        "textDocumentSync": 1,
+                        "definitionProvider": true,
+                        "referencesProvider": true,
+                        "hoverProvider": true,
+                        "completionProvider": { "triggerCharacters": ["@"] }
+                    },
+                    "serverInfo": { "name": "hensan", "version": env!("CARGO_PKG_VERSION") }
+                })
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                Value::Null
+            }
+            "textDocument/didOpen" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+                let text = params["textDocument"]["text"].as_str().unwrap_or("").to_string();
+                self.documents.insert(uri, text);
+                return self.publish_all();
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+                // 全文同期のため最後の変更内容がドキュメント全体
+                if let Some(text) = params["contentChanges"].as_array().and_then(|c| c.last()) {
+                    self.documents.insert(uri, text["text"].as_str().unwrap_or("").to_string());
+                }
+                return self.publish_all();
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                self.documents.remove(uri);
+                let mut replies = self.publish_all();
+                replies.push(notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                ));
+                return replies;
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            _ => {
+                // 未対応のリクエストにはエラーを返し、通知は無視する
+                return match id {
+                    Some(id) => vec![json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32601, "message": format!("Method not found: {}", method) }
+                    })],
+                    None => Vec::new(),
+                };
+            }
+        };
+
+        match id {
+            Some(id) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            None => Vec::new(),
+        }
+    }
+
+    /// 開いている全ドキュメントの診断を送る
+    /// (入力BNFの変更は対になる出力BNFの診断にも影響するため)
+    fn publish_all(&self) -> Vec<Value> {
+        let mut uris: Vec<&String> = self.documents.keys().collect();
+        uris.sort();
+        uris.into_iter()
+            .map(|uri| {
+                notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": self.diagnostics(uri) }),
+                )
+            })
+            .collect()
+    }
+
+    /// ドキュメントの診断
+    fn diagnostics(&self, uri: &str) -> Vec<Value> {
+        let text = &self.documents[uri];
+        let kind = self.grammar_kind(uri);
+        let mut diagnostics = Vec::new();
+
+        if let Err(err) = parse(text, kind) {
+            let end = next_char_boundary(text, err.offset);
+            diagnostics.push(diagnostic(self.encoding, text, err.offset, end, SEVERITY_ERROR, &err.message));
+            // 構文エラーがあると後続の解析は当てにならない
+            return diagnostics;
+        }
+
+        let symbols = scan_symbols(text);
+        let defined: Vec<&str> = symbols.iter().filter(|s| s.definition).map(|s| s.name.as_str()).collect();
+
+        // 重複したルール定義 (後の定義が有効になる)
+        for (i, symbol) in symbols.iter().enumerate().filter(|(_, s)| s.definition) {
+            if symbols[i + 1..].iter().any(|s| s.definition && s.name == symbol.name) {
+                let message = format!("Rule '{}' is defined again later; this definition is ignored", symbol.name);
+                diagnostics.push(diagnostic(self.encoding, text, symbol.start, symbol.end, SEVERITY_WARNING, &message));
+            }
+        }
+
+        match kind {
+            GrammarKind::Input => {
+                for symbol in symbols.iter().filter(|s| !s.definition) {
+                    if !defined.contains(&symbol.name.as_str()) {
+                        let message = format!("Undefined rule '{}'", symbol.name);
+                        diagnostics.push(diagnostic(self.encoding, text, symbol.start, symbol.end, SEVERITY_ERROR, &message));
+                    }
+                }
+            }
+            GrammarKind::Output => {
+                // 出力ルールの名前と参照は、対になる入力BNFのルール名でなければならない
+                if let Some((input_uri, input_text)) = self.paired_input(uri) {
+                    let input_rules: Vec<String> = scan_symbols(&input_text)
+                        .into_iter()
+                        .filter(|s| s.definition)
+                        .map(|s| s.name)
+                        .collect();
+                    let input_name = uri_file_name(&input_uri);
+                    for symbol in &symbols {
+                        if input_rules.contains(&symbol.name) {
+                            continue;
+                        }
+                        let message = if symbol.definition {
+                            format!("Output rule '{}' has no matching rule in {}", symbol.name, input_name)
+                        } else {
+                            format!("Rule '{}' is not defined in {}", symbol.name, input_name)
+                        };
+                        diagnostics.push(diagnostic(self.encoding, text, symbol.start, symbol.end, SEVERITY_WARNING, &message));
+                    }
+                }
+            }
+        }
+
+        diagnostics
+    }
+
+    /// 定義へ移動
+    fn definition(&self, params: &Value) -> Value {
+        let Some((uri, symbol)) = self.symbol_at(params) else { return Value::Null };
+
+        if let Some(location) = definition_location(self.encoding, &uri, &self.documents[&uri], &symbol.name) {
+            return location;
+        }
+        // 出力BNFでは入力BNFのルールに移動する
+        if self.grammar_kind(&uri) == GrammarKind::Output {
+            if let Some((input_uri, input_text)) = self.paired_input(&uri) {
+                return definition_location(self.encoding, &input_uri, &input_text, &symbol.name).unwrap_or(Value::Null);
+            }
+        }
+        Value::Null
+    }
+
+    /// 参照を検索
+    fn references(&self, params: &Value) -> Value {
+        let Some((uri, symbol)) = self.symbol_at(params) else { return json!([]) };
+        let include_declaration = params["context"]["includeDeclaration"].as_bool().unwrap_or(true);
+        let text = &self.documents[&uri];
+
+        let locations: Vec<Value> = scan_symbols(text)
+            .into_iter()
+            .filter(|s| s.name == symbol.name && (include_declaration || !s.definition))
+            .map(|s| location(self.encoding, &uri, text, s.start, s.end))
+            .collect();
+        Value::Array(locations)
+    }
+
+    /// ルールの式を表示
+    fn hover(&self, params: &Value) -> Value {
+        let Some((uri, symbol)) = self.symbol_at(params) else { return Value::Null };
+        let text = &self.documents[&uri];
+        let mut sections = Vec::new();
+
+        let kind = self.grammar_kind(&uri);
+        if let Some(rule) = rule_text(text, kind, &symbol.name) {
+            sections.push(rule);
+        }
+        // 出力BNFでは対応する入力ルールも並べる
+        if kind == GrammarKind::Output {
+            if let Some((_, input_text)) = self.paired_input(&uri) {
+                if let Some(rule) = rule_text(&input_text, GrammarKind::Input, &symbol.name) {
+                    sections.push(format!("// input\n{}", rule));
+                }
+            }
+        }
+
+        if sections.is_empty() {
+            return Value::Null;
+        }
+        json!({
+            "contents": { "kind": "markdown", "value": format!("```bnf\n{}\n```", sections.join("\n\n")) },
+            "range": range(self.encoding, text, symbol.start, symbol.end)
+        })
+    }
+
+    /// ルール名と組み込み構文を補完
+    fn completion(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let Some(text) = self.documents.get(uri) else { return json!([]) };
+        let kind = self.grammar_kind(uri);
+
+        let mut names: Vec<String> = scan_symbols(text).into_iter().filter(|s| s.definition).map(|s| s.name).collect();
+        if kind == GrammarKind::Output {
+            if let Some((_, input_text)) = self.paired_input(uri) {
+                names.extend(scan_symbols(&input_text).into_iter().filter(|s| s.definition).map(|s| s.name));
+            }
+        }
+        names.sort();
+        names.dedup();
+
+        let builtins: &[&str] = match kind {
+            GrammarKind::Input => &INPUT_BUILTINS,
+            GrammarKind::Output => &OUTPUT_BUILTINS,
+        };
+
+        let mut items: Vec<Value> = names
+            .iter()
+            .map(|name| json!({ "label": name, "kind": KIND_FUNCTION }))
+            .collect();
+        items.extend(builtins.iter().map(|b| {
+            // "@" を入力した後の補完では "@" を重ねない
+            json!({ "label": b, "kind": KIND_KEYWORD, "insertText": b.trim_start_matches('@') })
+        }));
+        Value::Array(items)
+    }
+
+    /// リクエストの位置にあるルール名
+    fn symbol_at(&self, params: &Value) -> Option<(String, Symbol)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let text = self.documents.get(uri)?;
+        let line = params["position"]["line"].as_u64()? as usize;
+        let character = params["position"]["character"].as_u64()? as usize;
+        let offset = LineIndex::new(text).offset(text, line, character, self.encoding);
+
+        let symbol = scan_symbols(text).into_iter().find(|s| s.start <= offset && offset <= s.end)?;
+        Some((uri.to_string(), symbol))
+    }
+
+    /// ドキュメントの文法の種類と、出力BNFなら対になる入力BNFの URI
+    /// hensan.toml のパイプラインで決め、設定がなければファイル名と同じディレクトリの input.bnf から推測する
+    fn grammar_role(&self, uri: &str) -> (GrammarKind, Option<String>) {
+        let path = uri_to_path(uri);
+        if let Some(role) = path.as_deref().and_then(config::find_grammar_role) {
+            let input_uri = role.input.map(|input| {
+                // 開いているドキュメントなら、クライアントの URI をそのまま使う
+                let open = self.documents.keys().find(|uri| uri_to_path(uri).is_some_and(|path| config::same_file(&path, &input)));
+                open.cloned().unwrap_or_else(|| path_to_uri(&input))
+            });
+            return (role.kind, input_uri);
+        }
+
+        let (dir, name) = uri.rsplit_once('/').unwrap_or(("", uri));
+        let input_uri = format!("{}/input.bnf", dir);
+        let kind = if name != "input.bnf" && self.documents.contains_key(&input_uri) {
+            GrammarKind::Output
+        } else {
+            path.map_or_else(|| GrammarKind::from_file_name(name), |path| GrammarKind::from_path(&path))
+        };
+        (kind, (kind == GrammarKind::Output).then_some(input_uri))
+    }
+
+    fn grammar_kind(&self, uri: &str) -> GrammarKind {
+        self.grammar_role(uri).0
+    }
+
+    /// 出力BNFと対になる入力BNF (開いていればその内容、なければディスクから読む)
+    fn paired_input(&self, uri: &str) -> Option<(String, String)> {
+        let input_uri = self.grammar_role(uri).1?;
+        if let Some(text) = self.documents.get(&input_uri) {
+            return Some((input_uri, text.clone()));
+        }
+        let text = fs::read_to_string(uri_to_path(&input_uri)?).ok()?;
+        Some((input_uri, text))
+    }
+}
+
+fn uri_file_name(uri: &str) -> &str {
+    uri.rsplit('/').next().unwrap_or(uri)
+}
+
+/// file:// URI をパスに変換
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%' && i + 2 < bytes.len())
+            .then(|| std::str::from_utf8(&bytes[i + 1..i + 3]).ok())
+            .flatten()
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    Some(Path::new(&String::from_utf8(decoded).ok()?).to_path_buf())
+}
+
+/// パスを file:// URI に変換 (英数字と "/-._~" 以外はパーセントエンコードする)
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &byte in path.to_string_lossy().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// MetaParser で文法をパースする
+fn parse(text: &str, kind: GrammarKind) -> Result<Parsed, MetaParseError> {
+    let mut parser = MetaParser::new(text);
+    match kind {
+        GrammarKind::Input => parser.parse_input_grammar().map(Parsed::Input),
+        GrammarKind::Output => parser.parse_output_grammar().map(Parsed::Output),
+    }
+}
+
+/// ルールを "name := expr;" の形で返す
+fn rule_text(text: &str, kind: GrammarKind, name: &str) -> Option<String> {
+    match parse(text, kind).ok()? {
+        Parsed::Input(grammar) => grammar.rules.get(name).map(|r| r.to_string()),
+        Parsed::Output(grammar) => grammar.rules.get(name).map(|r| r.to_string()),
+    }
+}
+
+/// ルール定義の位置
+fn definition_location(encoding: ColumnEncoding, uri: &str, text: &str, name: &str) -> Option<Value> {
+    scan_symbols(text)
+        .into_iter()
+        .find(|s| s.definition && s.name == name)
+        .map(|s| location(encoding, uri, text, s.start, s.end))
+}
+
+/// ソース中のルール名を列挙する
+/// 文字列・パターン・コメント・組み込み構文はルール名として扱わない
+fn scan_symbols(text: &str) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    // 次の識別子がルール定義の左辺になりうるか (ファイル先頭か ';' の直後)
+    let mut at_rule_start = true;
+
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '/' if text[i..].starts_with("//") => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                }
+            }
+            '[' => {
+                let mut depth = 1;
+                for (_, c) in chars.by_ref() {
+                    match c {
+                        '[' => depth += 1,
+                        ']' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            ';' => at_rule_start = true,
+            _ if ch.is_alphabetic() || ch == '_' || ch == '@' => {
+                let mut end = i + ch.len_utf8();
+                while let Some(&(j, c)) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        end = j + c.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let name = &text[i..end];
+                let definition = at_rule_start && text[end..].trim_start().starts_with(":=");
+                at_rule_start = false;
+
+                let builtin = name.starts_with('@')
+                    || name == "_"
+                    || LAYOUT_TOKENS.contains(&name)
+                    || OUTPUT_BUILTINS.contains(&name);
+                if definition || !builtin {
+                    symbols.push(Symbol { name: name.to_string(), start: i, end, definition });
+                }
+            }
+            _ if ch.is_whitespace() => {}
+            _ => at_rule_start = false,
+        }
+    }
+
+    symbols
+}
+
+fn next_char_boundary(text: &str, offset: usize) -> usize {
+    text[offset..].chars().next().map_or(offset, |c| offset + c.len_utf8())
+}
+
+/// バイトオフセットの範囲を LSP の範囲 (行, 取り決めた単位の列) に変換
+fn range(encoding: ColumnEncoding, text: &str, start: usize, end: usize) -> Value {
+    let index = LineIndex::new(text);
+    let position = |offset| {
+        let (line, character) = index.line_col(text, offset, encoding);
+        json!({ "line": line, "character": character })
+    };
+    json!({ "start": position(start), "end": position(end) })
+}
+
+fn location(encoding: ColumnEncoding, uri: &str, text: &str, start: usize, end: usize) -> Value {
+    json!({ "uri": uri, "range": range(encoding, text, start, end) })
+}
+
+fn diagnostic(encoding: ColumnEncoding, text: &str, start: usize, end: usize, severity: u32, message: &str) -> Value {
+    json!({
+        "range": range(encoding, text, start, end),
+        "severity": severity,
+        "source": "hensan",
+        "message": message
+    })
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// Content-Length ヘッダ付きのメッセージを1つ読む (入力の終わりなら None)
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(io::Error::other)
+}
+
+/// Content-Length ヘッダ付きでメッセージを書く
+fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT_URI: &str = "file:///project/Grammar/input.bnf";
+    const OUTPUT_URI: &str = "file:///project/Grammar/output.bnf";
+
+    fn open(server: &mut LanguageServer, uri: &str, text: &str) -> Vec<Value> {
+        server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "languageId": "bnf", "version": 1, "text": text } }
+        }))
+    }
+
+    fn diagnostics_for<'v>(replies: &'v [Value], uri: &str) -> &'v Vec<Value> {
+        replies
+            .iter()
+            .find(|r| r["params"]["uri"] == uri)
+            .and_then(|r| r["params"]["diagnostics"].as_array())
+            .expect("no diagnostics published")
+    }
+
+    fn request(server: &mut LanguageServer, method: &str, uri: &str, line: usize, character: usize) -> Value {
+        let replies = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": {
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+                "context": { "includeDeclaration": true }
+            }
+        }));
+        replies[0]["result"].clone()
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut server = LanguageServer::new();
+
+        let replies = open(&mut server, INPUT_URI, "start := name \"(\"\n");
+        let diagnostics = diagnostics_for(&replies, INPUT_URI);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["severity"], SEVERITY_ERROR);
+        assert_eq!(diagnostics[0]["range"]["start"]["line"], 1);
+
+        let replies = open(&mut server, INPUT_URI, "start := name other;\nname := \"[a-z]+\";\n");
+        let diagnostics = diagnostics_for(&replies, INPUT_URI);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["message"], "Undefined rule 'other'");
+
+        let replies = open(&mut server, OUTPUT_URI, "start := name;\nmissing := \"x\";\n");
+        let diagnostics = diagnostics_for(&replies, OUTPUT_URI);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["message"], "Output rule 'missing' has no matching rule in input.bnf");
+        assert_eq!(diagnostics[0]["range"]["start"], json!({ "line": 1, "character": 0 }));
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut server = LanguageServer::new();
+        open(&mut server, INPUT_URI, "start := name \"=\" name;\n// コメント\nname := \"[a-z]+\";\n");
+        open(&mut server, OUTPUT_URI, "start := name \" <- \" name;\n");
+
+        // 参照から定義へ
+        let definition = request(&mut server, "textDocument/definition", INPUT_URI, 0, 10);
+        assert_eq!(definition["range"]["start"], json!({ "line": 2, "character": 0 }));
+
+        // 出力BNFからは入力BNFの定義へ
+        let definition = request(&mut server, "textDocument/definition", OUTPUT_URI, 0, 10);
+        assert_eq!(definition["uri"], INPUT_URI);
+
+        let references = request(&mut server, "textDocument/references", INPUT_URI, 2, 1);
+        assert_eq!(references.as_array().unwrap().len(), 3);
+
+        let hover = request(&mut server, "textDocument/hover", OUTPUT_URI, 0, 0);
+        let contents = hover["contents"]["value"].as_str().unwrap();
+        assert!(contents.contains("start := name \" <- \" name;"));
+        assert!(contents.contains("start := name \"=\" name;"));
+
+        let completion = request(&mut server, "textDocument/completion", OUTPUT_URI, 0, 0);
+        let labels: Vec<&str> = completion.as_array().unwrap().iter().filter_map(|i| i["label"].as_str()).collect();
+        assert!(labels.contains(&"name"));
+        assert!(labels.contains(&"@value"));
+        assert!(labels.contains(&"join"));
+    }
+
+    #[test]
+    fn test_grammar_role() {
+        let dir = std::env::temp_dir().join(format!("hensan-lsp-{}", std::process::id()));
+        fs::create_dir_all(dir.join("grammar")).unwrap();
+        fs::write(dir.join("grammar/c.bnf"), "start := name;\nname := \"[a-z]+\";\n").unwrap();
+        let rust_uri = path_to_uri(&dir.join("grammar/rust.bnf"));
+        let mut server = LanguageServer::new();
+
+        // 設定がなくても、input.bnf の隣の文法は出力BNFとして扱う
+        fs::write(dir.join("grammar/input.bnf"), "start := name;\nname := \"[a-z]+\";\n").unwrap();
+        let replies = open(&mut server, &rust_uri, "start := name \";\";\nname := match @value { _ => @value };\n");
+        assert_eq!(diagnostics_for(&replies, &rust_uri), &Vec::<Value>::new());
+        fs::remove_file(dir.join("grammar/input.bnf")).unwrap();
+
+        // hensan.toml のパイプラインで対になる入力BNFを決める
+        let config = "[pipeline.main]\ninput = \"grammar/c.bnf\"\n[[pipeline.main.output]]\ngrammar = \"grammar/rust.bnf\"\n";
+        fs::write(dir.join(config::CONFIG_FILE), config).unwrap();
+        let replies = open(&mut server, &rust_uri, "start := name \";\";\nmissing := match @value { _ => @value };\n");
+        let diagnostics = diagnostics_for(&replies, &rust_uri);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["message"], "Output rule 'missing' has no matching rule in c.bnf");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_message_framing() {
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#;
+        let exit = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let input = format!("Content-Length: {}\r\n\r\n{}Content-Length: {}\r\n\r\n{}", body.len(), body, exit.len(), exit);
+
+        let mut output = Vec::new();
+        LanguageServer::new().run(input.as_bytes(), &mut output).unwrap();
+
+        let mut reader = output.as_slice();
+        let reply = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(reply["id"], 1);
+        assert_eq!(reply["result"]["capabilities"]["hoverProvider"], true);
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+}
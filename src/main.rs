@@ -1,171 +1,1541 @@
 mod ast;
+mod batch;
+mod cache;
+mod config;
+mod diagnostics;
+mod diff;
+mod doc;
+mod export;
+mod formatter;
 mod generator;
+mod golden;
+mod grammar_formatter;
+mod import;
+mod incremental;
+mod line_index;
+mod lsp;
 mod meta_parser;
 mod parser;
+mod repl;
+mod rewrite;
+mod source_map;
+mod templates;
+mod trace;
+mod unparser;
+mod watch;
 
 use std::env;
+use std::fmt::Display;
 use std::fs;
-use std::path::Path;
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::{Duration, Instant};
 
+use ast::ASTNode;
+use batch::{BatchTranslator, Failure};
+use cache::{CompiledGrammars, LoadError};
+use config::{Config, Project, CONFIG_FILE};
+use diagnostics::{Diagnostic, Renderer};
+use doc::DocGenerator;
+use export::{export_grammar, ExportFormat};
+use import::{import_grammar, ImportFormat};
+use lsp::LanguageServer;
+use formatter::{FormatError, Formatter};
 use generator::Generator;
-use meta_parser::MetaParser;
-use parser::Parser;
-
-const GRAMMAR_DIR: &str = "Grammar";
-const DEFAULT_INPUT_BNF: &str = "input.bnf";
-const DEFAULT_OUTPUT_BNF: &str = "output.bnf";
-
-// デフォルトの入力BNF
-const DEFAULT_INPUT_GRAMMAR: &str = r#"func_decl := ret_type name "(" args? ")" ";";
-args      := arg ("," arg)*;
-arg       := type name;
-ret_type  := "void" | "int";
-type      := "int" | "float";
-name      := "[a-zA-Z_]+";
-"#;
-
-// デフォルトの出力BNF
-const DEFAULT_OUTPUT_GRAMMAR: &str = r#"// nameとret_typeの位置が移動している
-func_decl := "fn " name "(" args? ")" " -> " ret_type ";";
-
-// リストはカンマ区切りで展開
-args      := arg join ", ";
-
-// typeとnameの順序が逆転
-arg       := name ": " type;
-
-// 型名の変換
-ret_type  := match @value {
-    "void" => "()",
-    "int"  => "i32",
-    _ => @value
-};
-type      := match @value {
-    "int" => "i32",
-    "float" => "f64",
-    _ => @value
-};
-"#;
-
-fn ensure_grammar_files() {
-    let grammar_dir = Path::new(GRAMMAR_DIR);
-
-    // Grammarディレクトリがなければ作成
-    if !grammar_dir.exists() {
-        fs::create_dir_all(grammar_dir).unwrap_or_else(|e| {
-            eprintln!("Error creating Grammar directory: {}", e);
-            process::exit(1);
-        });
-        eprintln!("Created {} directory", GRAMMAR_DIR);
+use golden::{GoldenTarget, GoldenTester, Outcome};
+use grammar_formatter::GrammarFormatter;
+use meta_parser::{GrammarKind, InputGrammar, OutputGrammar};
+use parser::{ParseError, Parser};
+use repl::Repl;
+use rewrite::RewritePass;
+use source_map::SourceMap;
+use unparser::Unparser;
+use watch::Watch;
+
+// 終了コード (スクリプトから失敗の種類を区別できるようにする)
+/// ソースのパースエラー (回復したエラーも含む)
+const EXIT_PARSE_ERROR: i32 = 1;
+/// ゴールデンテストの失敗 (パースエラーと同じ値)
+const EXIT_TEST_FAILED: i32 = 1;
+/// コマンドラインの誤り
+const EXIT_USAGE: i32 = 2;
+/// 文法ファイルや hensan.toml の誤り
+const EXIT_GRAMMAR_ERROR: i32 = 3;
+/// ファイルの読み書きの失敗
+const EXIT_IO_ERROR: i32 = 4;
+
+/// 標準エラー出力に書くメッセージの量
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+/// 全てのサブコマンドに共通のオプション
+struct GlobalOptions {
+    /// --pipeline NAME: 文法ファイルを省略したときに使う hensan.toml のパイプライン
+    pipeline: Option<String>,
+    /// --quiet / --verbose
+    verbosity: Verbosity,
+    /// --help: サブコマンドの使い方を表示する
+    help: bool,
+}
+
+impl GlobalOptions {
+    /// 共通のオプションを取り出して引数リストから取り除く
+    fn take(args: &mut Vec<String>) -> Self {
+        let pipeline = take_option(args, "--pipeline");
+        let verbosity = match (take_flag(args, "--quiet"), take_flag(args, "--verbose")) {
+            (true, true) => {
+                eprintln!("--quiet and --verbose cannot be used together");
+                process::exit(EXIT_USAGE);
+            }
+            (true, false) => Verbosity::Quiet,
+            (false, true) => Verbosity::Verbose,
+            (false, false) => Verbosity::Normal,
+        };
+        let help = take_flag(args, "--help") || take_flag(args, "-h");
+        GlobalOptions { pipeline, verbosity, help }
     }
 
-    // input.bnf がなければ作成
-    let input_path = grammar_dir.join(DEFAULT_INPUT_BNF);
-    if !input_path.exists() {
-        fs::write(&input_path, DEFAULT_INPUT_GRAMMAR).unwrap_or_else(|e| {
-            eprintln!("Error creating {}: {}", input_path.display(), e);
-            process::exit(1);
-        });
-        eprintln!("Created {}", input_path.display());
+    fn pipeline(&self) -> Option<&str> {
+        self.pipeline.as_deref()
     }
 
-    // output.bnf がなければ作成
-    let output_path = grammar_dir.join(DEFAULT_OUTPUT_BNF);
-    if !output_path.exists() {
-        fs::write(&output_path, DEFAULT_OUTPUT_GRAMMAR).unwrap_or_else(|e| {
-            eprintln!("Error creating {}: {}", output_path.display(), e);
-            process::exit(1);
-        });
-        eprintln!("Created {}", output_path.display());
+    /// 使い方を書いた後で終了する (--help で求められたなら成功、引数の誤りなら EXIT_USAGE)
+    fn exit_after_usage(&self) -> ! {
+        process::exit(if self.help { 0 } else { EXIT_USAGE })
+    }
+
+    /// 進捗や要約を書く (--quiet なら書かない)
+    fn info(&self, message: impl Display) {
+        if self.verbosity >= Verbosity::Normal {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// 詳しい情報を書く (--verbose のときだけ)
+    fn detail(&self, message: impl Display) {
+        if self.verbosity >= Verbosity::Verbose {
+            eprintln!("{}", message);
+        }
     }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+/// 文法ファイルを省略したときに使う hensan.toml のパイプライン
+/// (CWD から親ディレクトリへ探し、見つからなければ `hensan init` を案内して終了する)
+fn require_project(options: &GlobalOptions) -> Project {
+    let cwd = env::current_dir().unwrap_or_else(|e| {
+        eprintln!("Error reading the current directory: {}", e);
+        process::exit(EXIT_IO_ERROR);
+    });
+    let Some(path) = Config::find(&cwd) else {
+        eprintln!("Error: no grammar files given and no {} found in {} or its parents", CONFIG_FILE, cwd.display());
+        eprintln!("Run `hensan init` to create one, or pass the grammar files as arguments");
+        process::exit(EXIT_USAGE);
+    };
+    let root = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let project = Config::load(&path).and_then(|config| config.select(&root, options.pipeline())).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(EXIT_GRAMMAR_ERROR);
+    });
+    options.detail(format!("Using pipeline '{}' from {}", project.name, path.display()));
+    project
+}
+
+/// 入力BNFのパス (省略されたら hensan.toml のパイプラインの入力BNF)
+/// パイプラインから決めた場合は、その他の設定も使えるようパイプラインも返す
+fn input_bnf_path(arg: Option<&String>, options: &GlobalOptions) -> (String, Option<Project>) {
+    match arg {
+        Some(path) => (path.clone(), None),
+        None => {
+            let project = require_project(options);
+            (project.pipeline.input.display().to_string(), Some(project))
+        }
+    }
+}
+
+/// 出力BNFのパス (省略されたらパイプラインの最初の出力BNF)
+fn output_bnf_path(arg: Option<&String>, project: Option<&Project>, options: &GlobalOptions) -> String {
+    if let Some(path) = arg {
+        return path.clone();
+    }
+    let loaded;
+    let project = match project {
+        Some(project) => project,
+        None => {
+            loaded = require_project(options);
+            &loaded
+        }
+    };
+    match project.pipeline.outputs.first() {
+        Some(target) => target.grammar.display().to_string(),
+        None => {
+            eprintln!("Error: pipeline '{}' in {} has no output grammar", project.name, CONFIG_FILE);
+            process::exit(EXIT_GRAMMAR_ERROR);
+        }
+    }
+}
+
+/// パイプラインの書き換えパスを読み込む (パイプラインを使わなければ空)
+fn load_passes(project: Option<&Project>, no_cache: bool, options: &GlobalOptions) -> Vec<RewritePass> {
+    let passes = project.map_or(&[][..], |project| &project.pipeline.passes);
+    let mut warnings = Vec::new();
+    let passes = rewrite::load_passes(passes, !no_cache, &mut warnings).unwrap_or_else(|e| exit_load_error(e));
+    for warning in &warnings {
+        options.info(format!("Warning: {}", warning));
+    }
+    passes
+}
+
+/// 文法を読み込めなかったら報告して終了する
+fn exit_load_error(e: LoadError) -> ! {
+    eprintln!("{}", e);
+    match e {
+        LoadError::Io { .. } => process::exit(EXIT_IO_ERROR),
+        LoadError::Syntax { .. } => process::exit(EXIT_GRAMMAR_ERROR),
+    }
+}
+
+/// 入力BNFを読み込んでパースする (誤りがあれば終了する)
+fn load_input_grammar(path: &str, options: &GlobalOptions) -> InputGrammar {
+    options.detail(format!("Input grammar: {}", path));
+    cache::load_input_grammar(Path::new(path)).unwrap_or_else(|e| exit_load_error(e))
+}
+
+/// 出力BNFを読み込んでパースする (誤りがあれば終了する)
+fn load_output_grammar(path: &str, options: &GlobalOptions) -> OutputGrammar {
+    options.detail(format!("Output grammar: {}", path));
+    cache::load_output_grammar(Path::new(path)).unwrap_or_else(|e| exit_load_error(e))
+}
+
+/// 入力・出力BNFを読み込んでコンパイルする
+/// no_cache でなければ、入力BNFと同じディレクトリの .cache を使う
+fn compile_grammars(input_bnf_path: &str, output_bnf_path: &str, no_cache: bool, options: &GlobalOptions) -> CompiledGrammars {
+    options.detail(format!("Input grammar: {}", input_bnf_path));
+    options.detail(format!("Output grammar: {}", output_bnf_path));
+    let compiled = CompiledGrammars::load(Path::new(input_bnf_path), Path::new(output_bnf_path), !no_cache).unwrap_or_else(|e| exit_load_error(e));
+    for warning in &compiled.warnings {
+        options.info(format!("Warning: {}", warning));
+    }
+    compiled
+}
+
+/// 変換するソースの指定
+enum SourceArg {
+    /// --file PATH または位置引数のパス
+    File(String),
+    /// --code CODE
+    Code(String),
+    /// - (標準入力)
+    Stdin,
+}
+
+/// `--code CODE` / `--file PATH` / 位置引数 (パスか `-`) からソースの指定を取り出す
+/// 位置引数を使った場合はそれも引数リストから取り除き、残りの位置引数を文法ファイルとする
+fn take_source(args: &mut Vec<String>) -> Option<SourceArg> {
+    let code = take_option(args, "--code");
+    let file = take_option(args, "--file");
+    match (code, file) {
+        (Some(_), Some(_)) => {
+            eprintln!("--code and --file cannot be used together");
+            process::exit(EXIT_USAGE);
+        }
+        (Some(code), None) => Some(SourceArg::Code(code)),
+        (None, Some(path)) => Some(SourceArg::File(path)),
+        (None, None) if args.len() > 2 => {
+            let arg = args.remove(2);
+            Some(if arg == "-" { SourceArg::Stdin } else { SourceArg::File(arg) })
+        }
+        (None, None) => None,
+    }
+}
+
+/// ソースを読み込み、内容と診断に使う名前を返す
+fn read_source(source: &SourceArg) -> (String, String) {
+    match source {
+        SourceArg::File(path) => {
+            let content = fs::read_to_string(path).unwrap_or_else(|e| {
+                eprintln!("Error reading source file {}: {}", path, e);
+                if e.kind() == io::ErrorKind::NotFound {
+                    eprintln!("Use --code to pass source code on the command line");
+                }
+                process::exit(EXIT_IO_ERROR);
+            });
+            (content, path.clone())
+        }
+        SourceArg::Code(code) => (code.clone(), "<inline>".to_string()),
+        SourceArg::Stdin => (read_stdin(), "<stdin>".to_string()),
+    }
+}
+
+/// 標準入力を最後まで読む
+fn read_stdin() -> String {
+    let mut buf = String::new();
+    io::stdin().read_to_string(&mut buf).unwrap_or_else(|e| {
+        eprintln!("Error reading stdin: {}", e);
+        process::exit(EXIT_IO_ERROR);
+    });
+    buf
+}
+
+/// ファイルを読み込む (失敗したら終了)
+fn read_file(path: &str) -> String {
+    fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Error reading {}: {}", path, e);
+        process::exit(EXIT_IO_ERROR);
+    })
+}
+
+/// 結果を -o のファイルか標準出力に書く
+fn write_output(path: Option<&str>, text: &str) {
+    match path {
+        Some(path) => fs::write(path, text).unwrap_or_else(|e| {
+            eprintln!("Error writing {}: {}", path, e);
+            process::exit(EXIT_IO_ERROR);
+        }),
+        None => print!("{}", text),
+    }
+}
+
+/// サブコマンドを省略した `hensan source.c` のソースに見えるか (既存のファイル、標準入力の -、オプション、拡張子やディレクトリを含むパス)
+fn looks_like_source(arg: &str) -> bool {
+    arg.starts_with('-') || arg.contains(['.', '/']) || Path::new(arg).exists()
+}
+
+/// オプションを取り出した残りの位置引数 (サブコマンド名より後ろ) が max 個を超えたら、使い方の誤りとして終了する
+fn reject_extra_args(args: &[String], max: usize) {
+    if let Some(extra) = args.get(2 + max) {
+        let kind = if extra.starts_with('-') && extra != "-" { "Unknown option" } else { "Unexpected argument" };
+        eprintln!("{}: {} (see `{} {} --help`)", kind, extra, args[0], args[1]);
+        process::exit(EXIT_USAGE);
+    }
+}
+
+/// `--name value` 形式のオプションを取り出して引数リストから取り除く
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().position(|a| a == name)?;
+    args.remove(index);
+    if index < args.len() {
+        Some(args.remove(index))
+    } else {
+        eprintln!("Missing value for {}", name);
+        process::exit(EXIT_USAGE);
+    }
+}
+
+/// `--flag` を取り出して引数リストから取り除く (指定されていれば true)
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let len = args.len();
+    args.retain(|a| a != name);
+    args.len() != len
+}
+
+/// `--name A,B` 形式のカンマ区切りのリストを取り出す
+fn take_list(args: &mut Vec<String>, name: &str) -> Vec<String> {
+    take_option(args, name)
+        .map(|list| list.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// `--source-ext EXTS` の拡張子を取り出す (先頭の '.' は除く)
+fn take_source_exts(args: &mut Vec<String>) -> Vec<String> {
+    take_list(args, "--source-ext").into_iter().map(|e| e.trim_start_matches('.').to_string()).collect()
+}
+
+/// `--name N` 形式の正の整数のオプションを取り出す
+fn take_count(args: &mut Vec<String>, name: &str) -> Option<usize> {
+    take_option(args, name).map(|v| {
+        v.parse::<usize>().ok().filter(|&n| n > 0).unwrap_or_else(|| {
+            eprintln!("Invalid {} value: {}", name, v);
+            process::exit(EXIT_USAGE);
+        })
+    })
+}
+
+/// パースの設定 (translate / parse / check に共通)
+struct ParseOptions {
+    /// --start RULE: 入力BNFの開始ルール以外のルールから断片をパースする
+    start_rule: Option<String>,
+    /// --recover RULES: カンマ区切りのルールでエラーから回復し、パースを続ける
+    recovery_rules: Vec<String>,
+    /// --trace[=PATH]: ルールの出入りとバックトラックを記録する
+    trace_path: Option<Option<String>>,
+    /// --error-format=human|json: パースエラーの書式
+    json_errors: bool,
+}
 
-    // 使用法の表示
-    if args.len() < 2 {
-        eprintln!("Usage: {} <source> [input.bnf] [output.bnf]", args[0]);
+impl ParseOptions {
+    fn take(args: &mut Vec<String>) -> Self {
+        ParseOptions {
+            start_rule: take_option(args, "--start"),
+            recovery_rules: take_list(args, "--recover"),
+            trace_path: take_flag_with_path(args, "--trace"),
+            json_errors: take_error_format(args),
+        }
+    }
+
+    /// 文法をパイプラインから決めたときは、その設定も使う (コマンドラインの指定が優先)
+    fn with_pipeline(mut self, project: Option<&Project>) -> Self {
+        if let Some(project) = project {
+            self.start_rule = self.start_rule.or_else(|| project.pipeline.start.clone());
+            if self.recovery_rules.is_empty() {
+                self.recovery_rules = project.pipeline.recover.clone();
+            }
+        }
+        self
+    }
+
+    /// 開始ルールと回復ルールが文法にあるか確かめる (なければ終了する)
+    fn check_rules(&self, grammar: &InputGrammar, grammar_path: &str) {
+        let start = self.start_rule.iter().map(|rule| ("start", rule));
+        let recovery = self.recovery_rules.iter().map(|rule| ("recovery", rule));
+        if let Some((kind, rule)) = start.chain(recovery).find(|(_, rule)| !grammar.rules.contains_key(*rule)) {
+            eprintln!("Unknown {} rule '{}' in {}", kind, rule, grammar_path);
+            process::exit(EXIT_USAGE);
+        }
+    }
+}
+
+/// ソースをパースし、回復したものも含めて全てのエラーを報告する
+/// AST (パースに失敗したら None) と、エラーがあったかを返す
+fn parse_source(
+    grammar: &InputGrammar,
+    source: &str,
+    source_name: &str,
+    parse_options: &ParseOptions,
+    options: &GlobalOptions,
+) -> (Option<ASTNode>, bool) {
+    let started = Instant::now();
+    let mut parser = Parser::new(grammar, source);
+    if let Some(rule) = &parse_options.start_rule {
+        parser = parser.with_start_rule(rule);
+    }
+    if !parse_options.recovery_rules.is_empty() {
+        parser = parser.with_recovery(&parse_options.recovery_rules);
+    }
+    if parse_options.trace_path.is_some() {
+        parser = parser.with_trace();
+    }
+    let result = parser.parse();
+    options.detail(format!("Parsed {} ({} bytes) in {:.1?}", source_name, source.len(), started.elapsed()));
+
+    for err in parser.errors() {
+        report_parse_error(err, grammar, source, source_name, parse_options.json_errors);
+    }
+    let recovered = !parser.errors().is_empty();
+
+    // パースに失敗しても、その理由を追えるようトレースは書き出す
+    if let Some(path) = &parse_options.trace_path {
+        let trace = match path {
+            Some(path) if path.ends_with(".html") => parser.trace_html(),
+            _ => parser.trace_log(),
+        };
+        write_trace(path.as_deref(), &trace);
+    }
+
+    match result {
+        Ok(ast) => (Some(ast), recovered),
+        Err(err) => {
+            report_parse_error(&err, grammar, source, source_name, parse_options.json_errors);
+            (None, true)
+        }
+    }
+}
+
+/// test サブコマンド: ケースを変換して期待ファイルと比べる
+fn run_test(mut args: Vec<String>, options: &GlobalOptions) {
+    let bless = take_flag(&mut args, "--bless");
+    let output_ext = take_option(&mut args, "--ext");
+    let source_exts = take_source_exts(&mut args);
+    let no_cache = take_flag(&mut args, "--no-cache");
+    let parse_options = ParseOptions {
+        start_rule: take_option(&mut args, "--start"),
+        recovery_rules: take_list(&mut args, "--recover"),
+        trace_path: None,
+        json_errors: false,
+    };
+
+    if options.help {
+        eprintln!("Usage: {} test [dir] [input.bnf] [output.bnf] [--bless] [--ext EXT] [--source-ext EXTS] [--start RULE] [--recover RULES] [--no-cache]", args[0]);
+        eprintln!();
+        eprintln!("Arguments:");
+        eprintln!("  dir          : Directory of test cases (default: `tests` of the {} pipeline)", CONFIG_FILE);
+        eprintln!("  input.bnf    : Input grammar file (default: the input grammar of the {} pipeline)", CONFIG_FILE);
+        eprintln!("  output.bnf   : Output grammar file (default: every output of the pipeline when dir is omitted)");
+        eprintln!("  --bless      : Write the actual results to the expected files instead of comparing them");
+        eprintln!("  --ext        : Extension of the expected outputs (default: the ext of the pipeline output, or the extension of the case)");
+        eprintln!("  --source-ext : Only test files with these comma-separated extensions (default: all files)");
+        eprintln!();
+        eprintln!("Each case.py is translated and compared with case.expected.rs (for --ext rs),");
+        eprintln!("or with case.expected.{} if it should fail to parse. Mismatches are shown as a unified diff.", golden::ERROR_EXT);
+        options.exit_after_usage();
+    }
+
+    // ケースのディレクトリと、(出力BNF, 期待ファイルの拡張子) の組
+    let dir_arg = args.get(2).filter(|arg| Path::new(arg).is_dir()).cloned();
+    let grammar_args = &args[if dir_arg.is_some() { 3 } else { 2 }..];
+    let (dir, input_bnf_path, project, targets) = match dir_arg {
+        Some(dir) => {
+            let (input_bnf_path, project) = input_bnf_path(grammar_args.first(), options);
+            let output_bnf_path = output_bnf_path(grammar_args.get(1), project.as_ref(), options);
+            let ext = output_ext.or_else(|| project.as_ref()?.pipeline.outputs.first()?.ext.clone());
+            (PathBuf::from(dir), input_bnf_path, project, vec![(output_bnf_path, ext)])
+        }
+        None => {
+            if let Some(arg) = grammar_args.first() {
+                eprintln!("Test directory not found: {}", arg);
+                process::exit(EXIT_USAGE);
+            }
+            let project = require_project(options);
+            if project.pipeline.outputs.is_empty() {
+                eprintln!("Error: pipeline '{}' in {} needs at least one [[output]] to test", project.name, CONFIG_FILE);
+                process::exit(EXIT_GRAMMAR_ERROR);
+            }
+            let dir = project.pipeline.tests.clone().unwrap_or_else(|| project.root.join("tests"));
+            let targets = project.pipeline.outputs.iter().map(|target| {
+                (target.grammar.display().to_string(), output_ext.clone().or_else(|| target.ext.clone()))
+            }).collect();
+            (dir, project.pipeline.input.display().to_string(), Some(project), targets)
+        }
+    };
+
+    let parse_options = parse_options.with_pipeline(project.as_ref());
+    let passes = load_passes(project.as_ref(), no_cache, options);
+    let compiled: Vec<_> = targets.iter().map(|(output_bnf_path, _)| compile_grammars(&input_bnf_path, output_bnf_path, no_cache, options)).collect();
+    parse_options.check_rules(&compiled[0].input, &input_bnf_path);
+
+    let golden_targets = compiled.iter().zip(&targets).map(|(grammars, (_, ext))| GoldenTarget { grammar: &grammars.output, ext: ext.clone() }).collect();
+    let tester = GoldenTester::new(&compiled[0].input, golden_targets)
+        .with_start_rule(parse_options.start_rule.as_deref())
+        .with_recovery(&parse_options.recovery_rules)
+        .with_passes(&passes)
+        .with_bless(bless);
+    let results = tester.run_dir(&dir, &source_exts).unwrap_or_else(|e| {
+        eprintln!("Error reading test cases in {}: {}", dir.display(), e);
+        process::exit(EXIT_IO_ERROR);
+    });
+
+    let mut updated = 0;
+    for result in &results {
+        for check in &result.checks {
+            match &check.outcome {
+                Outcome::Passed => options.detail(format!("ok {}", check.expected.display())),
+                Outcome::Blessed => {
+                    options.info(format!("Updated {}", check.expected.display()));
+                    updated += 1;
+                }
+                Outcome::Failed(message) => {
+                    eprintln!("FAIL {}", result.path.display());
+                    eprint!("{}", message);
+                    if !message.ends_with('\n') {
+                        eprintln!();
+                    }
+                }
+            }
+        }
+    }
+    let failed = results.iter().filter(|result| result.failed()).count();
+    let plural = if results.len() == 1 { "" } else { "s" };
+    let mut summary = format!("Tested {} case{} ({} failed", results.len(), plural, failed);
+    if bless {
+        summary.push_str(&format!(", {} updated", updated));
+    }
+    options.info(format!("{})", summary));
+
+    if failed > 0 {
+        process::exit(EXIT_TEST_FAILED);
+    }
+}
+
+/// init サブコマンド: 同梱のテンプレートから hensan.toml と文法と例のソースを作る
+fn run_init(mut args: Vec<String>, options: &GlobalOptions) {
+    if options.help {
+        eprintln!("Usage: {} init [dir] [--from LANG] [--to LANG] [--list]", args[0]);
         eprintln!();
         eprintln!("Arguments:");
-        eprintln!("  source       : Source file path or inline code (required)");
-        eprintln!("  input.bnf    : Input grammar file (default: Grammar/input.bnf)");
-        eprintln!("  output.bnf   : Output grammar file (default: Grammar/output.bnf)");
+        eprintln!("  dir          : Project directory to create {} in (default: current directory)", CONFIG_FILE);
+        eprintln!("  --from       : Language of the sources (default: {})", templates::DEFAULT_SOURCE);
+        eprintln!("  --to         : Language to translate into (default: the first template for --from)");
+        eprintln!("  --list       : Print the available templates and exit");
         eprintln!();
-        eprintln!("Examples:");
-        eprintln!("  # Inline source code");
-        eprintln!("  {} 'int my_func(int a, float b);'", args[0]);
+        eprintln!("Creates Grammar/input.bnf and Grammar/output.bnf (ignoring their grammar cache), an example source in src/,");
+        eprintln!("and the same example with its expected output in tests/.");
+        options.exit_after_usage();
+    }
+
+    if take_flag(&mut args, "--list") {
+        print!("{}", templates::list());
+        return;
+    }
+    let from = take_option(&mut args, "--from");
+    let to = take_option(&mut args, "--to");
+    let template = templates::find(from.as_deref().unwrap_or(templates::DEFAULT_SOURCE), to.as_deref()).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        eprintln!("Run `{} init --list` to see the available templates", args[0]);
+        process::exit(EXIT_USAGE);
+    });
+
+    let dir = PathBuf::from(args.get(2).map_or("", |s| s.as_str()));
+    let created = config::init_project(&dir, &template).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(EXIT_IO_ERROR);
+    });
+    for path in created {
+        options.info(format!("Created {}", path.display()));
+    }
+}
+
+/// translate のオプション
+struct TranslateOptions {
+    parse: ParseOptions,
+    /// -o PATH: 変換結果を書き出すファイル (ディレクトリを変換するときは --out-dir と同じ)
+    output_path: Option<String>,
+    /// --out-dir DIR: ディレクトリを変換するときの出力先
+    out_dir: Option<String>,
+    /// --ext EXT: 出力ファイルの拡張子
+    output_ext: Option<String>,
+    /// --source-ext EXTS: ディレクトリから変換するファイルの拡張子
+    source_exts: Vec<String>,
+    /// --jobs N: 並列に変換するファイル数
+    jobs: Option<usize>,
+    /// --unparse: 出力BNFを使わず入力BNFでソースを再生成する
+    unparse: bool,
+    /// --dump-ast=json|sexp: パース結果のASTを標準エラー出力に書き出す
+    dump_ast: Option<AstFormat>,
+    /// --trace-output[=PATH]: 出力の各区間を生成した出力ルールと AST ノードを記録する
+    trace_output_path: Option<Option<String>>,
+    /// --source-map PATH: 生成コードから入力ソースへのソースマップを書き出す
+    source_map_path: Option<String>,
+    /// --no-cache: コンパイル済み文法のキャッシュを使わない
+    no_cache: bool,
+}
+
+/// translate サブコマンド: ソース1つか、ディレクトリやパイプラインのソースをまとめて変換する
+fn run_translate(mut args: Vec<String>, options: &GlobalOptions) {
+    let opts = TranslateOptions {
+        output_path: take_option(&mut args, "-o"),
+        out_dir: take_option(&mut args, "--out-dir"),
+        output_ext: take_option(&mut args, "--ext"),
+        source_exts: take_source_exts(&mut args),
+        jobs: take_count(&mut args, "--jobs"),
+        unparse: take_flag(&mut args, "--unparse"),
+        dump_ast: take_dump_ast(&mut args),
+        trace_output_path: take_flag_with_path(&mut args, "--trace-output"),
+        source_map_path: take_option(&mut args, "--source-map"),
+        no_cache: take_flag(&mut args, "--no-cache"),
+        parse: ParseOptions::take(&mut args),
+    };
+
+    if options.help {
+        eprintln!("Usage: {} translate [<source> | --file PATH | --code CODE | -] [input.bnf] [output.bnf] [options]", args[0]);
+        eprintln!("       {} translate [src_dir] [input.bnf] [output.bnf] --out-dir DIR [options]", args[0]);
         eprintln!();
-        eprintln!("  # From file");
-        eprintln!("  {} source.c", args[0]);
+        eprintln!("Arguments:");
+        eprintln!("  source       : Source file to translate, or '-' to read stdin");
+        eprintln!("  --file       : Source file to translate (for file names that start with '-')");
+        eprintln!("  --code       : Source code given on the command line");
+        eprintln!("  src_dir      : Directory of source files to translate (default: the sources of the hensan.toml pipeline)");
+        eprintln!("  input.bnf    : Input grammar file (default: the input grammar of the hensan.toml pipeline)");
+        eprintln!("  output.bnf   : Output grammar file (default: the first output grammar of the hensan.toml pipeline)");
         eprintln!();
-        eprintln!("  # With custom grammar files");
-        eprintln!("  {} source.c Grammar/custom_in.bnf Grammar/custom_out.bnf", args[0]);
-        process::exit(1);
+        eprintln!("Options:");
+        eprintln!("  -o           : Write the output to a file instead of stdout (the output directory for src_dir)");
+        eprintln!("  --out-dir    : Directory to write the outputs into, mirroring src_dir");
+        eprintln!("  --ext        : Extension of the output files (default: keep the source file name)");
+        eprintln!("  --source-ext : Only translate files with these comma-separated extensions (default: all files)");
+        eprintln!("  --jobs       : Number of files translated in parallel (default: number of CPU cores)");
+        eprintln!("  --start      : Parse the source as a fragment starting at RULE (default: @start or the first rule)");
+        eprintln!("  --recover    : On a parse error in one of the comma-separated RULES, skip to the next line and continue");
+        eprintln!("               (the skipped text is marked in the output, or emitted by the `error` rule of output.bnf)");
+        eprintln!("  --trace      : Print a parse trace to stderr, or write it to PATH (HTML if PATH ends in .html)");
+        eprintln!("  --trace-output : Print which output rule and AST node produced each output span");
+        eprintln!("  --source-map : Write a Source Map v3 from the output to the source to PATH (a line table if PATH ends in .txt)");
+        eprintln!("  --error-format : Print parse errors for humans (default) or as JSON lines for editors");
+        eprintln!("  --unparse    : Regenerate the source with input.bnf (output.bnf is ignored)");
+        eprintln!("  --dump-ast   : Print the AST to stderr as JSON or an S-expression");
+        eprintln!("  --no-cache   : Do not read or write the compiled grammar cache (.cache next to input.bnf)");
+        options.exit_after_usage();
     }
 
-    // Grammarディレクトリとファイルの確認・作成
-    ensure_grammar_files();
+    let source = take_source(&mut args);
+    reject_extra_args(&args, 2);
+    let grammar_args = &args[2.min(args.len())..];
+    match source {
+        Some(SourceArg::File(path)) if Path::new(&path).is_dir() => translate_batch(Some(path), grammar_args, opts, options),
+        Some(source) => translate_source(&source, grammar_args, opts, options),
+        None => translate_batch(None, grammar_args, opts, options),
+    }
+}
 
-    let source_arg = &args[1];
+/// ソース1つを変換して -o のファイルか標準出力に書く
+fn translate_source(source: &SourceArg, grammar_args: &[String], opts: TranslateOptions, options: &GlobalOptions) {
+    let (input_bnf_path, project) = input_bnf_path(grammar_args.first(), options);
+    let parse_options = opts.parse.with_pipeline(project.as_ref());
 
-    // ソースコードの取得（ファイルパスならファイルを読み込む）
-    let (source, source_name) = if Path::new(source_arg).exists() {
-        let content = fs::read_to_string(source_arg).unwrap_or_else(|e| {
-            eprintln!("Error reading source file {}: {}", source_arg, e);
-            process::exit(1);
-        });
-        (content, source_arg.to_string())
+    // Step 1: 入力・出力BNFをパース (キャッシュがあればそれを使う)
+    let (input_grammar, output_grammar) = if opts.unparse {
+        // 逆パースでは出力BNFを使わない
+        (load_input_grammar(&input_bnf_path, options), None)
     } else {
-        (source_arg.to_string(), "<inline>".to_string())
+        let output_bnf_path = output_bnf_path(grammar_args.get(1), project.as_ref(), options);
+        let compiled = compile_grammars(&input_bnf_path, &output_bnf_path, opts.no_cache, options);
+        (compiled.input, Some(compiled.output))
     };
+    parse_options.check_rules(&input_grammar, &input_bnf_path);
 
-    // BNFファイルパスの決定
-    let input_bnf_path = args.get(2)
-        .map(|s| s.to_string())
-        .unwrap_or_else(|| format!("{}/{}", GRAMMAR_DIR, DEFAULT_INPUT_BNF));
+    // Step 2: ソースコードをパースしてAST生成
+    let (source, source_name) = read_source(source);
+    let (ast, recovered) = parse_source(&input_grammar, &source, &source_name, &parse_options, options);
+    let Some(ast) = ast else {
+        process::exit(EXIT_PARSE_ERROR);
+    };
 
-    let output_bnf_path = args.get(3)
-        .map(|s| s.to_string())
-        .unwrap_or_else(|| format!("{}/{}", GRAMMAR_DIR, DEFAULT_OUTPUT_BNF));
+    match opts.dump_ast {
+        Some(AstFormat::Json) => eprintln!("{}", ast.to_json()),
+        Some(AstFormat::Sexp) => eprintln!("{}", ast.to_sexp()),
+        None => {}
+    }
+
+    let output = match output_grammar {
+        // 逆パース: 入力BNFだけでソースを再生成
+        None => Unparser::new(&input_grammar).unparse(&ast).unwrap_or_else(|e| {
+            eprintln!("Error in {}: {}", input_bnf_path, e);
+            process::exit(EXIT_GRAMMAR_ERROR);
+        }),
+        Some(output_grammar) => {
+            // パイプラインの書き換えパスを順に適用する
+            let ast = rewrite::apply_passes(&load_passes(project.as_ref(), opts.no_cache, options), ast).unwrap_or_else(|diagnostic| {
+                eprintln!("{}", diagnostic);
+                process::exit(EXIT_GRAMMAR_ERROR);
+            });
+
+            // Step 3: ASTから出力コード生成
+            let gen = Generator::new(&output_grammar);
+            let output = if opts.trace_output_path.is_some() || opts.source_map_path.is_some() {
+                let (output, trace) = gen.generate_traced(&ast);
+                if let Some(path) = &opts.trace_output_path {
+                    write_trace(path.as_deref(), &trace.to_text(&output));
+                }
+                if let Some(path) = &opts.source_map_path {
+                    let map = SourceMap::new(&output, &source, &trace);
+                    let text = if path.ends_with(".txt") {
+                        map.to_line_table()
+                    } else {
+                        // "out.rs.map" は "out.rs" のソースマップ
+                        let file = path.strip_suffix(".map").unwrap_or("");
+                        map.to_json(file, &source_name, &source)
+                    };
+                    write_trace(Some(path), &text);
+                }
+                output
+            } else {
+                gen.generate(&ast)
+            };
+            format!("{}\n", output)
+        }
+    };
+    write_output(opts.output_path.as_deref(), &output);
+
+    // エラーを読み飛ばして生成した出力は、失敗として終了する
+    if recovered {
+        process::exit(EXIT_PARSE_ERROR);
+    }
+}
+
+/// ディレクトリ (src_dir) か、hensan.toml のパイプラインの sources をまとめて並列に変換する
+/// パイプラインのソースは全ての出力BNFで変換する
+fn translate_batch(src_dir: Option<String>, grammar_args: &[String], opts: TranslateOptions, options: &GlobalOptions) {
+    // 1ファイルの AST や出力の区間を書き出すオプションは、まとめて変換するときには使えない
+    let single_file_options = [
+        ("--trace", opts.parse.trace_path.is_some()),
+        ("--dump-ast", opts.dump_ast.is_some()),
+        ("--unparse", opts.unparse),
+        ("--trace-output", opts.trace_output_path.is_some()),
+        ("--source-map", opts.source_map_path.is_some()),
+    ];
+    if let Some((option, _)) = single_file_options.iter().find(|(_, given)| *given) {
+        eprintln!("{} can only be used when translating a single source, not a directory or a pipeline", option);
+        process::exit(EXIT_USAGE);
+    }
+
+    let out_dir = opts.out_dir.or(opts.output_path);
+
+    // 変換するファイル (ディレクトリとそこからの相対パス) と、(出力BNF, 拡張子, 出力先) の組
+    let (input_bnf_path, project, files, targets) = match src_dir {
+        Some(src_dir) => {
+            let Some(out_dir) = out_dir.map(PathBuf::from) else {
+                eprintln!("Missing --out-dir for translating the directory {}", src_dir);
+                process::exit(EXIT_USAGE);
+            };
+            let (input_bnf_path, project) = input_bnf_path(grammar_args.first(), options);
+            let output_bnf_path = output_bnf_path(grammar_args.get(1), project.as_ref(), options);
+            let paths = batch::source_files(Path::new(&src_dir), &out_dir, &opts.source_exts).unwrap_or_else(|e| {
+                eprintln!("Error reading {}: {}", src_dir, e);
+                process::exit(EXIT_IO_ERROR);
+            });
+            let files: Vec<_> = paths.into_iter().map(|path| (PathBuf::from(&src_dir), path)).collect();
+            let ext = opts.output_ext.or_else(|| project.as_ref()?.pipeline.outputs.first()?.ext.clone());
+            (input_bnf_path, project, files, vec![(output_bnf_path, ext, out_dir)])
+        }
+        None => {
+            let project = require_project(options);
+            if project.pipeline.sources.is_empty() || project.pipeline.outputs.is_empty() {
+                eprintln!("Error: pipeline '{}' in {} needs `sources` and at least one [[output]] to translate", project.name, CONFIG_FILE);
+                process::exit(EXIT_GRAMMAR_ERROR);
+            }
+            let files = project.pipeline.source_files(&project.root).unwrap_or_else(|e| {
+                eprintln!("Error reading sources of pipeline '{}': {}", project.name, e);
+                process::exit(EXIT_IO_ERROR);
+            });
+            let targets = project.pipeline.outputs.iter().map(|target| {
+                let dir = out_dir.as_ref().map(PathBuf::from).or_else(|| target.out_dir.clone()).or_else(|| project.pipeline.out_dir.clone());
+                let Some(dir) = dir else {
+                    eprintln!("Error: no out_dir for output {} in {} (set it or pass --out-dir)", target.grammar.display(), CONFIG_FILE);
+                    process::exit(EXIT_GRAMMAR_ERROR);
+                };
+                (target.grammar.display().to_string(), opts.output_ext.clone().or_else(|| target.ext.clone()), dir)
+            }).collect();
+            (project.pipeline.input.display().to_string(), Some(project), files, targets)
+        }
+    };
+
+    // 文法をパイプラインから決めたときは、その設定も使う (コマンドラインの指定が優先)
+    let parse_options = opts.parse.with_pipeline(project.as_ref());
+    let passes = load_passes(project.as_ref(), opts.no_cache, options);
+    let jobs = opts.jobs.or_else(|| project.as_ref()?.pipeline.jobs);
+
+    let mut exit_code = 0;
+    for (index, (output_bnf_path, ext, out_dir)) in targets.iter().enumerate() {
+        let compiled = compile_grammars(&input_bnf_path, output_bnf_path, opts.no_cache, options);
+        parse_options.check_rules(&compiled.input, &input_bnf_path);
+
+        let mut translator = BatchTranslator::new(&compiled.input, &compiled.output)
+            .with_start_rule(parse_options.start_rule.as_deref())
+            .with_recovery(&parse_options.recovery_rules)
+            .with_passes(&passes);
+        if let Some(ext) = ext {
+            translator = translator.with_output_ext(ext);
+        }
+        if let Some(jobs) = jobs {
+            translator = translator.with_jobs(jobs);
+        }
+        let report = translator.translate_files(&files, out_dir);
 
-    // BNFファイルの読み込み
-    let input_bnf = fs::read_to_string(&input_bnf_path).unwrap_or_else(|e| {
-        eprintln!("Error reading {}: {}", input_bnf_path, e);
-        process::exit(1);
+        for file in &report.files {
+            let source_name = file.path.display().to_string();
+            // パースと書き換えの失敗は出力BNFによらないので、最初の出力でだけ報告する
+            match &file.failure {
+                None => {
+                    if let Some(output) = &file.output {
+                        options.detail(format!("{} -> {}", source_name, output.display()));
+                    }
+                }
+                Some(Failure::Io(message)) => {
+                    eprintln!("{}", message);
+                    exit_code = exit_code.max(EXIT_IO_ERROR);
+                }
+                Some(Failure::Parse { source, errors }) => {
+                    if index == 0 {
+                        for err in errors {
+                            report_parse_error(err, &compiled.input, source, &source_name, parse_options.json_errors);
+                        }
+                    }
+                    exit_code = exit_code.max(EXIT_PARSE_ERROR);
+                }
+                Some(Failure::Pass(diagnostic)) => {
+                    if index == 0 {
+                        eprintln!("{}", diagnostic);
+                    }
+                    exit_code = exit_code.max(EXIT_GRAMMAR_ERROR);
+                }
+            }
+        }
+        if targets.len() > 1 {
+            options.info(format!("{} -> {}", report.summary(), out_dir.display()));
+        } else {
+            options.info(report.summary());
+        }
+    }
+
+    if exit_code != 0 {
+        process::exit(exit_code);
+    }
+}
+
+/// parse サブコマンド: ソースをパースして AST を書き出す
+fn run_parse(mut args: Vec<String>, options: &GlobalOptions) {
+    let output_path = take_option(&mut args, "-o");
+    let format = take_option(&mut args, "--format").map(|v| {
+        AstFormat::from_name(&v).unwrap_or_else(|| {
+            eprintln!("Invalid --format value: {} (expected json or sexp)", v);
+            process::exit(EXIT_USAGE);
+        })
     });
+    let parse_options = ParseOptions::take(&mut args);
+    let source = take_source(&mut args);
+    let (Some(source), false) = (source, options.help) else {
+        eprintln!("Usage: {} parse [<source> | --file PATH | --code CODE | -] [input.bnf] [--format sexp|json] [-o PATH] [--start RULE] [--recover RULES] [--trace[=PATH]] [--error-format=human|json]", args[0]);
+        eprintln!();
+        eprintln!("Arguments:");
+        eprintln!("  source       : Source file to parse, or '-' to read stdin (required)");
+        eprintln!("  input.bnf    : Input grammar file (default: the input grammar of the hensan.toml pipeline)");
+        eprintln!("  --format     : Print the AST as an S-expression (default) or JSON");
+        eprintln!("  -o           : Write the AST to a file instead of stdout");
+        options.exit_after_usage();
+    };
+    reject_extra_args(&args, 1);
+
+    let (input_bnf_path, project) = input_bnf_path(args.get(2), options);
+    let parse_options = parse_options.with_pipeline(project.as_ref());
+    let input_grammar = load_input_grammar(&input_bnf_path, options);
+    parse_options.check_rules(&input_grammar, &input_bnf_path);
+
+    let (source, source_name) = read_source(&source);
+    let (ast, recovered) = parse_source(&input_grammar, &source, &source_name, &parse_options, options);
+    let Some(ast) = ast else {
+        process::exit(EXIT_PARSE_ERROR);
+    };
+    let text = match format.unwrap_or(AstFormat::Sexp) {
+        AstFormat::Json => ast.to_json(),
+        AstFormat::Sexp => ast.to_sexp(),
+    };
+    write_output(output_path.as_deref(), &format!("{}\n", text));
+
+    if recovered {
+        process::exit(EXIT_PARSE_ERROR);
+    }
+}
+
+/// check サブコマンド: ソースをパースしてエラーだけを報告する
+/// ディレクトリか、省略したら hensan.toml のパイプラインの sources を全て調べる
+fn run_check(mut args: Vec<String>, options: &GlobalOptions) {
+    let source_exts = take_source_exts(&mut args);
+    let parse_options = ParseOptions::take(&mut args);
+
+    if options.help {
+        eprintln!("Usage: {} check [<source> | src_dir | --file PATH | --code CODE | -] [input.bnf] [--source-ext EXTS] [--start RULE] [--recover RULES] [--error-format=human|json]", args[0]);
+        eprintln!();
+        eprintln!("Arguments:");
+        eprintln!("  source       : Source file to check, or '-' to read stdin");
+        eprintln!("  src_dir      : Directory of source files to check (default: the sources of the hensan.toml pipeline)");
+        eprintln!("  input.bnf    : Input grammar file (default: the input grammar of the hensan.toml pipeline)");
+        eprintln!("  --source-ext : Only check files with these comma-separated extensions in src_dir (default: all files)");
+        options.exit_after_usage();
+    }
+
+    let source = take_source(&mut args);
+    reject_extra_args(&args, 1);
+    let (input_bnf_path, project) = input_bnf_path(args.get(2), options);
+    let parse_options = parse_options.with_pipeline(project.as_ref());
+    let input_grammar = load_input_grammar(&input_bnf_path, options);
+    parse_options.check_rules(&input_grammar, &input_bnf_path);
+
+    // 調べるソースと、ファイルをまとめて調べたか
+    let (sources, many) = match source {
+        Some(SourceArg::File(dir)) if Path::new(&dir).is_dir() => {
+            let paths = batch::source_files(Path::new(&dir), Path::new(""), &source_exts).unwrap_or_else(|e| {
+                eprintln!("Error reading {}: {}", dir, e);
+                process::exit(EXIT_IO_ERROR);
+            });
+            let sources = paths.iter().map(|path| SourceArg::File(Path::new(&dir).join(path).display().to_string())).collect();
+            (sources, true)
+        }
+        Some(source) => (vec![source], false),
+        None => {
+            let loaded;
+            let project = match &project {
+                Some(project) => project,
+                None => {
+                    loaded = require_project(options);
+                    &loaded
+                }
+            };
+            let files = project.pipeline.source_files(&project.root).unwrap_or_else(|e| {
+                eprintln!("Error reading sources of pipeline '{}': {}", project.name, e);
+                process::exit(EXIT_IO_ERROR);
+            });
+            let sources = files.iter().map(|(dir, path)| SourceArg::File(dir.join(path).display().to_string())).collect();
+            (sources, true)
+        }
+    };
+
+    let mut exit_code = 0;
+    let mut failed = 0;
+    for source in &sources {
+        // 読めないファイルは報告して次へ進む
+        if let SourceArg::File(path) = source {
+            if let Err(e) = fs::metadata(path) {
+                eprintln!("Error reading source file {}: {}", path, e);
+                exit_code = exit_code.max(EXIT_IO_ERROR);
+                failed += 1;
+                continue;
+            }
+        }
+        let (text, source_name) = read_source(source);
+        match parse_source(&input_grammar, &text, &source_name, &parse_options, options) {
+            (Some(_), false) => options.detail(format!("ok {}", source_name)),
+            _ => {
+                exit_code = exit_code.max(EXIT_PARSE_ERROR);
+                failed += 1;
+            }
+        }
+    }
+    if many {
+        let plural = if sources.len() == 1 { "" } else { "s" };
+        options.info(format!("Checked {} file{} ({} failed)", sources.len(), plural, failed));
+    }
+
+    if exit_code != 0 {
+        process::exit(exit_code);
+    }
+}
+
+/// fmt サブコマンド: 入力BNFでパースしてソースを正規化されたスタイルで再出力する
+fn run_fmt(mut args: Vec<String>, options: &GlobalOptions) {
+    let indent = take_count(&mut args, "--indent");
+    let comment = take_option(&mut args, "--comment");
+    let output_path = take_option(&mut args, "-o");
+    let json_errors = take_error_format(&mut args);
+    let source = take_source(&mut args);
+    let (Some(source), false) = (source, options.help) else {
+        eprintln!("Usage: {} fmt [<source> | --file PATH | --code CODE | -] [input.bnf] [-o PATH] [--indent N] [--comment MARKER] [--error-format=human|json]", args[0]);
+        eprintln!();
+        eprintln!("Arguments:");
+        eprintln!("  source       : Source file to format, or '-' to read stdin (required)");
+        eprintln!("  input.bnf    : Input grammar file (default: the input grammar of the hensan.toml pipeline)");
+        eprintln!("  -o           : Write the formatted source to a file instead of stdout");
+        eprintln!("  --indent     : Spaces per indentation level (default: 4)");
+        eprintln!("  --comment    : Line comment marker to preserve (default: #)");
+        eprintln!("  --error-format : Print parse errors for humans (default) or as JSON lines for editors");
+        options.exit_after_usage();
+    };
+    reject_extra_args(&args, 1);
+
+    let (source, source_name) = read_source(&source);
+    let (input_bnf_path, _) = input_bnf_path(args.get(2), options);
+    let input_grammar = load_input_grammar(&input_bnf_path, options);
+
+    let mut formatter = Formatter::new(&input_grammar);
+    if let Some(width) = indent {
+        formatter = formatter.with_indent_width(width);
+    }
+    if let Some(marker) = &comment {
+        formatter = formatter.with_comment_marker(marker);
+    }
 
-    let output_bnf = fs::read_to_string(&output_bnf_path).unwrap_or_else(|e| {
-        eprintln!("Error reading {}: {}", output_bnf_path, e);
-        process::exit(1);
+    match formatter.format(&source) {
+        Ok(formatted) => write_output(output_path.as_deref(), &formatted),
+        Err(FormatError::Parse(err)) => {
+            report_parse_error(&err, &input_grammar, &source, &source_name, json_errors);
+            process::exit(EXIT_PARSE_ERROR);
+        }
+        Err(FormatError::Unparse(e)) => {
+            eprintln!("Error in {}: {}", input_bnf_path, e);
+            process::exit(EXIT_GRAMMAR_ERROR);
+        }
+    }
+}
+
+/// fmt-grammar サブコマンド: 入力・出力BNFを正規の形に整形する
+fn run_fmt_grammar(mut args: Vec<String>, options: &GlobalOptions) {
+    let kind = take_option(&mut args, "--kind").map(|v| match v.as_str() {
+        "input" => GrammarKind::Input,
+        "output" => GrammarKind::Output,
+        _ => {
+            eprintln!("Invalid --kind value: {} (expected input or output)", v);
+            process::exit(EXIT_USAGE);
+        }
     });
+    let width = take_count(&mut args, "--width");
+    let write = take_flag(&mut args, "--write");
 
-    // Step 1: 入力BNFをパース
-    let mut input_meta_parser = MetaParser::new(&input_bnf);
-    let input_grammar = input_meta_parser.parse_input_grammar();
+    if args.len() < 3 || options.help {
+        eprintln!("Usage: {} fmt-grammar <grammar.bnf>... [--kind input|output] [--width N] [--write]", args[0]);
+        eprintln!();
+        eprintln!("Arguments:");
+        eprintln!("  grammar.bnf  : Grammar files to format (required)");
+        eprintln!("  --kind       : Grammar kind (default: from the hensan.toml pipelines, else output if the file name contains \"output\" or an input.bnf is next to it)");
+        eprintln!("  --width      : Wrap alternatives of rules longer than N columns (default: 80)");
+        eprintln!("  --write      : Rewrite the files in place instead of printing them");
+        options.exit_after_usage();
+    }
 
-    // Step 2: 出力BNFをパース
-    let mut output_meta_parser = MetaParser::new(&output_bnf);
-    let output_grammar = output_meta_parser.parse_output_grammar();
+    for path in &args[2..] {
+        let source = read_file(path);
+        // 種類は hensan.toml のパイプラインから決め、設定がなければパスから推測する
+        let kind = kind.unwrap_or_else(|| match config::find_grammar_role(Path::new(path)) {
+            Some(role) => role.kind,
+            None => GrammarKind::from_path(Path::new(path)),
+        });
+        let mut formatter = GrammarFormatter::new(kind);
+        if let Some(width) = width {
+            formatter = formatter.with_max_width(width);
+        }
 
-    // Step 3: ソースコードをパースしてAST生成
-    let mut source_parser = Parser::new(&input_grammar, &source);
-    let ast = match source_parser.parse() {
-        Ok(ast) => ast,
-        Err(err) => {
-            eprintln!("Error in {}:", source_name);
-            eprintln!("{}", err);
-            process::exit(1);
+        let formatted = formatter.format(&source).unwrap_or_else(|e| {
+            eprintln!("Error in {}: {}", path, e);
+            process::exit(EXIT_GRAMMAR_ERROR);
+        });
+
+        if !write {
+            print!("{}", formatted);
+        } else if formatted != source {
+            fs::write(path, formatted).unwrap_or_else(|e| {
+                eprintln!("Error writing {}: {}", path, e);
+                process::exit(EXIT_IO_ERROR);
+            });
+            options.info(format!("Formatted {}", path));
         }
+    }
+}
+
+/// `--error-format=human|json` を取り出して引数リストから取り除く (json なら true)
+fn take_error_format(args: &mut Vec<String>) -> bool {
+    let Some(index) = args.iter().position(|a| a.starts_with("--error-format")) else { return false };
+    let arg = args.remove(index);
+    match arg.as_str() {
+        "--error-format=human" => false,
+        "--error-format=json" => true,
+        _ => {
+            eprintln!("Invalid {}: expected --error-format=human or --error-format=json", arg);
+            process::exit(EXIT_USAGE);
+        }
+    }
+}
+
+/// パースエラーを rustc 風の診断 (json なら1行の JSON) として標準エラー出力に書く
+/// 端末に書く場合だけ色を付ける (NO_COLOR が設定されていれば付けない)
+fn report_parse_error(err: &ParseError, grammar: &InputGrammar, source: &str, source_name: &str, json: bool) {
+    let diagnostic = Diagnostic::from_parse_error(err, grammar, source);
+    let renderer = Renderer::new(source_name, source)
+        .with_color(!json && io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none());
+    if json {
+        eprintln!("{}", renderer.render_json(&diagnostic));
+    } else {
+        eprintln!("{}", renderer.render(&diagnostic));
+    }
+}
+
+/// `--flag` / `--flag=PATH` 形式のオプションを取り出して引数リストから取り除く
+/// (指定がなければ None、パスなしなら Some(None))
+fn take_flag_with_path(args: &mut Vec<String>, flag: &str) -> Option<Option<String>> {
+    let prefix = format!("{}=", flag);
+    let index = args.iter().position(|a| a == flag || a.starts_with(&prefix))?;
+    let arg = args.remove(index);
+    Some(arg.strip_prefix(&prefix).map(|path| path.to_string()))
+}
+
+/// トレースを書き出す (パスがなければ標準エラー出力へ)
+fn write_trace(path: Option<&str>, trace: &str) {
+    match path {
+        Some(path) => fs::write(path, trace).unwrap_or_else(|e| {
+            eprintln!("Error writing {}: {}", path, e);
+            process::exit(EXIT_IO_ERROR);
+        }),
+        None => eprint!("{}", trace),
+    }
+}
+
+/// AST の出力形式 (--dump-ast / parse --format)
+#[derive(Clone, Copy)]
+enum AstFormat {
+    Json,
+    Sexp,
+}
+
+impl AstFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(AstFormat::Json),
+            "sexp" => Some(AstFormat::Sexp),
+            _ => None,
+        }
+    }
+}
+
+/// `--dump-ast=FORMAT` を取り出して引数リストから取り除く
+fn take_dump_ast(args: &mut Vec<String>) -> Option<AstFormat> {
+    let index = args.iter().position(|a| a.starts_with("--dump-ast"))?;
+    let arg = args.remove(index);
+    if arg == "--dump-ast" {
+        return Some(AstFormat::Json);
+    }
+    let format = arg.strip_prefix("--dump-ast=").and_then(AstFormat::from_name);
+    if format.is_none() {
+        eprintln!("Invalid {} (expected --dump-ast=json or --dump-ast=sexp)", arg);
+        process::exit(EXIT_USAGE);
+    }
+    format
+}
+
+/// generate サブコマンド: JSON形式のASTを読み込んで出力BNFでコードを生成する
+fn run_generate(mut args: Vec<String>, options: &GlobalOptions) {
+    let output_path = take_option(&mut args, "-o");
+
+    let (Some(source), false) = (take_source(&mut args), options.help) else {
+        eprintln!("Usage: {} generate [<ast.json> | --file PATH | --code JSON | -] [output.bnf] [-o PATH]", args[0]);
+        eprintln!();
+        eprintln!("Arguments:");
+        eprintln!("  ast.json     : AST in JSON format (as written by `parse --format json`, '-' for stdin)");
+        eprintln!("  output.bnf   : Output grammar file (default: the first output grammar of the hensan.toml pipeline)");
+        eprintln!("  -o           : Write the output to a file instead of stdout");
+        options.exit_after_usage();
     };
+    reject_extra_args(&args, 1);
 
-    // デバッグ: ASTを出力
-    eprintln!("AST: {:#?}", ast);
+    let (json, source_name) = read_source(&source);
+    let ast = ASTNode::from_json(&json).unwrap_or_else(|e| {
+        eprintln!("Error parsing AST JSON {}: {}", source_name, e);
+        process::exit(EXIT_PARSE_ERROR);
+    });
 
-    // Step 4: ASTから出力コード生成
-    let gen = Generator::new(&output_grammar);
-    let output = gen.generate(&ast);
+    let output_bnf_path = output_bnf_path(args.get(2), None, options);
+    let output_grammar = load_output_grammar(&output_bnf_path, options);
 
-    println!("{}", output);
+    write_output(output_path.as_deref(), &format!("{}\n", Generator::new(&output_grammar).generate(&ast)));
+}
+
+/// export サブコマンド: 入力BNFを他のパーサー形式に変換する
+fn run_export(mut args: Vec<String>, options: &GlobalOptions) {
+    let name = take_option(&mut args, "--name");
+    let out_dir = take_option(&mut args, "--out-dir");
+
+    let format = args.get(2).and_then(|f| ExportFormat::from_name(f));
+    let (Some(format), false) = (format, options.help) else {
+        eprintln!("Usage: {} export <ebnf|abnf|pest|tree-sitter> [input.bnf] [--name NAME] [--out-dir DIR]", args[0]);
+        eprintln!();
+        eprintln!("Arguments:");
+        eprintln!("  format       : Target format (required)");
+        eprintln!("  input.bnf    : Input grammar file (default: the input grammar of the hensan.toml pipeline)");
+        eprintln!("  --name       : Grammar name used by tree-sitter (default: input file name)");
+        eprintln!("  --out-dir    : Write the grammar (and scanner stubs) into DIR instead of stdout");
+        options.exit_after_usage();
+    };
+
+    let (input_bnf_path, _) = input_bnf_path(args.get(3), options);
+    let input_grammar = load_input_grammar(&input_bnf_path, options);
+
+    let name = name.unwrap_or_else(|| {
+        Path::new(&input_bnf_path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().replace(|c: char| !c.is_alphanumeric(), "_"))
+            .unwrap_or_else(|| "hensan".to_string())
+    });
+
+    let export = export_grammar(&input_grammar, format, &name);
+
+    for warning in &export.warnings {
+        options.info(format!("Warning: {}", warning));
+    }
+
+    match out_dir {
+        Some(dir) => {
+            let files = std::iter::once((format.file_name().to_string(), export.text)).chain(export.extra_files);
+            for (file, content) in files {
+                let path = Path::new(&dir).join(file);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).unwrap_or_else(|e| {
+                        eprintln!("Error creating {}: {}", parent.display(), e);
+                        process::exit(EXIT_IO_ERROR);
+                    });
+                }
+                fs::write(&path, content).unwrap_or_else(|e| {
+                    eprintln!("Error writing {}: {}", path.display(), e);
+                    process::exit(EXIT_IO_ERROR);
+                });
+                options.info(format!("Wrote {}", path.display()));
+            }
+        }
+        None => {
+            print!("{}", export.text);
+            for (file, _) in &export.extra_files {
+                options.info(format!("Note: {} was not written (use --out-dir)", file));
+            }
+        }
+    }
+}
+
+/// import サブコマンド: 他形式の文法を hensan の入力BNFに変換する
+fn run_import(mut args: Vec<String>, options: &GlobalOptions) {
+    let output_path = take_option(&mut args, "-o");
+
+    let format = args.get(2).and_then(|f| ImportFormat::from_name(f));
+    let (Some(format), Some(grammar_path), false) = (format, args.get(3), options.help) else {
+        eprintln!("Usage: {} import <ebnf|abnf|pest> <grammar> [-o input.bnf]", args[0]);
+        eprintln!();
+        eprintln!("Arguments:");
+        eprintln!("  format       : Source format: ISO 14977 EBNF, RFC 5234 ABNF or pest (required)");
+        eprintln!("  grammar      : Grammar file to convert (required)");
+        eprintln!("  -o           : Write the input BNF to a file instead of stdout");
+        options.exit_after_usage();
+    };
+
+    let source = read_file(grammar_path);
+    let import = import_grammar(&source, format).unwrap_or_else(|e| {
+        eprintln!("Error in {}: {}", grammar_path, e);
+        process::exit(EXIT_GRAMMAR_ERROR);
+    });
+
+    for warning in &import.warnings {
+        options.info(format!("Warning: {}", warning));
+    }
+    options.info(format!(
+        "Imported {} rules (start rule: {})",
+        import.grammar.rules.len(),
+        import.grammar.start_rule
+    ));
+
+    write_output(output_path.as_deref(), &import.bnf);
+}
+
+/// doc サブコマンド: 文法を鉄道線路図つきの HTML ドキュメントにする
+fn run_doc(mut args: Vec<String>, options: &GlobalOptions) {
+    let output_path = take_option(&mut args, "-o");
+
+    if options.help {
+        eprintln!("Usage: {} doc [input.bnf] [output.bnf] [-o out.html]", args[0]);
+        eprintln!();
+        eprintln!("Arguments:");
+        eprintln!("  input.bnf    : Input grammar file (default: the input grammar of the hensan.toml pipeline)");
+        eprintln!("  output.bnf   : Output grammar file shown next to each rule (default: the first output grammar of the hensan.toml pipeline, if any)");
+        eprintln!("  -o           : Write the HTML to a file instead of stdout");
+        options.exit_after_usage();
+    }
+
+    let (input_bnf_path, project) = input_bnf_path(args.get(2), options);
+    let input_grammar = load_input_grammar(&input_bnf_path, options);
+
+    // 出力BNFは明示されたときか、パイプラインにあるときだけ使う
+    let output_bnf_path = args.get(3).cloned().or_else(|| {
+        let target = project.as_ref()?.pipeline.outputs.first()?;
+        Some(target.grammar.display().to_string())
+    });
+    let output_grammar = output_bnf_path.map(|path| load_output_grammar(&path, options));
+
+    let title = Path::new(&input_bnf_path)
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| input_bnf_path.clone());
+    let mut generator = DocGenerator::new(&input_grammar).with_title(&title);
+    if let Some(output_grammar) = &output_grammar {
+        generator = generator.with_output(output_grammar);
+    }
+
+    write_output(output_path.as_deref(), &generator.generate());
+}
+
+/// watch サブコマンド: ソースと文法を監視し、変わるたびに変換し直す
+fn run_watch(mut args: Vec<String>, options: &GlobalOptions) {
+    let out_dir = take_option(&mut args, "--out-dir");
+    let output_ext = take_option(&mut args, "--ext");
+    let source_exts = take_source_exts(&mut args);
+    let poll = take_count(&mut args, "--poll");
+    let mut start_rule = take_option(&mut args, "--start");
+    let mut recovery_rules = take_list(&mut args, "--recover");
+    let no_cache = take_flag(&mut args, "--no-cache");
+
+    let (Some(source), false) = (args.get(2).cloned(), options.help) else {
+        eprintln!("Usage: {} watch <source> [input.bnf] [output.bnf] [--out-dir DIR] [--ext EXT] [--source-ext EXTS] [--start RULE] [--recover RULES] [--poll MS] [--no-cache]", args[0]);
+        eprintln!();
+        eprintln!("Arguments:");
+        eprintln!("  source       : Source file or directory to watch (required)");
+        eprintln!("  input.bnf    : Input grammar file (default: the input grammar of the hensan.toml pipeline)");
+        eprintln!("  output.bnf   : Output grammar file (default: the first output grammar of the hensan.toml pipeline)");
+        eprintln!("  --out-dir    : Write the outputs into DIR, mirroring the source directory (default: print a single file's output)");
+        eprintln!("  --ext        : Extension of the output files (default: keep the source file name)");
+        eprintln!("  --source-ext : Only translate files with these comma-separated extensions (default: all files)");
+        eprintln!("  --start      : Parse the sources as fragments starting at RULE (default: @start or the first rule)");
+        eprintln!("  --recover    : Recover from parse errors in RULES and still write the output");
+        eprintln!("  --poll       : Polling interval in milliseconds when file notifications are unavailable (default: 500)");
+        eprintln!("  --no-cache   : Do not read or write the compiled grammar cache (.cache next to input.bnf)");
+        options.exit_after_usage();
+    };
+    reject_extra_args(&args, 3);
+    if !Path::new(&source).exists() {
+        eprintln!("Error: {} does not exist", source);
+        process::exit(EXIT_IO_ERROR);
+    }
+
+    let (input_bnf_path, project) = input_bnf_path(args.get(3), options);
+    let output_bnf_path = output_bnf_path(args.get(4), project.as_ref(), options);
+
+    // 文法をパイプラインから決めたときは、その設定も使う (コマンドラインの指定が優先)
+    let target = project.as_ref().and_then(|p| p.pipeline.outputs.first());
+    let out_dir = out_dir.map(PathBuf::from).or_else(|| {
+        let pipeline = &project.as_ref()?.pipeline;
+        target?.out_dir.clone().or_else(|| pipeline.out_dir.clone())
+    });
+    let output_ext = output_ext.or_else(|| target?.ext.clone());
+    start_rule = start_rule.or_else(|| project.as_ref()?.pipeline.start.clone());
+    if recovery_rules.is_empty() {
+        recovery_rules = project.as_ref().map(|p| p.pipeline.recover.clone()).unwrap_or_default();
+    }
+
+    let mut watch = Watch::new(Path::new(&source), Path::new(&input_bnf_path), Path::new(&output_bnf_path))
+        .with_source_exts(&source_exts)
+        .with_start_rule(start_rule.as_deref())
+        .with_recovery(&recovery_rules)
+        .with_cache(!no_cache)
+        .with_terminal(io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none());
+    if let Some(project) = &project {
+        watch = watch.with_passes(&project.pipeline.passes);
+    }
+    if let Some(dir) = &out_dir {
+        watch = watch.with_out_dir(dir);
+    }
+    if let Some(ext) = &output_ext {
+        watch = watch.with_output_ext(ext);
+    }
+    if let Some(ms) = poll {
+        watch = watch.with_poll_interval(Duration::from_millis(ms as u64));
+    }
+
+    if let Err(e) = watch.run(&mut io::stdout().lock()) {
+        eprintln!("Error: {}", e);
+        process::exit(EXIT_IO_ERROR);
+    }
+}
+
+/// repl サブコマンド: 文法を読み込んでソースを対話的に変換する
+fn run_repl(args: Vec<String>, options: &GlobalOptions) {
+    if options.help {
+        eprintln!("Usage: {} repl [input.bnf] [output.bnf]", args[0]);
+        eprintln!();
+        eprintln!("Arguments:");
+        eprintln!("  input.bnf    : Input grammar file (default: the input grammar of the hensan.toml pipeline)");
+        eprintln!("  output.bnf   : Output grammar file (default: the first output grammar of the hensan.toml pipeline)");
+        eprintln!();
+        eprintln!("Type a source to translate it; `:help` lists the REPL commands.");
+        options.exit_after_usage();
+    }
+    reject_extra_args(&args, 2);
+
+    let (input_bnf_path, project) = input_bnf_path(args.get(2), options);
+    let output_bnf_path = output_bnf_path(args.get(3), project.as_ref(), options);
+
+    let mut repl = Repl::load(&input_bnf_path, &output_bnf_path).unwrap_or_else(|e| exit_load_error(e));
+    if let Err(e) = repl.run(io::stdin().lock(), io::stdout().lock()) {
+        eprintln!("Error: {}", e);
+        process::exit(EXIT_IO_ERROR);
+    }
+}
+
+/// 使用法を表示する
+fn print_usage(program: &str) {
+    eprintln!("Usage: {} <command> [arguments] [--pipeline NAME] [--quiet | --verbose]", program);
+    eprintln!();
+    eprintln!("Commands:");
+    eprintln!("  translate    Translate a source file, a directory or the sources of a pipeline (the default command)");
+    eprintln!("  parse        Parse a source file and print its AST");
+    eprintln!("  check        Parse source files and report errors without writing anything");
+    eprintln!("  test         Translate test cases and compare them with their expected outputs");
+    eprintln!("  init         Create {} with grammars and examples from a template", CONFIG_FILE);
+    eprintln!("  fmt          Reformat a source file in canonical style");
+    eprintln!("  fmt-grammar  Reformat grammar files");
+    eprintln!("  watch        Retranslate whenever a source or grammar file changes");
+    eprintln!("  generate     Generate code from an AST in JSON");
+    eprintln!("  export       Convert the input grammar to ebnf, abnf, pest or tree-sitter");
+    eprintln!("  import       Convert an ebnf, abnf or pest grammar to an input grammar");
+    eprintln!("  doc          Write HTML documentation with railroad diagrams for a grammar");
+    eprintln!("  repl         Translate sources interactively");
+    eprintln!("  lsp          Run the language server for grammar files on stdin/stdout");
+    eprintln!();
+    eprintln!("Run `{} <command> --help` for the arguments of a command.", program);
+    eprintln!();
+    eprintln!("Sources are file paths, '-' for stdin, --file PATH or --code CODE.");
+    eprintln!("Grammar files that are not given come from the nearest {} in the current directory or its parents.", CONFIG_FILE);
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --pipeline   : Pipeline of {} to use (default: `default` or the only pipeline)", CONFIG_FILE);
+    eprintln!("  --quiet      : Only print errors");
+    eprintln!("  --verbose    : Also print the grammars used, timings and each translated file");
+    eprintln!();
+    eprintln!("Exit status:");
+    eprintln!(
+        "  0 on success, {} for parse errors in a source or failed tests, {} for invalid arguments, {} for errors in a grammar or {}, {} for I/O errors",
+        EXIT_PARSE_ERROR, EXIT_USAGE, EXIT_GRAMMAR_ERROR, CONFIG_FILE, EXIT_IO_ERROR
+    );
+    eprintln!();
+    eprintln!("Examples:");
+    eprintln!("  # Create a project that translates Python into Rust");
+    eprintln!("  {} init --from python --to rust", program);
+    eprintln!();
+    eprintln!("  # Inline source code");
+    eprintln!("  {} translate --code 'int my_func(int a, float b);'", program);
+    eprintln!();
+    eprintln!("  # From a file, with custom grammar files, into out.rs");
+    eprintln!("  {} translate source.c Grammar/custom_in.bnf Grammar/custom_out.bnf -o out.rs", program);
+    eprintln!();
+    eprintln!("  # From stdin");
+    eprintln!("  cat source.c | {} translate -", program);
+    eprintln!();
+    eprintln!("  # Translate a fragment");
+    eprintln!("  {} translate --code 'int a' --start param", program);
+    eprintln!();
+    eprintln!("  # Translate every .py file under src/ into gen/*.rs");
+    eprintln!("  {} translate src/ --out-dir gen/ --source-ext py --ext rs", program);
+    eprintln!();
+    eprintln!("  # Translate the sources of the \"python\" pipeline in {}", CONFIG_FILE);
+    eprintln!("  {} translate --pipeline python", program);
+    eprintln!();
+    eprintln!("  # Print the AST as JSON");
+    eprintln!("  {} parse source.c --format json", program);
+    eprintln!();
+    eprintln!("  # Check that every source of the pipeline parses");
+    eprintln!("  {} check", program);
+    eprintln!();
+    eprintln!("  # Run the test cases in test/ and update their expected outputs");
+    eprintln!("  {} test test/ grammar/input.bnf grammar/output.bnf --ext rs --bless", program);
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let options = GlobalOptions::take(&mut args);
+
+    match args.get(1).map(|s| s.as_str()) {
+        None => {
+            print_usage(&args[0]);
+            options.exit_after_usage();
+        }
+        Some("help") => print_usage(&args[0]),
+        Some("init") => run_init(args, &options),
+        Some("translate") => run_translate(args, &options),
+        Some("parse") => run_parse(args, &options),
+        Some("check") => run_check(args, &options),
+        Some("test") => run_test(args, &options),
+        Some("fmt") => run_fmt(args, &options),
+        Some("fmt-grammar") => run_fmt_grammar(args, &options),
+        Some("watch") => run_watch(args, &options),
+        Some("generate") => run_generate(args, &options),
+        Some("export") => run_export(args, &options),
+        Some("import") => run_import(args, &options),
+        Some("doc") => run_doc(args, &options),
+        Some("repl") => run_repl(args, &options),
+        Some("lsp") => {
+            if options.help {
+                eprintln!("Usage: {} lsp", args[0]);
+                eprintln!();
+                eprintln!("Runs the language server for .bnf files, talking to the editor over stdin/stdout.");
+                options.exit_after_usage();
+            }
+            // 標準入出力で LSP クライアントと通信する
+            if let Err(e) = LanguageServer::new().run(io::stdin().lock(), io::stdout().lock()) {
+                eprintln!("Error in language server: {}", e);
+                process::exit(EXIT_IO_ERROR);
+            }
+        }
+        // サブコマンドを省略したら translate とみなす (`hensan source.c` は `hensan translate source.c`)
+        // ただしファイルでもパスでもない単語はサブコマンドの打ち間違いとみなす
+        Some(word) => {
+            if !looks_like_source(word) {
+                eprintln!("Unknown command '{}' (run `{} help` for the list of commands)", word, args[0]);
+                process::exit(EXIT_USAGE);
+            }
+            args.insert(1, "translate".to_string());
+            run_translate(args, &options);
+        }
+    }
 }
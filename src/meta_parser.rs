@@ -1,7 +1,12 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 /// 文法式 (入力BNF用)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum GrammarExpr {
     /// 文字列リテラル "..."
     Literal(String),
@@ -29,10 +34,24 @@ pub enum GrammarExpr {
     Newline,
     /// 現在のインデントレベルと一致 (SAME_INDENT)
     SameIndent,
+    /// エラー時の表示を指定した式 (A @label("...") / A @expect("..."))
+    Annotated {
+        inner: Box<GrammarExpr>,
+        annotation: Annotation,
+    },
+}
+
+/// 式が失敗したときのエラーの表示
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Annotation {
+    /// 期待されたものとして、リテラルや正規表現の代わりに表示する名前
+    Label(String),
+    /// 期待されたものの一覧の代わりに表示するエラーメッセージ
+    Expect(String),
 }
 
 /// 出力BNF用の式
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OutputExpr {
     /// 文字列リテラル
     Literal(String),
@@ -60,36 +79,282 @@ pub enum OutputExpr {
 }
 
 /// 入力BNFのルール
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InputRule {
     pub name: String,
     pub expr: GrammarExpr,
 }
 
 /// 出力BNFのルール
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OutputRule {
     pub name: String,
     pub expr: OutputExpr,
 }
 
 /// 入力BNF全体
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct InputGrammar {
     pub rules: HashMap<String, InputRule>,
     pub start_rule: String,
+    /// ルールの定義順
+    pub order: Vec<String>,
+    /// パターンをコンパイルした正規表現 (入力の先頭でだけ当たるよう ^ を付けたもの)
+    /// キャッシュには保存せず、読み込んだ後に compile_patterns で作り直す
+    #[serde(skip)]
+    patterns: HashMap<String, Regex>,
 }
 
 /// 出力BNF全体
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct OutputGrammar {
     pub rules: HashMap<String, OutputRule>,
+    /// ルールの定義順
+    pub order: Vec<String>,
+}
+
+/// 文法ファイルの種類
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GrammarKind {
+    Input,
+    Output,
+}
+
+impl GrammarKind {
+    /// ファイル名から文法の種類を判定 (名前に "output" を含むものを出力BNFとみなす)
+    pub fn from_file_name(name: &str) -> Self {
+        if name.contains("output") {
+            GrammarKind::Output
+        } else {
+            GrammarKind::Input
+        }
+    }
+
+    /// パスから文法の種類を推測する (hensan.toml で決まらないときに使う)
+    /// 名前で決まらなくても、同じディレクトリに input.bnf があればそれと対になる出力BNFとみなす
+    pub fn from_path(path: &Path) -> Self {
+        let name = path.file_name().map(|s| s.to_string_lossy()).unwrap_or_default();
+        match Self::from_file_name(&name) {
+            GrammarKind::Input if name != "input.bnf" && path.with_file_name("input.bnf").is_file() => GrammarKind::Output,
+            kind => kind,
+        }
+    }
+}
+
+impl InputGrammar {
+    /// パターンの正規表現 (文法を読み込んだときにコンパイル済み)
+    pub fn pattern(&self, pattern: &str) -> &Regex {
+        &self.patterns[pattern]
+    }
+
+    /// 全てのルールのパターンをコンパイルし直す (キャッシュから読み込んだ後に使う)
+    pub fn compile_patterns(&mut self) -> Result<(), regex::Error> {
+        let mut patterns = HashMap::new();
+        for rule in self.rules.values() {
+            collect_patterns(&rule.expr, &mut patterns)?;
+        }
+        self.patterns = patterns;
+        Ok(())
+    }
+}
+
+fn collect_patterns(expr: &GrammarExpr, patterns: &mut HashMap<String, Regex>) -> Result<(), regex::Error> {
+    match expr {
+        GrammarExpr::Pattern(pattern) if !patterns.contains_key(pattern) => {
+            patterns.insert(pattern.clone(), compile_pattern(pattern)?);
+        }
+        GrammarExpr::Sequence(items) | GrammarExpr::Choice(items) => {
+            for item in items {
+                collect_patterns(item, patterns)?;
+            }
+        }
+        GrammarExpr::ZeroOrMore(inner)
+        | GrammarExpr::OneOrMore(inner)
+        | GrammarExpr::Optional(inner)
+        | GrammarExpr::Group(inner)
+        | GrammarExpr::Annotated { inner, .. } => collect_patterns(inner, patterns)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// パターンを入力の先頭でだけ当たる正規表現にする
+fn compile_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^{}", pattern))
+}
+
+/// ルール定義として出力 ("name := expr;")
+impl fmt::Display for InputRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} := {};", self.name, self.expr)
+    }
+}
+
+impl fmt::Display for OutputRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} := {};", self.name, self.expr)
+    }
+}
+
+/// 入力BNFの記法で出力
+impl fmt::Display for GrammarExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrammarExpr::Literal(lit) => write!(f, "\"{}\"", lit),
+            GrammarExpr::Pattern(pattern) => {
+                // 文字列形式でパターンとして認識されるものは "..." で、それ以外は [...] で書く
+                let as_string = !pattern.contains('"')
+                    && pattern != "[" && pattern != "]"
+                    && (pattern.starts_with('[') || pattern.contains('+') || pattern.contains('*') || pattern.contains('\\'));
+                if as_string {
+                    write!(f, "\"{}\"", pattern)
+                } else {
+                    write!(f, "[{}]", pattern)
+                }
+            }
+            GrammarExpr::RuleRef(name) => write!(f, "{}", name),
+            GrammarExpr::Sequence(items) if items.is_empty() => write!(f, "\"\""),
+            GrammarExpr::Sequence(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    match item {
+                        GrammarExpr::Choice(_) => write!(f, "({})", item)?,
+                        _ => write!(f, "{}", item)?,
+                    }
+                }
+                Ok(())
+            }
+            GrammarExpr::Choice(choices) => {
+                for (i, choice) in choices.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{}", choice)?;
+                }
+                Ok(())
+            }
+            GrammarExpr::ZeroOrMore(inner) => write!(f, "{}*", inner),
+            GrammarExpr::OneOrMore(inner) => write!(f, "{}+", inner),
+            GrammarExpr::Optional(inner) => write!(f, "{}?", inner),
+            GrammarExpr::Group(inner) => write!(f, "({})", inner),
+            GrammarExpr::Indent => write!(f, "INDENT"),
+            GrammarExpr::Dedent => write!(f, "DEDENT"),
+            GrammarExpr::Newline => write!(f, "NEWLINE"),
+            GrammarExpr::SameIndent => write!(f, "SAME_INDENT"),
+            GrammarExpr::Annotated { inner, annotation } => {
+                match inner.as_ref() {
+                    GrammarExpr::Sequence(_) | GrammarExpr::Choice(_) => write!(f, "({})", inner)?,
+                    _ => write!(f, "{}", inner)?,
+                }
+                match annotation {
+                    Annotation::Label(label) => write!(f, " @label(\"{}\")", label),
+                    Annotation::Expect(message) => write!(f, " @expect(\"{}\")", message),
+                }
+            }
+        }
+    }
+}
+
+/// 出力BNFの記法で出力
+impl fmt::Display for OutputExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputExpr::Literal(lit) => write!(f, "\"{}\"", lit),
+            OutputExpr::RuleRef(name) => write!(f, "{}", name),
+            OutputExpr::Sequence(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    match item {
+                        OutputExpr::Choice(_) | OutputExpr::Match { .. } | OutputExpr::ContextIf { .. } => {
+                            write!(f, "({})", item)?
+                        }
+                        _ => write!(f, "{}", item)?,
+                    }
+                }
+                Ok(())
+            }
+            OutputExpr::Optional(inner) => match inner.as_ref() {
+                OutputExpr::RuleRef(_) => write!(f, "{}?", inner),
+                _ => write!(f, "({})?", inner),
+            },
+            OutputExpr::Join { rule, separator } => write!(f, "{} join \"{}\"", rule, separator),
+            OutputExpr::Match { cases, default } => {
+                write!(f, "match @value {{")?;
+                for (i, (pattern, replacement)) in cases.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " \"{}\" => \"{}\"", pattern, replacement)?;
+                }
+                // default が空なのは `_` の腕が省略された場合
+                if !default.is_empty() {
+                    if !cases.is_empty() {
+                        write!(f, ",")?;
+                    }
+                    if default == "@value" {
+                        write!(f, " _ => @value")?;
+                    } else {
+                        write!(f, " _ => \"{}\"", default)?;
+                    }
+                }
+                write!(f, " }}")
+            }
+            OutputExpr::ContextIf { context_value, then_expr, else_expr } => {
+                write!(f, "if @context == \"{}\" then ", context_value)?;
+                write_branch(f, then_expr)?;
+                write!(f, " else ")?;
+                write_branch(f, else_expr)
+            }
+            OutputExpr::Choice(choices) => {
+                for (i, choice) in choices.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{}", choice)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// if @context の then / else 節 (単一のリテラルかルール参照以外は括弧で囲む)
+fn write_branch(f: &mut fmt::Formatter<'_>, expr: &OutputExpr) -> fmt::Result {
+    match expr {
+        OutputExpr::Literal(_) | OutputExpr::RuleRef(_) => write!(f, "{}", expr),
+        _ => write!(f, "({})", expr),
+    }
+}
+
+/// 文法の構文エラー
+#[derive(Debug, Clone)]
+pub struct MetaParseError {
+    /// エラー位置 (バイトオフセット)
+    pub offset: usize,
+    /// 行番号 (1-indexed)
+    pub line: usize,
+    /// 列番号 (1-indexed)
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MetaParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
 }
 
 /// BNFパーサー
+/// 構文エラーは止まった位置とともに MetaParseError で返す
 pub struct MetaParser {
     input: String,
     pos: usize,
+    /// 入力BNFのパターンをコンパイルした正規表現
+    patterns: HashMap<String, Regex>,
 }
 
 impl MetaParser {
@@ -97,9 +362,19 @@ impl MetaParser {
         MetaParser {
             input: input.to_string(),
             pos: 0,
+            patterns: HashMap::new(),
         }
     }
 
+    /// 現在の位置のエラーを作る
+    fn error(&self, message: impl Into<String>) -> MetaParseError {
+        let offset = self.pos.min(self.input.len());
+        let before = &self.input[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before[before.rfind('\n').map_or(0, |i| i + 1)..].chars().count() + 1;
+        MetaParseError { offset, line, column, message: message.into() }
+    }
+
     fn skip_whitespace_and_comments(&mut self) {
         loop {
             // 空白スキップ
@@ -136,9 +411,24 @@ impl MetaParser {
         Some(ch)
     }
 
-    fn expect_char(&mut self, expected: char) {
-        let ch = self.consume_char().expect("Unexpected end of input");
-        assert_eq!(ch, expected, "Expected '{}', got '{}'", expected, ch);
+    fn expect_char(&mut self, expected: char) -> Result<(), MetaParseError> {
+        match self.peek_char() {
+            Some(ch) if ch == expected => {
+                self.pos += ch.len_utf8();
+                Ok(())
+            }
+            Some(ch) => Err(self.error(format!("Expected '{}', got '{}'", expected, ch))),
+            None => Err(self.error(format!("Expected '{}', got end of input", expected))),
+        }
+    }
+
+    /// キーワードを読む (なければ message のエラー)
+    fn expect_keyword(&mut self, keyword: &str, message: &str) -> Result<(), MetaParseError> {
+        if !self.input[self.pos..].starts_with(keyword) {
+            return Err(self.error(message));
+        }
+        self.pos += keyword.len();
+        Ok(())
     }
 
     fn parse_identifier(&mut self) -> String {
@@ -153,8 +443,8 @@ impl MetaParser {
         self.input[start..self.pos].to_string()
     }
 
-    fn parse_string_literal(&mut self) -> String {
-        self.expect_char('"');
+    fn parse_string_literal(&mut self) -> Result<String, MetaParseError> {
+        self.expect_char('"')?;
         let start = self.pos;
         while let Some(ch) = self.peek_char() {
             if ch == '"' {
@@ -163,92 +453,144 @@ impl MetaParser {
             self.consume_char();
         }
         let result = self.input[start..self.pos].to_string();
-        self.expect_char('"');
-        result
+        self.expect_char('"')?;
+        Ok(result)
     }
 
-    fn parse_pattern(&mut self) -> String {
-        self.expect_char('[');
+    fn parse_pattern(&mut self) -> Result<String, MetaParseError> {
+        self.expect_char('[')?;
         let start = self.pos;
         let mut depth = 1;
         while depth > 0 {
-            let ch = self.consume_char().expect("Unclosed pattern");
+            let ch = self.consume_char().ok_or_else(|| self.error("Unclosed pattern"))?;
             if ch == '[' {
                 depth += 1;
             } else if ch == ']' {
                 depth -= 1;
             }
         }
-        self.input[start..self.pos - 1].to_string()
+        Ok(self.input[start..self.pos - 1].to_string())
+    }
+
+    /// パターンを正規表現にコンパイルして覚える (不正な正規表現ならパターンの位置のエラー)
+    fn add_pattern(&mut self, pattern: &str, pattern_pos: usize) -> Result<(), MetaParseError> {
+        if self.patterns.contains_key(pattern) {
+            return Ok(());
+        }
+        match compile_pattern(pattern) {
+            Ok(regex) => {
+                self.patterns.insert(pattern.to_string(), regex);
+                Ok(())
+            }
+            Err(e) => {
+                self.pos = pattern_pos;
+                // regex のエラーは複数行で、最後の行に理由がある
+                let reason = e.to_string().lines().last().unwrap_or_default().trim_start_matches("error: ").to_string();
+                Err(self.error(format!("Invalid regular expression /{}/: {}", pattern, reason)))
+            }
+        }
+    }
+
+    /// ルール名を読む (空ならエラー)
+    fn expect_rule_name(&mut self) -> Result<String, MetaParseError> {
+        let name = self.parse_identifier();
+        if name.is_empty() {
+            return Err(self.error("Expected a rule name"));
+        }
+        Ok(name)
     }
 
     /// 入力BNFをパース
-    pub fn parse_input_grammar(&mut self) -> InputGrammar {
+    pub fn parse_input_grammar(&mut self) -> Result<InputGrammar, MetaParseError> {
         let mut rules = HashMap::new();
         let mut start_rule = String::new();
+        let mut order = Vec::new();
+        // @start ディレクティブで指定された開始ルール
+        let mut start_directive: Option<(String, usize)> = None;
 
-        while self.pos < self.input.len() {
+        loop {
             self.skip_whitespace_and_comments();
             if self.pos >= self.input.len() {
                 break;
             }
 
-            let name = self.parse_identifier();
-            if name.is_empty() {
-                break;
+            // @start rule_name;
+            if self.input[self.pos..].starts_with("@start") {
+                if start_directive.is_some() {
+                    return Err(self.error("Duplicate @start directive"));
+                }
+                let directive_pos = self.pos;
+                self.pos += 6;
+                self.skip_whitespace_and_comments();
+                let name = self.parse_identifier();
+                if name.is_empty() {
+                    return Err(self.error("Expected a rule name after @start"));
+                }
+                self.skip_whitespace_and_comments();
+                self.expect_char(';')?;
+                start_directive = Some((name, directive_pos));
+                continue;
             }
 
+            let name = self.expect_rule_name()?;
             if start_rule.is_empty() {
                 start_rule = name.clone();
             }
 
             self.skip_whitespace_and_comments();
-            // := を消費
-            assert!(
-                self.input[self.pos..].starts_with(":="),
-                "Expected ':=' after rule name"
-            );
-            self.pos += 2;
+            self.expect_keyword(":=", "Expected ':=' after rule name")?;
 
             self.skip_whitespace_and_comments();
-            let expr = self.parse_input_expr();
+            let expr = self.parse_input_expr()?;
 
             self.skip_whitespace_and_comments();
-            self.expect_char(';');
+            self.expect_char(';')?;
 
+            if !rules.contains_key(&name) {
+                order.push(name.clone());
+            }
             rules.insert(name.clone(), InputRule { name, expr });
         }
 
-        InputGrammar { rules, start_rule }
+        // 開始ルールは @start があればそれ、なければ最初に定義されたルール
+        if let Some((name, directive_pos)) = start_directive {
+            if !rules.contains_key(&name) {
+                self.pos = directive_pos;
+                return Err(self.error(format!("@start names an undefined rule '{}'", name)));
+            }
+            start_rule = name;
+        }
+
+        Ok(InputGrammar { rules, start_rule, order, patterns: std::mem::take(&mut self.patterns) })
     }
 
-    fn parse_input_expr(&mut self) -> GrammarExpr {
-        let mut choices = vec![self.parse_input_sequence()];
+    fn parse_input_expr(&mut self) -> Result<GrammarExpr, MetaParseError> {
+        let mut choices = vec![self.parse_input_sequence()?];
 
         loop {
             self.skip_whitespace_and_comments();
             if self.peek_char() == Some('|') {
                 self.consume_char();
                 self.skip_whitespace_and_comments();
-                choices.push(self.parse_input_sequence());
+                choices.push(self.parse_input_sequence()?);
             } else {
                 break;
             }
         }
 
         if choices.len() == 1 {
-            choices.pop().unwrap()
+            Ok(choices.pop().unwrap())
         } else {
-            GrammarExpr::Choice(choices)
+            Ok(GrammarExpr::Choice(choices))
         }
     }
 
-    fn parse_input_sequence(&mut self) -> GrammarExpr {
+    fn parse_input_sequence(&mut self) -> Result<GrammarExpr, MetaParseError> {
         let mut items = Vec::new();
 
         loop {
             self.skip_whitespace_and_comments();
-            if let Some(item) = self.parse_input_atom() {
+            if let Some(item) = self.parse_input_atom()? {
                 items.push(item);
             } else {
                 break;
@@ -256,40 +598,43 @@ impl MetaParser {
         }
 
         if items.len() == 1 {
-            items.pop().unwrap()
+            Ok(items.pop().unwrap())
         } else {
-            GrammarExpr::Sequence(items)
+            Ok(GrammarExpr::Sequence(items))
         }
     }
 
-    fn parse_input_atom(&mut self) -> Option<GrammarExpr> {
+    fn parse_input_atom(&mut self) -> Result<Option<GrammarExpr>, MetaParseError> {
         self.skip_whitespace_and_comments();
 
-        let ch = self.peek_char()?;
+        let Some(ch) = self.peek_char() else { return Ok(None) };
+        let atom_pos = self.pos;
 
         let base = match ch {
             '"' => {
-                let lit = self.parse_string_literal();
+                let lit = self.parse_string_literal()?;
                 // 単一の [ や ] はリテラルとして扱う
                 if lit == "[" || lit == "]" {
                     GrammarExpr::Literal(lit)
                 // 正規表現メタ文字を含む場合はパターンとして扱う
                 } else if lit.starts_with('[') || lit.contains('+') || lit.contains('*') || lit.contains('\\') {
+                    self.add_pattern(&lit, atom_pos)?;
                     GrammarExpr::Pattern(lit)
                 } else {
                     GrammarExpr::Literal(lit)
                 }
             }
             '[' => {
-                let pattern = self.parse_pattern();
+                let pattern = self.parse_pattern()?;
+                self.add_pattern(&pattern, atom_pos)?;
                 GrammarExpr::Pattern(pattern)
             }
             '(' => {
                 self.consume_char();
                 self.skip_whitespace_and_comments();
-                let inner = self.parse_input_expr();
+                let inner = self.parse_input_expr()?;
                 self.skip_whitespace_and_comments();
-                self.expect_char(')');
+                self.expect_char(')')?;
                 GrammarExpr::Group(Box::new(inner))
             }
             _ if ch.is_alphabetic() || ch == '_' => {
@@ -303,90 +648,117 @@ impl MetaParser {
                     _ => GrammarExpr::RuleRef(name),
                 }
             }
-            _ => return None,
+            _ => return Ok(None),
         };
 
+        // 後置演算子の前後の注釈 (x @label("...")? / x? @label("..."))
+        let base = self.parse_annotations(base)?;
+
         // 後置演算子をチェック
         self.skip_whitespace_and_comments();
-        match self.peek_char() {
+        let expr = match self.peek_char() {
             Some('*') => {
                 self.consume_char();
-                Some(GrammarExpr::ZeroOrMore(Box::new(base)))
+                GrammarExpr::ZeroOrMore(Box::new(base))
             }
             Some('+') => {
                 self.consume_char();
-                Some(GrammarExpr::OneOrMore(Box::new(base)))
+                GrammarExpr::OneOrMore(Box::new(base))
             }
             Some('?') => {
                 self.consume_char();
-                Some(GrammarExpr::Optional(Box::new(base)))
+                GrammarExpr::Optional(Box::new(base))
             }
-            _ => Some(base),
+            _ => return Ok(Some(base)),
+        };
+        Ok(Some(self.parse_annotations(expr)?))
+    }
+
+    /// @label("...") / @expect("...") を読んで式に付ける
+    fn parse_annotations(&mut self, mut expr: GrammarExpr) -> Result<GrammarExpr, MetaParseError> {
+        loop {
+            self.skip_whitespace_and_comments();
+            let rest = &self.input[self.pos..];
+            let (keyword, make): (&str, fn(String) -> Annotation) = if rest.starts_with("@label") {
+                ("@label", Annotation::Label)
+            } else if rest.starts_with("@expect") {
+                ("@expect", Annotation::Expect)
+            } else {
+                return Ok(expr);
+            };
+            self.pos += keyword.len();
+            self.skip_whitespace_and_comments();
+            self.expect_char('(')?;
+            self.skip_whitespace_and_comments();
+            if self.peek_char() != Some('"') {
+                return Err(self.error(format!("Expected a string after {}(", keyword)));
+            }
+            let text = self.parse_string_literal()?;
+            self.skip_whitespace_and_comments();
+            self.expect_char(')')?;
+            expr = GrammarExpr::Annotated { inner: Box::new(expr), annotation: make(text) };
         }
     }
 
     /// 出力BNFをパース
-    pub fn parse_output_grammar(&mut self) -> OutputGrammar {
+    pub fn parse_output_grammar(&mut self) -> Result<OutputGrammar, MetaParseError> {
         let mut rules = HashMap::new();
+        let mut order = Vec::new();
 
-        while self.pos < self.input.len() {
+        loop {
             self.skip_whitespace_and_comments();
             if self.pos >= self.input.len() {
                 break;
             }
 
-            let name = self.parse_identifier();
-            if name.is_empty() {
-                break;
-            }
+            let name = self.expect_rule_name()?;
 
             self.skip_whitespace_and_comments();
-            assert!(
-                self.input[self.pos..].starts_with(":="),
-                "Expected ':=' after rule name"
-            );
-            self.pos += 2;
+            self.expect_keyword(":=", "Expected ':=' after rule name")?;
 
             self.skip_whitespace_and_comments();
-            let expr = self.parse_output_expr();
+            let expr = self.parse_output_expr()?;
 
             self.skip_whitespace_and_comments();
-            self.expect_char(';');
+            self.expect_char(';')?;
 
+            if !rules.contains_key(&name) {
+                order.push(name.clone());
+            }
             rules.insert(name.clone(), OutputRule { name, expr });
         }
 
-        OutputGrammar { rules }
+        Ok(OutputGrammar { rules, order })
     }
 
-    fn parse_output_expr(&mut self) -> OutputExpr {
-        let mut choices = vec![self.parse_output_sequence()];
+    fn parse_output_expr(&mut self) -> Result<OutputExpr, MetaParseError> {
+        let mut choices = vec![self.parse_output_sequence()?];
 
         loop {
             self.skip_whitespace_and_comments();
             if self.peek_char() == Some('|') {
                 self.consume_char();
                 self.skip_whitespace_and_comments();
-                choices.push(self.parse_output_sequence());
+                choices.push(self.parse_output_sequence()?);
             } else {
                 break;
             }
         }
 
         if choices.len() == 1 {
-            choices.pop().unwrap()
+            Ok(choices.pop().unwrap())
         } else {
-            OutputExpr::Choice(choices)
+            Ok(OutputExpr::Choice(choices))
         }
     }
 
-    fn parse_output_sequence(&mut self) -> OutputExpr {
+    fn parse_output_sequence(&mut self) -> Result<OutputExpr, MetaParseError> {
         self.skip_whitespace_and_comments();
 
         // match構文のチェック (matchの後が識別子文字でないことを確認)
         if self.input[self.pos..].starts_with("match") {
             let after_match = self.input[self.pos + 5..].chars().next();
-            if after_match.map_or(true, |ch| !ch.is_alphanumeric() && ch != '_') {
+            if after_match.is_none_or(|ch| !ch.is_alphanumeric() && ch != '_') {
                 return self.parse_match_expr();
             }
         }
@@ -394,7 +766,7 @@ impl MetaParser {
         // if @context構文のチェック
         if self.input[self.pos..].starts_with("if") {
             let after_if = self.input[self.pos + 2..].chars().next();
-            if after_if.map_or(true, |ch| !ch.is_alphanumeric() && ch != '_') {
+            if after_if.is_none_or(|ch| !ch.is_alphanumeric() && ch != '_') {
                 return self.parse_context_if_expr();
             }
         }
@@ -403,18 +775,19 @@ impl MetaParser {
 
         loop {
             self.skip_whitespace_and_comments();
-            if let Some(item) = self.parse_output_atom() {
+            let item_pos = self.pos;
+            if let Some(item) = self.parse_output_atom()? {
                 // join構文のチェック
                 self.skip_whitespace_and_comments();
                 if self.input[self.pos..].starts_with("join") {
+                    let OutputExpr::RuleRef(rule) = item else {
+                        self.pos = item_pos;
+                        return Err(self.error("join must follow a rule reference"));
+                    };
                     self.pos += 4;
                     self.skip_whitespace_and_comments();
-                    let separator = self.parse_string_literal();
-                    if let OutputExpr::RuleRef(rule) = item {
-                        items.push(OutputExpr::Join { rule, separator });
-                    } else {
-                        panic!("join must follow a rule reference");
-                    }
+                    let separator = self.parse_string_literal()?;
+                    items.push(OutputExpr::Join { rule, separator });
                 } else {
                     items.push(item);
                 }
@@ -424,36 +797,36 @@ impl MetaParser {
         }
 
         if items.len() == 1 {
-            items.pop().unwrap()
+            Ok(items.pop().unwrap())
         } else {
-            OutputExpr::Sequence(items)
+            Ok(OutputExpr::Sequence(items))
         }
     }
 
-    fn parse_output_atom(&mut self) -> Option<OutputExpr> {
+    fn parse_output_atom(&mut self) -> Result<Option<OutputExpr>, MetaParseError> {
         self.skip_whitespace_and_comments();
 
-        let ch = self.peek_char()?;
+        let Some(ch) = self.peek_char() else { return Ok(None) };
 
         match ch {
             '"' => {
-                let lit = self.parse_string_literal();
-                Some(OutputExpr::Literal(lit))
+                let lit = self.parse_string_literal()?;
+                Ok(Some(OutputExpr::Literal(lit)))
             }
             '(' => {
                 self.consume_char();
                 self.skip_whitespace_and_comments();
-                let inner = self.parse_output_expr();
+                let inner = self.parse_output_expr()?;
                 self.skip_whitespace_and_comments();
-                self.expect_char(')');
+                self.expect_char(')')?;
 
                 // 後置演算子
                 self.skip_whitespace_and_comments();
                 if self.peek_char() == Some('?') {
                     self.consume_char();
-                    Some(OutputExpr::Optional(Box::new(inner)))
+                    Ok(Some(OutputExpr::Optional(Box::new(inner))))
                 } else {
-                    Some(inner)
+                    Ok(Some(inner))
                 }
             }
             _ if ch.is_alphabetic() || ch == '_' => {
@@ -462,29 +835,25 @@ impl MetaParser {
                 self.skip_whitespace_and_comments();
                 if self.peek_char() == Some('?') {
                     self.consume_char();
-                    Some(OutputExpr::Optional(Box::new(OutputExpr::RuleRef(name))))
+                    Ok(Some(OutputExpr::Optional(Box::new(OutputExpr::RuleRef(name)))))
                 } else {
-                    Some(OutputExpr::RuleRef(name))
+                    Ok(Some(OutputExpr::RuleRef(name)))
                 }
             }
-            _ => None,
+            _ => Ok(None),
         }
     }
 
-    fn parse_match_expr(&mut self) -> OutputExpr {
+    fn parse_match_expr(&mut self) -> Result<OutputExpr, MetaParseError> {
         // "match" を消費
         self.pos += 5;
         self.skip_whitespace_and_comments();
 
         // "@value" を期待
-        assert!(
-            self.input[self.pos..].starts_with("@value"),
-            "Expected @value after match"
-        );
-        self.pos += 6;
+        self.expect_keyword("@value", "Expected @value after match")?;
 
         self.skip_whitespace_and_comments();
-        self.expect_char('{');
+        self.expect_char('{')?;
 
         let mut cases = Vec::new();
         let mut default = String::new();
@@ -502,29 +871,21 @@ impl MetaParser {
                 // デフォルトケース
                 self.consume_char();
                 self.skip_whitespace_and_comments();
-                assert!(
-                    self.input[self.pos..].starts_with("=>"),
-                    "Expected '=>' in match"
-                );
-                self.pos += 2;
+                self.expect_keyword("=>", "Expected '=>' in match")?;
                 self.skip_whitespace_and_comments();
 
                 if self.input[self.pos..].starts_with("@value") {
                     self.pos += 6;
                     default = "@value".to_string();
                 } else {
-                    default = self.parse_string_literal();
+                    default = self.parse_string_literal()?;
                 }
             } else if self.peek_char() == Some('"') {
-                let pattern = self.parse_string_literal();
+                let pattern = self.parse_string_literal()?;
                 self.skip_whitespace_and_comments();
-                assert!(
-                    self.input[self.pos..].starts_with("=>"),
-                    "Expected '=>' in match"
-                );
-                self.pos += 2;
+                self.expect_keyword("=>", "Expected '=>' in match")?;
                 self.skip_whitespace_and_comments();
-                let replacement = self.parse_string_literal();
+                let replacement = self.parse_string_literal()?;
                 cases.push((pattern, replacement));
             } else {
                 break;
@@ -537,86 +898,60 @@ impl MetaParser {
             }
         }
 
-        OutputExpr::Match { cases, default }
+        Ok(OutputExpr::Match { cases, default })
     }
 
     /// if @context == "value" then expr else expr をパース
-    fn parse_context_if_expr(&mut self) -> OutputExpr {
+    fn parse_context_if_expr(&mut self) -> Result<OutputExpr, MetaParseError> {
         // "if" を消費
         self.pos += 2;
         self.skip_whitespace_and_comments();
 
         // "@context" を期待
-        assert!(
-            self.input[self.pos..].starts_with("@context"),
-            "Expected @context after if"
-        );
-        self.pos += 8;
-
+        self.expect_keyword("@context", "Expected @context after if")?;
         self.skip_whitespace_and_comments();
 
         // "==" を期待
-        assert!(
-            self.input[self.pos..].starts_with("=="),
-            "Expected '==' after @context"
-        );
-        self.pos += 2;
-
+        self.expect_keyword("==", "Expected '==' after @context")?;
         self.skip_whitespace_and_comments();
 
         // コンテキスト値（文字列リテラル）
-        let context_value = self.parse_string_literal();
-
+        let context_value = self.parse_string_literal()?;
         self.skip_whitespace_and_comments();
 
         // "then" を期待
-        assert!(
-            self.input[self.pos..].starts_with("then"),
-            "Expected 'then' after context value"
-        );
-        self.pos += 4;
-
+        self.expect_keyword("then", "Expected 'then' after context value")?;
         self.skip_whitespace_and_comments();
 
         // then式をパース（括弧で囲まれた式、または単一のアトム）
-        let then_expr = if self.peek_char() == Some('(') {
-            self.consume_char();
-            self.skip_whitespace_and_comments();
-            let inner = self.parse_output_expr();
-            self.skip_whitespace_and_comments();
-            self.expect_char(')');
-            inner
-        } else {
-            self.parse_output_atom().expect("Expected expression after 'then'")
-        };
-
+        let then_expr = self.parse_context_branch("Expected expression after 'then'")?;
         self.skip_whitespace_and_comments();
 
         // "else" を期待
-        assert!(
-            self.input[self.pos..].starts_with("else"),
-            "Expected 'else' after then expression"
-        );
-        self.pos += 4;
-
+        self.expect_keyword("else", "Expected 'else' after then expression")?;
         self.skip_whitespace_and_comments();
 
         // else式をパース（括弧で囲まれた式、または単一のアトム）
-        let else_expr = if self.peek_char() == Some('(') {
-            self.consume_char();
-            self.skip_whitespace_and_comments();
-            let inner = self.parse_output_expr();
-            self.skip_whitespace_and_comments();
-            self.expect_char(')');
-            inner
-        } else {
-            self.parse_output_atom().expect("Expected expression after 'else'")
-        };
+        let else_expr = self.parse_context_branch("Expected expression after 'else'")?;
 
-        OutputExpr::ContextIf {
+        Ok(OutputExpr::ContextIf {
             context_value,
             then_expr: Box::new(then_expr),
             else_expr: Box::new(else_expr),
+        })
+    }
+
+    /// then / else の式 (括弧で囲まれた式、または単一のアトム)
+    fn parse_context_branch(&mut self, message: &str) -> Result<OutputExpr, MetaParseError> {
+        if self.peek_char() == Some('(') {
+            self.consume_char();
+            self.skip_whitespace_and_comments();
+            let inner = self.parse_output_expr()?;
+            self.skip_whitespace_and_comments();
+            self.expect_char(')')?;
+            Ok(inner)
+        } else {
+            self.parse_output_atom()?.ok_or_else(|| self.error(message))
         }
     }
 }
@@ -637,10 +972,90 @@ mod tests {
         "#;
 
         let mut parser = MetaParser::new(input);
-        let grammar = parser.parse_input_grammar();
+        let grammar = parser.parse_input_grammar().unwrap();
 
         assert!(grammar.rules.contains_key("func_decl"));
         assert!(grammar.rules.contains_key("args"));
         assert!(grammar.rules.contains_key("arg"));
+        assert_eq!(grammar.start_rule, "func_decl");
+    }
+
+    #[test]
+    fn test_start_directive() {
+        let input = r#"
+            name    := "[a-z]+";
+            @start program;
+            program := name+;
+        "#;
+        let grammar = MetaParser::new(input).parse_input_grammar().unwrap();
+        assert_eq!(grammar.start_rule, "program");
+        assert_eq!(grammar.order, vec!["name", "program"]);
+
+        let err = MetaParser::new("a := \"x\"; @start b;").parse_input_grammar().unwrap_err();
+        assert_eq!(err.message, "@start names an undefined rule 'b'");
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let err = MetaParser::new("a := \"x\";\nb = \"y\";").parse_input_grammar().unwrap_err();
+        assert_eq!((err.line, err.column, err.message.as_str()), (2, 3, "Expected ':=' after rule name"));
+
+        let err = MetaParser::new("a := (\"x\" ;").parse_input_grammar().unwrap_err();
+        assert_eq!(err.message, "Expected ')', got ';'");
+        let err = MetaParser::new("a := [x").parse_input_grammar().unwrap_err();
+        assert_eq!(err.message, "Unclosed pattern");
+        let err = MetaParser::new("a := \"x\"; %").parse_input_grammar().unwrap_err();
+        assert_eq!((err.offset, err.message.as_str()), (10, "Expected a rule name"));
+
+        let err = MetaParser::new("a := \"x\";\nname := \"[a-z+\";").parse_input_grammar().unwrap_err();
+        assert_eq!((err.line, err.column), (2, 9));
+        assert_eq!(err.message, "Invalid regular expression /[a-z+/: unclosed character class");
+
+        let err = MetaParser::new("a := match @val { _ => @value };").parse_output_grammar().unwrap_err();
+        assert_eq!(err.message, "Expected @value after match");
+        let err = MetaParser::new("a := \"x\" join \",\";").parse_output_grammar().unwrap_err();
+        assert_eq!((err.column, err.message.as_str()), (6, "join must follow a rule reference"));
+        let err = MetaParser::new("a := if @context == \"b\" then c").parse_output_grammar().unwrap_err();
+        assert_eq!(err.message, "Expected 'else' after then expression");
+    }
+
+    #[test]
+    fn test_display_roundtrip() {
+        let input = r#"
+            stmt := SAME_INDENT (if_stmt | call) NEWLINE?;
+            if_stmt := "if" name ":" NEWLINE INDENT stmt+ DEDENT;
+            call := name "(" (name ("," name)*)? ")";
+            name := "[a-zA-Z_]+" | [x];
+            arg := name @label("argument")? ("=" name)? @expect("expected a default value");
+        "#;
+        let output = r#"
+            stmt := if_stmt | call;
+            call := name "(" args? ")" ";";
+            args := name join ", ";
+            name := match @value { "main" => "main_impl", _ => @value };
+            block := if @context == "func" then ("{" stmt "}") else stmt;
+        "#;
+
+        let input_grammar = MetaParser::new(input).parse_input_grammar().unwrap();
+        let output_grammar = MetaParser::new(output).parse_output_grammar().unwrap();
+        for rule in input_grammar.rules.values() {
+            let printed = rule.to_string();
+            let reparsed = MetaParser::new(&printed).parse_input_grammar().unwrap();
+            assert_eq!(reparsed.rules[&rule.name].expr.to_string(), rule.expr.to_string());
+        }
+        for rule in output_grammar.rules.values() {
+            let printed = rule.to_string();
+            let reparsed = MetaParser::new(&printed).parse_output_grammar().unwrap();
+            assert_eq!(reparsed.rules[&rule.name].expr.to_string(), rule.expr.to_string());
+        }
+        assert_eq!(input_grammar.rules["name"].expr.to_string(), r#""[a-zA-Z_]+" | [x]"#);
+        assert_eq!(
+            input_grammar.rules["arg"].expr.to_string(),
+            r#"name @label("argument")? ("=" name)? @expect("expected a default value")"#
+        );
+        assert_eq!(
+            output_grammar.rules["name"].expr.to_string(),
+            r#"match @value { "main" => "main_impl", _ => @value }"#
+        );
     }
 }
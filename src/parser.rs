@@ -1,9 +1,12 @@
-use regex::Regex;
+use std::cell::OnceCell;
 use std::collections::HashMap;
 use std::fmt;
 
-use crate::ast::ASTNode;
-use crate::meta_parser::{GrammarExpr, InputGrammar};
+use crate::ast::{ASTNode, Span};
+use crate::incremental::{self, ReparseError, Reparsed, TextEdit};
+use crate::line_index::{self, ColumnEncoding, LineIndex};
+use crate::meta_parser::{Annotation, GrammarExpr, InputGrammar};
+use crate::trace::{self, TraceEvent, TraceKind};
 
 /// パースエラー情報
 #[derive(Debug, Clone)]
@@ -12,7 +15,7 @@ pub struct ParseError {
     pub position: usize,
     /// 行番号 (1-indexed)
     pub line: usize,
-    /// 列番号 (1-indexed)
+    /// 列番号 (1-indexed、文字単位)
     pub column: usize,
     /// 期待されたもの
     pub expected: Vec<String>,
@@ -22,6 +25,8 @@ pub struct ParseError {
     pub context_rule: String,
     /// ソースコードの該当行
     pub source_line: String,
+    /// 入力BNFの @expect で指定されたエラーメッセージ
+    pub message: Option<String>,
 }
 
 impl fmt::Display for ParseError {
@@ -29,17 +34,20 @@ impl fmt::Display for ParseError {
         writeln!(f, "Parse error at line {}, column {}:", self.line, self.column)?;
         writeln!(f)?;
 
-        // 行番号付きでソース行を表示
+        // 行番号付きでソース行を表示 (タブは展開する)
         let line_num_width = self.line.to_string().len();
-        writeln!(f, " {:>width$} | {}", self.line, self.source_line, width = line_num_width)?;
+        writeln!(f, " {:>width$} | {}", self.line, line_index::expand_tabs(&self.source_line), width = line_num_width)?;
 
-        // エラー位置を示す矢印
-        let arrow_padding = " ".repeat(line_num_width + 3 + self.column - 1);
+        // エラー位置を示す矢印 (タブや全角文字の表示幅に合わせる)
+        let before: String = self.source_line.chars().take(self.column - 1).collect();
+        let arrow_padding = " ".repeat(line_num_width + 4 + line_index::display_width(&before));
         writeln!(f, "{}^", arrow_padding)?;
         writeln!(f)?;
 
         // 期待されたものと実際に見つかったもの
-        if !self.expected.is_empty() {
+        if let Some(message) = &self.message {
+            writeln!(f, "Error: {}", message)?;
+        } else if !self.expected.is_empty() {
             writeln!(f, "Expected: {}", self.expected.join(" or "))?;
         }
         writeln!(f, "Found: '{}'", self.found)?;
@@ -52,20 +60,54 @@ impl fmt::Display for ParseError {
 /// パース結果
 pub type ParseResult = Result<ASTNode, ParseError>;
 
+/// バックトラック用に保存するパーサーの状態
+#[derive(Clone)]
+struct SavedState {
+    pos: usize,
+    indent_stack: Vec<usize>,
+    pending_dedents: usize,
+    at_line_start: bool,
+    current_line_indent: usize,
+}
+
+/// ルールのパース結果を再利用するためのキー (ルールと開始時の状態)
+#[derive(PartialEq, Eq, Hash)]
+struct MemoKey {
+    rule: String,
+    pos: usize,
+    indent_stack: Vec<usize>,
+    pending_dedents: usize,
+    at_line_start: bool,
+    current_line_indent: usize,
+}
+
+/// 成功したルールのパース結果
+struct MemoEntry {
+    node: ASTNode,
+    /// パース後の状態
+    end: SavedState,
+    /// ルールの中で記録したエラー情報
+    furthest: Furthest,
+    /// 結果を決めるのに読んだ範囲の終わり (この位置より前の編集だけが結果を変えうる)
+    examined: usize,
+}
+
 /// ソースコードパーサー
 /// 入力BNFに基づいてソースコードをパースし、ASTを構築する
 pub struct Parser<'a> {
     grammar: &'a InputGrammar,
     input: String,
     pos: usize,
-    /// 正規表現のキャッシュ
-    regex_cache: HashMap<String, Regex>,
     /// 最も遠くまで進んだ位置 (エラー報告用)
     furthest_pos: usize,
     /// その位置で期待されていたもの
     furthest_expected: Vec<String>,
     /// その位置でパース中だったルール
     furthest_rule: String,
+    /// その位置での @expect のメッセージ
+    furthest_message: Option<String>,
+    /// パース中の注釈 (@label / @expect) と、その式の開始位置・最初のトークンの位置 (外側から順)
+    annotations: Vec<(usize, usize, Annotation)>,
     /// インデントスタック (インデントレベルを追跡)
     indent_stack: Vec<usize>,
     /// 保留中のDEDENTトークン数
@@ -74,31 +116,227 @@ pub struct Parser<'a> {
     at_line_start: bool,
     /// 現在の行のインデントレベル (スペース数)
     current_line_indent: usize,
+    /// 開始ルール (None なら文法の開始ルール)
+    start_rule: Option<String>,
+    /// トレース (有効な場合のみ記録)
+    trace: Option<Vec<TraceEvent>>,
+    /// 現在のルール呼び出しの深さ
+    depth: usize,
+    /// エラー報告用の行頭の一覧 (最初のエラーで作る)
+    line_index: OnceCell<LineIndex>,
+    /// エラーから回復するルール (同期ルール)
+    recovery_rules: Vec<String>,
+    /// 回復したエラー
+    errors: Vec<ParseError>,
+    /// ルールのパース結果 (インクリメンタルパースの場合のみ)
+    memo: Option<HashMap<MemoKey, MemoEntry>>,
+    /// 前回のパースで再利用した結果の数
+    reused: usize,
+    /// 前回成功したパースの AST とそのソース (変わったノードを求めるため)
+    previous: Option<(ASTNode, String)>,
 }
 
+/// 最も遠くまで進んだ位置の情報 (位置、期待されていたもの、ルール、メッセージ)
+type Furthest = (usize, Vec<String>, String, Option<String>);
+
 impl<'a> Parser<'a> {
     pub fn new(grammar: &'a InputGrammar, input: &str) -> Self {
         Parser {
             grammar,
             input: input.to_string(),
             pos: 0,
-            regex_cache: HashMap::new(),
             furthest_pos: 0,
             furthest_expected: Vec::new(),
             furthest_rule: String::new(),
+            furthest_message: None,
+            annotations: Vec::new(),
             indent_stack: vec![0], // 初期インデントレベルは0
             pending_dedents: 0,
             at_line_start: true,
             current_line_indent: 0,
+            start_rule: None,
+            trace: None,
+            depth: 0,
+            line_index: OnceCell::new(),
+            recovery_rules: Vec::new(),
+            errors: Vec::new(),
+            memo: None,
+            reused: 0,
+            previous: None,
+        }
+    }
+
+    /// 指定したルールから断片をパースする
+    pub fn with_start_rule(mut self, rule: &str) -> Self {
+        self.start_rule = Some(rule.to_string());
+        self
+    }
+
+    /// ルールの出入りをトレースする
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(Vec::new());
+        self
+    }
+
+    /// 指定したルールのパースに失敗したら、エラーを記録して読み飛ばし、パースを続ける
+    /// 読み飛ばした部分は error ノードになる
+    pub fn with_recovery(mut self, rules: &[String]) -> Self {
+        self.recovery_rules = rules.to_vec();
+        self
+    }
+
+    /// ルールのパース結果を覚えておき、reparse で編集範囲の外の結果を再利用する
+    pub fn with_incremental(mut self) -> Self {
+        self.memo = Some(HashMap::new());
+        self
+    }
+
+    /// 前回のパースで再利用したルールの結果の数
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+
+    /// ソースに編集を適用してパースし直す
+    /// 編集範囲の外のルールの結果 (AST の部分木) は、位置をずらして再利用する
+    /// 編集がソースに当てはまらなければ、何も変えずに ReparseError::Edit を返す
+    #[allow(clippy::result_large_err)]
+    pub fn reparse(&mut self, edit: &TextEdit) -> Result<Reparsed, ReparseError> {
+        let input = edit.apply(&self.input)?;
+
+        // 編集より前で読み終えた結果と、編集した行より後ろから始まる結果だけを残す
+        let after_edit = self.input[edit.end..].find('\n').map_or(self.input.len(), |i| edit.end + i + 1);
+        if let Some(memo) = self.memo.take() {
+            let kept = memo.into_iter().filter_map(|(mut key, mut entry)| {
+                if entry.examined <= edit.start {
+                    Some((key, entry))
+                } else if key.pos >= after_edit {
+                    key.pos = edit.shift(key.pos);
+                    edit.shift_node(&mut entry.node);
+                    entry.end.pos = edit.shift(entry.end.pos);
+                    entry.furthest.0 = edit.shift(entry.furthest.0);
+                    entry.examined = edit.shift(entry.examined);
+                    Some((key, entry))
+                } else {
+                    None
+                }
+            });
+            self.memo = Some(kept.collect());
+        }
+
+        // パースに失敗したら、次に成功するまで前回成功した木と比べる
+        let previous = self.previous.take();
+        self.input = input;
+        self.reset();
+        let ast = match self.parse() {
+            Ok(ast) => ast,
+            Err(err) => {
+                self.previous = previous;
+                return Err(err.into());
+            }
+        };
+        let changed = match &previous {
+            // 間に失敗したパースがあれば、それらの編集もまとめた差分で比べる
+            Some((tree, source)) => incremental::changed_nodes(Some(tree), &ast, &TextEdit::between(source, &self.input)),
+            None => incremental::changed_nodes(None, &ast, edit),
+        };
+        Ok(Reparsed { ast, changed })
+    }
+
+    /// パースの状態を初期状態に戻す
+    fn reset(&mut self) {
+        self.load_state(&SavedState {
+            pos: 0,
+            indent_stack: vec![0],
+            pending_dedents: 0,
+            at_line_start: true,
+            current_line_indent: 0,
+        });
+        self.furthest_pos = 0;
+        self.furthest_expected.clear();
+        self.furthest_rule.clear();
+        self.furthest_message = None;
+        self.annotations.clear();
+        self.depth = 0;
+        self.line_index = OnceCell::new();
+        self.errors.clear();
+        self.reused = 0;
+        if let Some(trace) = &mut self.trace {
+            trace.clear();
         }
     }
 
+    /// 回復したエラー (parse が成功しても空とは限らない)
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    /// 記録したトレース
+    pub fn trace(&self) -> &[TraceEvent] {
+        self.trace.as_deref().unwrap_or(&[])
+    }
+
+    /// トレースを字下げしたテキストにする
+    pub fn trace_log(&self) -> String {
+        trace::format_text(self.trace(), &self.input)
+    }
+
+    /// トレースをルール呼び出しの木の HTML にする
+    pub fn trace_html(&self) -> String {
+        trace::format_html(self.trace(), &self.input)
+    }
+
+    /// トレースが有効ならイベントを記録
+    fn record_trace(&mut self, rule: &str, kind: TraceKind, position: usize) {
+        if let Some(trace) = &mut self.trace {
+            trace.push(TraceEvent {
+                depth: self.depth,
+                rule: rule.to_string(),
+                kind,
+                position,
+                indent_stack: self.indent_stack.clone(),
+                pending_dedents: self.pending_dedents,
+            });
+        }
+    }
+
+    /// バックトラック用に現在の状態を保存
+    fn save_state(&self) -> SavedState {
+        SavedState {
+            pos: self.pos,
+            indent_stack: self.indent_stack.clone(),
+            pending_dedents: self.pending_dedents,
+            at_line_start: self.at_line_start,
+            current_line_indent: self.current_line_indent,
+        }
+    }
+
+    /// 保存した状態に戻す (読み進めていた場合はトレースに記録)
+    fn restore_state(&mut self, state: &SavedState, context_rule: &str) {
+        if self.pos != state.pos || self.indent_stack != state.indent_stack || self.pending_dedents != state.pending_dedents {
+            self.record_trace(context_rule, TraceKind::Backtrack(state.pos), self.pos);
+        }
+        self.load_state(state);
+    }
+
+    fn load_state(&mut self, state: &SavedState) {
+        self.pos = state.pos;
+        self.indent_stack = state.indent_stack.clone();
+        self.pending_dedents = state.pending_dedents;
+        self.at_line_start = state.at_line_start;
+        self.current_line_indent = state.current_line_indent;
+    }
+
     /// ソースコードをパースしてASTを返す
+    #[allow(clippy::result_large_err)]
     pub fn parse(&mut self) -> ParseResult {
         // 最初の行のインデントを計算
         self.update_line_indent();
 
-        let start_rule = self.grammar.start_rule.clone();
+        let start_rule = self.start_rule.clone().unwrap_or_else(|| self.grammar.start_rule.clone());
+        if !self.grammar.rules.contains_key(&start_rule) {
+            self.record_error(&format!("a definition of rule '{}'", start_rule), &start_rule);
+            return Err(self.build_error());
+        }
         let result = self.parse_rule(&start_rule);
         self.skip_whitespace_no_newline();
 
@@ -109,6 +347,9 @@ impl<'a> Parser<'a> {
                     self.record_error("end of input", &start_rule);
                     Err(self.build_error())
                 } else {
+                    if self.memo.is_some() {
+                        self.previous = Some((ast.clone(), self.input.clone()));
+                    }
                     Ok(ast)
                 }
             }
@@ -122,36 +363,38 @@ impl<'a> Parser<'a> {
             return;
         }
 
-        let mut indent = 0;
-        let mut temp_pos = self.pos;
-
-        while temp_pos < self.input.len() {
-            let ch = self.input[temp_pos..].chars().next().unwrap();
-            match ch {
-                ' ' => {
-                    indent += 1;
-                    temp_pos += 1;
-                }
-                '\t' => {
-                    // タブは8スペースとして扱う (Python準拠)
-                    indent = (indent / 8 + 1) * 8;
-                    temp_pos += 1;
-                }
-                _ => break,
-            }
-        }
-
-        self.current_line_indent = indent;
+        // タブは次のタブ位置まで進める (Python準拠)
+        self.current_line_indent = line_index::indent_width(&self.input[self.pos..]);
     }
 
     /// エラー情報を記録
+    /// 注釈の付いた式の開始位置で失敗した場合は、リテラルや正規表現の代わりに注釈を使う
     fn record_error(&mut self, expected: &str, context_rule: &str) {
+        let annotation = self
+            .annotations
+            .iter()
+            .find(|(start, token, _)| (*start..=*token).contains(&self.pos))
+            .map(|(_, _, a)| a.clone());
+        let (expected, message) = match &annotation {
+            Some(Annotation::Label(label)) => (label.as_str(), None),
+            Some(Annotation::Expect(message)) => (expected, Some(message)),
+            None => (expected, None),
+        };
+
         if self.pos > self.furthest_pos {
             self.furthest_pos = self.pos;
             self.furthest_expected.clear();
             self.furthest_expected.push(expected.to_string());
             self.furthest_rule = context_rule.to_string();
+            self.furthest_message = message.cloned();
         } else if self.pos == self.furthest_pos {
+            // 同期ルールで数え直した直後はルールがまだない
+            if self.furthest_rule.is_empty() {
+                self.furthest_rule = context_rule.to_string();
+            }
+            if self.furthest_message.is_none() {
+                self.furthest_message = message.cloned();
+            }
             let exp = expected.to_string();
             if !self.furthest_expected.contains(&exp) {
                 self.furthest_expected.push(exp);
@@ -173,36 +416,23 @@ impl<'a> Parser<'a> {
             found,
             context_rule: self.furthest_rule.clone(),
             source_line,
+            message: self.furthest_message.clone(),
         }
     }
 
-    /// バイト位置から行番号と列番号を計算
-    fn pos_to_line_col(&self, pos: usize) -> (usize, usize) {
-        let mut line = 1;
-        let mut col = 1;
-
-        for (i, ch) in self.input.chars().enumerate() {
-            if i >= pos {
-                break;
-            }
-            if ch == '\n' {
-                line += 1;
-                col = 1;
-            } else {
-                col += 1;
-            }
-        }
+    fn line_index(&self) -> &LineIndex {
+        self.line_index.get_or_init(|| LineIndex::new(&self.input))
+    }
 
-        (line, col)
+    /// バイト位置から行番号と列番号 (1始まり、列は文字単位) を計算
+    fn pos_to_line_col(&self, pos: usize) -> (usize, usize) {
+        let (line, column) = self.line_index().line_col(&self.input, pos, ColumnEncoding::Char);
+        (line + 1, column + 1)
     }
 
     /// 指定行のソースコードを取得
     fn get_source_line(&self, line_num: usize) -> String {
-        self.input
-            .lines()
-            .nth(line_num - 1)
-            .unwrap_or("")
-            .to_string()
+        self.line_index().line_text(&self.input, line_num - 1).to_string()
     }
 
     /// エラー位置で見つかったテキストを取得
@@ -233,24 +463,6 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// 空白をスキップ (インデントトラッキングなし)
-    fn skip_whitespace(&mut self) {
-        while self.pos < self.input.len() {
-            let ch = self.input[self.pos..].chars().next().unwrap();
-            if ch.is_whitespace() {
-                if ch == '\n' {
-                    self.at_line_start = true;
-                }
-                self.pos += ch.len_utf8();
-            } else {
-                break;
-            }
-        }
-        if self.at_line_start {
-            self.update_line_indent();
-        }
-    }
-
     fn remaining(&self) -> &str {
         &self.input[self.pos..]
     }
@@ -261,18 +473,220 @@ impl<'a> Parser<'a> {
         let expr = rule.expr.clone();
 
         let start_pos = self.pos;
+        let memo_key = self.memo_key(rule_name);
+        if let Some(entry) = memo_key.as_ref().and_then(|key| self.memo.as_ref()?.get(key)) {
+            let (node, end, furthest) = (entry.node.clone(), entry.end.clone(), entry.furthest.clone());
+            self.record_trace(rule_name, TraceKind::Enter, start_pos);
+            self.load_state(&end);
+            self.record_trace(rule_name, TraceKind::Success(self.pos), start_pos);
+            self.replay_furthest(furthest);
+            self.reused += 1;
+            return Some(node);
+        }
+
+        // 同期ルールでは、このルールの中で起きたエラーだけを報告する
+        let recovery = if self.can_recover(rule_name) {
+            Some((self.save_state(), self.take_furthest()))
+        } else {
+            None
+        };
+        // 結果を覚えておく場合は、このルールの中で記録したエラー情報も一緒に覚える
+        let memo_outer = memo_key.as_ref().map(|_| self.take_furthest());
+        let errors_before = self.errors.len();
+        self.record_trace(rule_name, TraceKind::Enter, start_pos);
+        self.depth += 1;
         let result = self.parse_expr(&expr, rule_name);
+        self.depth -= 1;
 
         if let Some(mut node) = result {
+            self.record_trace(rule_name, TraceKind::Success(self.pos), start_pos);
             // ルール名で葉ノードの値を設定
             if node.children.is_empty() && node.value.is_empty() {
                 node.value = self.input[start_pos..self.pos].to_string();
             }
             node.name = rule_name.to_string();
+            // 先頭の空白 (インデントや空行) はノードの範囲に含めない
+            let matched = &self.input[start_pos..self.pos];
+            let start = start_pos + matched.len() - matched.trim_start().len();
+            node.span = Some(Span { start, end: self.pos });
+            if let (Some(key), Some(outer)) = (memo_key, memo_outer) {
+                let furthest = self.restore_furthest(outer);
+                // 回復したエラーを含む結果は覚えない
+                if self.errors.len() == errors_before {
+                    let examined = self.examined_end(self.pos.max(furthest.0));
+                    let entry = MemoEntry { node: node.clone(), end: self.save_state(), furthest, examined };
+                    self.memo.get_or_insert_with(HashMap::new).insert(key, entry);
+                }
+            }
+            if let Some((_, outer)) = recovery {
+                self.merge_furthest(outer);
+            }
             Some(node)
         } else {
             self.pos = start_pos;
-            None
+            self.record_trace(rule_name, TraceKind::Fail, start_pos);
+            if let Some(outer) = memo_outer {
+                self.restore_furthest(outer);
+            }
+            let (state, outer) = recovery?;
+
+            self.restore_state(&state, rule_name);
+            self.errors.push(self.build_error());
+            self.skip_to_sync_point();
+
+            let skipped = &self.input[start_pos..self.pos];
+            let start = start_pos + skipped.len() - skipped.trim_start().len();
+            let mut node = ASTNode::error(rule_name, skipped.trim());
+            node.span = Some(Span { start, end: self.pos });
+
+            // 読み飛ばした後のエラーは、そこから数え直す
+            self.furthest_pos = self.pos;
+            self.merge_furthest(outer);
+            Some(node)
+        }
+    }
+
+    /// ルールの結果を再利用するためのキー
+    /// インクリメンタルパースでない場合や、外側の注釈 (@label / @expect) がかかる位置では None
+    fn memo_key(&self, rule_name: &str) -> Option<MemoKey> {
+        self.memo.as_ref()?;
+        if self.annotations.iter().any(|(_, token, _)| *token >= self.pos) {
+            return None;
+        }
+        Some(MemoKey {
+            rule: rule_name.to_string(),
+            pos: self.pos,
+            indent_stack: self.indent_stack.clone(),
+            pending_dedents: self.pending_dedents,
+            at_line_start: self.at_line_start,
+            current_line_indent: self.current_line_indent,
+        })
+    }
+
+    /// 位置 reached まで読んだルールの結果が依存する範囲の終わり
+    /// 正規表現の先読みはその行の中で決まるものとして、行末 (改行の後) までとする
+    /// 入力の終わりまで読んだ場合は、末尾への追加でも結果が変わりうるので入力の長さより後ろ
+    fn examined_end(&self, reached: usize) -> usize {
+        self.input[reached..].find('\n').map_or(self.input.len() + 1, |i| reached + i + 1)
+    }
+
+    /// このルールの失敗から回復できるか
+    fn can_recover(&self, rule_name: &str) -> bool {
+        if !self.recovery_rules.iter().any(|r| r == rule_name) {
+            return false;
+        }
+        // 入力の終わりや、ブロックを抜けるところ (DEDENT) では回復しない
+        if self.remaining().trim().is_empty() || self.pending_dedents > 0 {
+            return false;
+        }
+        if self.at_line_content_start() {
+            self.current_line_indent >= *self.indent_stack.last().unwrap_or(&0)
+        } else {
+            // 行の途中では、読み飛ばすものが残っている場合だけ
+            !self.remaining().lines().next().unwrap_or("").trim().is_empty()
+        }
+    }
+
+    /// 現在位置より前に、その行の空白しかないか
+    /// (INDENT や SAME_INDENT はインデントを消費するので at_line_start では判断できない)
+    fn at_line_content_start(&self) -> bool {
+        let line_start = self.input[..self.pos].rfind('\n').map_or(0, |i| i + 1);
+        self.input[line_start..self.pos].trim().is_empty()
+    }
+
+    /// 次の同期点まで読み飛ばす
+    /// 行の途中なら次の NEWLINE の手前まで、行頭なら同じかより浅いインデントの次の行まで
+    /// (深くインデントされた後続行は、読み飛ばした行のブロックとして一緒に読み飛ばす)
+    fn skip_to_sync_point(&mut self) {
+        if !self.at_line_content_start() {
+            self.pos = self.remaining().find('\n').map_or(self.input.len(), |i| self.pos + i);
+            return;
+        }
+
+        let level = *self.indent_stack.last().unwrap_or(&0);
+        loop {
+            self.pos = self.remaining().find('\n').map_or(self.input.len(), |i| self.pos + i + 1);
+            self.at_line_start = true;
+            self.update_line_indent();
+            if self.remaining().is_empty() {
+                break;
+            }
+            let line = self.remaining().lines().next().unwrap_or("");
+            if !line.trim().is_empty() && self.current_line_indent <= level {
+                break;
+            }
+        }
+    }
+
+    /// 最も遠くまで進んだ位置の情報を取り出し、現在位置から数え直す
+    fn take_furthest(&mut self) -> Furthest {
+        let furthest = (
+            self.furthest_pos,
+            std::mem::take(&mut self.furthest_expected),
+            std::mem::take(&mut self.furthest_rule),
+            self.furthest_message.take(),
+        );
+        self.furthest_pos = self.pos;
+        furthest
+    }
+
+    /// 取り出しておいた外側の情報に戻し、その後にルールの中で記録した情報を記録し直す
+    /// 戻り値はルールの中で記録した情報
+    fn restore_furthest(&mut self, (pos, expected, rule, message): Furthest) -> Furthest {
+        let inner = self.take_furthest();
+        self.furthest_pos = pos;
+        self.furthest_expected = expected;
+        self.furthest_rule = rule;
+        self.furthest_message = message;
+        self.replay_furthest(inner.clone());
+        inner
+    }
+
+    /// ルールの中で記録したエラー情報を、record_error と同じ規則で記録し直す
+    fn replay_furthest(&mut self, (pos, expected, rule, message): Furthest) {
+        let mut expected = expected.into_iter();
+        let Some(first) = expected.next() else { return };
+        if pos > self.furthest_pos {
+            self.furthest_pos = pos;
+            self.furthest_expected = vec![first];
+            self.furthest_rule = rule;
+            self.furthest_message = message;
+        } else if pos == self.furthest_pos {
+            if self.furthest_rule.is_empty() {
+                self.furthest_rule = rule;
+            }
+            if self.furthest_message.is_none() {
+                self.furthest_message = message;
+            }
+            if !self.furthest_expected.contains(&first) {
+                self.furthest_expected.push(first);
+            }
+        } else {
+            return;
+        }
+        for exp in expected {
+            if !self.furthest_expected.contains(&exp) {
+                self.furthest_expected.push(exp);
+            }
+        }
+    }
+
+    /// 取り出しておいた情報と、より遠い方を残す
+    fn merge_furthest(&mut self, (pos, expected, rule, message): Furthest) {
+        if pos > self.furthest_pos || (pos == self.furthest_pos && self.furthest_expected.is_empty()) {
+            self.furthest_pos = pos;
+            self.furthest_expected = expected;
+            self.furthest_rule = rule;
+            self.furthest_message = message;
+        } else if pos == self.furthest_pos {
+            if self.furthest_message.is_none() {
+                self.furthest_message = message;
+            }
+            for exp in expected {
+                if !self.furthest_expected.contains(&exp) {
+                    self.furthest_expected.push(exp);
+                }
+            }
         }
     }
 
@@ -303,6 +717,15 @@ impl<'a> Parser<'a> {
             GrammarExpr::Dedent => self.parse_dedent(context_rule),
             GrammarExpr::Newline => self.parse_newline(context_rule),
             GrammarExpr::SameIndent => self.parse_same_indent(context_rule),
+            GrammarExpr::Annotated { inner, annotation } => {
+                // 注釈は、式の最初のトークン (行内の空白の後) で失敗したときに使う
+                let rest = self.remaining();
+                let token = self.pos + rest.len() - rest.trim_start_matches([' ', '\t', '\r']).len();
+                self.annotations.push((self.pos, token, annotation.clone()));
+                let result = self.parse_expr(inner, context_rule);
+                self.annotations.pop();
+                result
+            }
         }
     }
 
@@ -439,16 +862,8 @@ impl<'a> Parser<'a> {
     fn parse_pattern(&mut self, pattern: &str, context_rule: &str) -> Option<ASTNode> {
         self.skip_whitespace_no_newline();
 
-        // 正規表現をキャッシュから取得または作成
-        let regex_pattern = format!("^{}", pattern);
-        let regex = if let Some(r) = self.regex_cache.get(&regex_pattern) {
-            r.clone()
-        } else {
-            let r = Regex::new(&regex_pattern).expect("Invalid regex pattern");
-            self.regex_cache.insert(regex_pattern.clone(), r.clone());
-            r
-        };
-
+        // 正規表現は文法を読み込んだときにコンパイル済み
+        let regex = self.grammar.pattern(pattern);
         if let Some(m) = regex.find(self.remaining()) {
             let matched = m.as_str().to_string();
             self.pos += matched.len();
@@ -461,11 +876,7 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_sequence(&mut self, items: &[GrammarExpr], context_rule: &str) -> Option<ASTNode> {
-        let start_pos = self.pos;
-        let start_indent_stack = self.indent_stack.clone();
-        let start_pending_dedents = self.pending_dedents;
-        let start_at_line_start = self.at_line_start;
-        let start_current_line_indent = self.current_line_indent;
+        let state = self.save_state();
 
         let mut node = ASTNode::new(context_rule);
 
@@ -486,11 +897,7 @@ impl<'a> Parser<'a> {
                 }
             } else {
                 // パース失敗、バックトラック
-                self.pos = start_pos;
-                self.indent_stack = start_indent_stack;
-                self.pending_dedents = start_pending_dedents;
-                self.at_line_start = start_at_line_start;
-                self.current_line_indent = start_current_line_indent;
+                self.restore_state(&state, context_rule);
                 return None;
             }
         }
@@ -499,11 +906,7 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_choice(&mut self, choices: &[GrammarExpr], context_rule: &str) -> Option<ASTNode> {
-        let start_pos = self.pos;
-        let start_indent_stack = self.indent_stack.clone();
-        let start_pending_dedents = self.pending_dedents;
-        let start_at_line_start = self.at_line_start;
-        let start_current_line_indent = self.current_line_indent;
+        let state = self.save_state();
 
         for choice in choices {
             if let Some(child) = self.parse_expr(choice, context_rule) {
@@ -527,11 +930,7 @@ impl<'a> Parser<'a> {
                 return Some(node);
             }
             // バックトラック
-            self.pos = start_pos;
-            self.indent_stack = start_indent_stack.clone();
-            self.pending_dedents = start_pending_dedents;
-            self.at_line_start = start_at_line_start;
-            self.current_line_indent = start_current_line_indent;
+            self.restore_state(&state, context_rule);
         }
 
         None
@@ -545,11 +944,7 @@ impl<'a> Parser<'a> {
         let mut node = ASTNode::new("_repeat");
 
         loop {
-            let start_pos = self.pos;
-            let start_indent_stack = self.indent_stack.clone();
-            let start_pending_dedents = self.pending_dedents;
-            let start_at_line_start = self.at_line_start;
-            let start_current_line_indent = self.current_line_indent;
+            let state = self.save_state();
 
             if let Some(child) = self.parse_expr(inner, context_rule) {
                 if !child.name.starts_with('_') {
@@ -563,11 +958,7 @@ impl<'a> Parser<'a> {
                     }
                 }
             } else {
-                self.pos = start_pos;
-                self.indent_stack = start_indent_stack;
-                self.pending_dedents = start_pending_dedents;
-                self.at_line_start = start_at_line_start;
-                self.current_line_indent = start_current_line_indent;
+                self.restore_state(&state, context_rule);
                 break;
             }
         }
@@ -596,11 +987,7 @@ impl<'a> Parser<'a> {
 
         // 残りは0回以上
         loop {
-            let loop_start = self.pos;
-            let start_indent_stack = self.indent_stack.clone();
-            let start_pending_dedents = self.pending_dedents;
-            let start_at_line_start = self.at_line_start;
-            let start_current_line_indent = self.current_line_indent;
+            let state = self.save_state();
 
             if let Some(child) = self.parse_expr(inner, context_rule) {
                 if !child.name.starts_with('_') {
@@ -613,11 +1000,7 @@ impl<'a> Parser<'a> {
                     }
                 }
             } else {
-                self.pos = loop_start;
-                self.indent_stack = start_indent_stack;
-                self.pending_dedents = start_pending_dedents;
-                self.at_line_start = start_at_line_start;
-                self.current_line_indent = start_current_line_indent;
+                self.restore_state(&state, context_rule);
                 break;
             }
         }
@@ -626,20 +1009,12 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_optional(&mut self, inner: &GrammarExpr, context_rule: &str) -> Option<ASTNode> {
-        let start_pos = self.pos;
-        let start_indent_stack = self.indent_stack.clone();
-        let start_pending_dedents = self.pending_dedents;
-        let start_at_line_start = self.at_line_start;
-        let start_current_line_indent = self.current_line_indent;
+        let state = self.save_state();
 
         if let Some(child) = self.parse_expr(inner, context_rule) {
             Some(child)
         } else {
-            self.pos = start_pos;
-            self.indent_stack = start_indent_stack;
-            self.pending_dedents = start_pending_dedents;
-            self.at_line_start = start_at_line_start;
-            self.current_line_indent = start_current_line_indent;
+            self.restore_state(&state, context_rule);
             // 空のノードを返す (optionalなのでOK)
             Some(ASTNode::new("_optional_empty"))
         }
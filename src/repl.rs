@@ -0,0 +1,240 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::cache::{CompiledGrammars, LoadError};
+use crate::diagnostics::{Diagnostic, Renderer};
+use crate::generator::Generator;
+use crate::meta_parser::{InputGrammar, OutputGrammar};
+use crate::parser::Parser;
+
+const PROMPT: &str = "hensan> ";
+const CONTINUATION_PROMPT: &str = "   ...> ";
+
+const HELP: &str = "\
+Type source code to parse it and translate it with the output grammar.
+A line ending with ':' starts a block; finish it with an empty line.
+
+Commands:
+  :rule NAME   Parse from rule NAME (`:rule` alone restores the start rule)
+  :rules       List the rules of the input grammar
+  :reload      Reload the grammar files
+  :trace       Toggle parse tracing
+  :help        Show this help
+  :quit        Exit
+";
+
+/// 文法開発用の対話環境
+/// 入力したソースをパースし、AST と出力BNFで生成したコードをその場で表示する
+pub struct Repl {
+    input_path: String,
+    output_path: String,
+    input: InputGrammar,
+    output: OutputGrammar,
+    /// :rule で指定した開始ルール
+    start_rule: Option<String>,
+    trace: bool,
+    /// 文法を読み込んだときのキャッシュの警告 (次の出力の前に書く)
+    warnings: Vec<String>,
+}
+
+impl Repl {
+    /// 文法ファイルを読み込んで対話環境を作る
+    pub fn load(input_path: &str, output_path: &str) -> Result<Self, LoadError> {
+        let compiled = load_grammars(input_path, output_path)?;
+        Ok(Repl {
+            input_path: input_path.to_string(),
+            output_path: output_path.to_string(),
+            input: compiled.input,
+            output: compiled.output,
+            start_rule: None,
+            trace: false,
+            warnings: compiled.warnings,
+        })
+    }
+
+    /// :quit か入力の終わりまで対話を続ける
+    pub fn run(&mut self, mut reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+        for warning in self.warnings.drain(..) {
+            writeln!(writer, "Warning: {}", warning)?;
+        }
+        writeln!(writer, "hensan {} ({} rules). Type :help for help.", env!("CARGO_PKG_VERSION"), self.input.rules.len())?;
+
+        loop {
+            write!(writer, "{}", PROMPT)?;
+            writer.flush()?;
+            let Some(line) = read_line(&mut reader)? else { break };
+
+            if let Some(command) = line.trim().strip_prefix(':') {
+                if !self.command(command, &mut writer)? {
+                    break;
+                }
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // ':' で終わる行はブロックの始まり。空行までを1つのソースとして読む
+            let mut source = line;
+            if source.trim_end().ends_with(':') {
+                loop {
+                    write!(writer, "{}", CONTINUATION_PROMPT)?;
+                    writer.flush()?;
+                    match read_line(&mut reader)? {
+                        Some(next) if !next.trim().is_empty() => {
+                            source.push('\n');
+                            source.push_str(&next);
+                        }
+                        _ => break,
+                    }
+                }
+                // ブロックを閉じる DEDENT のため、最後の行も改行で終える
+                source.push('\n');
+            }
+
+            self.evaluate(&source, &mut writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// コマンドを実行 (false なら終了)
+    fn command(&mut self, command: &str, writer: &mut impl Write) -> io::Result<bool> {
+        let mut words = command.split_whitespace();
+        match (words.next().unwrap_or(""), words.next()) {
+            ("quit" | "q" | "exit", _) => return Ok(false),
+            ("help" | "h", _) => write!(writer, "{}", HELP)?,
+            ("rule", None) => {
+                self.start_rule = None;
+                writeln!(writer, "Parsing from the start rule '{}'", self.input.start_rule)?;
+            }
+            ("rule", Some(name)) if self.input.rules.contains_key(name) => {
+                self.start_rule = Some(name.to_string());
+                writeln!(writer, "Parsing from rule '{}'", name)?;
+            }
+            ("rule", Some(name)) => writeln!(writer, "Unknown rule '{}' (see :rules)", name)?,
+            ("rules", _) => {
+                for name in &self.input.order {
+                    let marker = if Some(name) == self.start_rule.as_ref() || (self.start_rule.is_none() && *name == self.input.start_rule) {
+                        " *"
+                    } else {
+                        ""
+                    };
+                    writeln!(writer, "  {}{}", name, marker)?;
+                }
+            }
+            ("reload", _) => match load_grammars(&self.input_path, &self.output_path) {
+                Ok(compiled) => {
+                    for warning in &compiled.warnings {
+                        writeln!(writer, "Warning: {}", warning)?;
+                    }
+                    self.input = compiled.input;
+                    self.output = compiled.output;
+                    // 指定中の開始ルールがなくなっていたら元に戻す
+                    self.start_rule = self.start_rule.take().filter(|r| self.input.rules.contains_key(r));
+                    writeln!(writer, "Reloaded {} and {} ({} rules)", self.input_path, self.output_path, self.input.rules.len())?;
+                }
+                // 読み込みに失敗したら前の文法を使い続ける
+                Err(e) => writeln!(writer, "{}", e)?,
+            },
+            ("trace", _) => {
+                self.trace = !self.trace;
+                writeln!(writer, "Tracing {}", if self.trace { "on" } else { "off" })?;
+            }
+            (other, _) => writeln!(writer, "Unknown command ':{}' (type :help for help)", other)?,
+        }
+        Ok(true)
+    }
+
+    /// ソースをパースして AST と生成コードを表示
+    fn evaluate(&self, source: &str, writer: &mut impl Write) -> io::Result<()> {
+        let mut parser = Parser::new(&self.input, source);
+        if let Some(rule) = &self.start_rule {
+            parser = parser.with_start_rule(rule);
+        }
+        if self.trace {
+            parser = parser.with_trace();
+        }
+        let result = parser.parse();
+
+        if self.trace {
+            write!(writer, "{}", parser.trace_log())?;
+        }
+
+        match result {
+            Ok(ast) => {
+                writeln!(writer, "ast:    {}", ast.to_sexp())?;
+                let output = Generator::new(&self.output).generate(&ast);
+                writeln!(writer, "output: {}", output.replace('\n', "\n        "))?;
+            }
+            Err(err) => writeln!(writer, "{}", Renderer::new("<input>", source).render(&Diagnostic::from_parse_error(&err, &self.input, source)))?,
+        }
+        Ok(())
+    }
+}
+
+/// 入力・出力BNFを読み込む
+fn load_grammars(input_path: &str, output_path: &str) -> Result<CompiledGrammars, LoadError> {
+    CompiledGrammars::load(Path::new(input_path), Path::new(output_path), true)
+}
+
+/// 1行読む (入力の終わりなら None)
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const INPUT: &str = r#"
+        block    := stmt+;
+        stmt     := SAME_INDENT (if_stmt | call) NEWLINE?;
+        if_stmt  := "if" name ":" NEWLINE INDENT block DEDENT;
+        call     := name "(" ")";
+        name     := "[a-z]+";
+    "#;
+    const OUTPUT: &str = r#"
+        block    := stmt join "\n";
+        stmt     := if_stmt | call;
+        if_stmt  := "if (" name ") { " block " }";
+        call     := name "();";
+    "#;
+
+    fn session(name: &str, commands: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("hensan-repl-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input_path = dir.join("input.bnf");
+        let output_path = dir.join("output.bnf");
+        fs::write(&input_path, INPUT).unwrap();
+        fs::write(&output_path, OUTPUT).unwrap();
+
+        let mut repl = Repl::load(input_path.to_str().unwrap(), output_path.to_str().unwrap()).unwrap();
+        let mut output = Vec::new();
+        repl.run(commands.as_bytes(), &mut output).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_evaluate_block() {
+        let output = session("block", "if x:\n    go()\n\n");
+        assert!(output.contains("output: if (x) { go(); }"), "{}", output);
+    }
+
+    #[test]
+    fn test_commands() {
+        let output = session("commands", ":rule call\nf()\n:rule nope\n:trace\nf()\n:quit\nignored()\n");
+        assert!(output.contains("Parsing from rule 'call'"));
+        assert!(output.contains("output: f();"));
+        assert!(output.contains("Unknown rule 'nope'"));
+        assert!(output.contains("Tracing on"));
+        assert!(output.contains("  name ok 1:1-1:2"));
+        assert!(!output.contains("ignored"));
+    }
+}
@@ -0,0 +1,146 @@
+use crate::ast::{ASTNode, Span};
+use crate::cache::{CompiledGrammars, LoadError};
+use crate::config::Pass;
+use crate::diagnostics::{Diagnostic, Renderer};
+use crate::generator::{GenerationTrace, Generator};
+use crate::meta_parser::{InputGrammar, OutputGrammar};
+use crate::parser::Parser;
+
+/// AST を書き換えるパス
+/// 中間の出力BNFで AST をテキストにし、中間の入力BNFでパースし直す
+/// (構文糖の展開や正規化を、最後の出力BNFの前に済ませておける)
+pub struct RewritePass {
+    /// 表示用の名前 (中間の入力BNFのパス)
+    pub name: String,
+    pub output: OutputGrammar,
+    pub input: InputGrammar,
+}
+
+impl RewritePass {
+    pub fn new(name: &str, output: OutputGrammar, input: InputGrammar) -> Self {
+        RewritePass { name: name.to_string(), output, input }
+    }
+
+    /// パスを1つ適用する
+    /// 書き換えた AST の範囲は、中間のテキストではなく元の入力ソースを指す
+    /// 中間のテキストをパースできなければ、そのテキストでの診断を返す
+    pub fn apply(&self, ast: &ASTNode) -> Result<ASTNode, String> {
+        let (text, trace) = Generator::new(&self.output).generate_traced(ast);
+        let mut rewritten = Parser::new(&self.input, &text).parse().map_err(|err| {
+            let diagnostic = Diagnostic::from_parse_error(&err, &self.input, &text)
+                .with_note(&format!("in the text generated by rewrite pass `{}`", self.name));
+            Renderer::new(&format!("<{}>", self.name), &text).render(&diagnostic)
+        })?;
+        map_spans(&mut rewritten, &trace);
+        Ok(rewritten)
+    }
+}
+
+/// ノードとその子孫の中間のテキスト中の範囲を、そこを出力した元の AST ノードの範囲を合わせた範囲に置き換える
+/// (元のノードに範囲がなければ範囲なし)
+fn map_spans(node: &mut ASTNode, trace: &GenerationTrace) {
+    node.span = node.span.and_then(|span| {
+        // 区間は出力順に隙間なく並んでいる
+        let first = trace.spans.partition_point(|generated| generated.end <= span.start);
+        let sources = trace.spans[first..].iter().take_while(|generated| generated.start < span.end.max(span.start + 1));
+        sources.map(|generated| generated.source).reduce(|a, b| {
+            let (a, b) = (a?, b?);
+            Some(Span { start: a.start.min(b.start), end: a.end.max(b.end) })
+        })?
+    });
+    for children in node.children.values_mut() {
+        for child in children {
+            map_spans(child, trace);
+        }
+    }
+}
+
+/// hensan.toml のパスの文法を読み込む (use_cache ならコンパイル済み文法のキャッシュを使う)
+/// キャッシュの警告は warnings に加える
+pub fn load_passes(passes: &[Pass], use_cache: bool, warnings: &mut Vec<String>) -> Result<Vec<RewritePass>, LoadError> {
+    passes
+        .iter()
+        .map(|pass| {
+            let compiled = CompiledGrammars::load(&pass.input, &pass.output, use_cache)?;
+            warnings.extend(compiled.warnings);
+            Ok(RewritePass::new(&pass.input.display().to_string(), compiled.output, compiled.input))
+        })
+        .collect()
+}
+
+/// パスを順に適用する
+pub fn apply_passes(passes: &[RewritePass], ast: ASTNode) -> Result<ASTNode, String> {
+    passes.iter().try_fold(ast, |ast, pass| pass.apply(&ast))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta_parser::MetaParser;
+
+    #[test]
+    fn test_apply_passes() {
+        let input = MetaParser::new(
+            r#"
+            program := stmt+;
+            stmt    := incr | call;
+            incr    := "inc" name NEWLINE;
+            call    := name "(" ")" NEWLINE;
+            name    := "[a-z]+";
+            "#,
+        )
+        .parse_input_grammar().unwrap();
+
+        // inc x を x += 1 に展開するパス
+        let desugar = RewritePass::new(
+            "desugar",
+            MetaParser::new(
+                r#"
+                program := stmt join "\n";
+                stmt    := incr | call;
+                incr    := name " += 1";
+                call    := name "()";
+                "#,
+            )
+            .parse_output_grammar().unwrap(),
+            MetaParser::new(
+                r#"
+                program := stmt (NEWLINE stmt)* NEWLINE?;
+                stmt    := assign | call;
+                assign  := name "\+=" value;
+                call    := name "(" ")";
+                name    := "[a-z]+";
+                value   := "[0-9]+";
+                "#,
+            )
+            .parse_input_grammar().unwrap(),
+        );
+        let output = MetaParser::new(
+            r#"
+            program := stmt join "\n";
+            stmt    := assign | call;
+            assign  := name " = " name " + " value ";";
+            call    := name "();";
+            "#,
+        )
+        .parse_output_grammar().unwrap();
+
+        let ast = Parser::new(&input, "inc x\nf()\n").parse().unwrap();
+        let rewritten = apply_passes(std::slice::from_ref(&desugar), ast).unwrap();
+        assert_eq!(Generator::new(&output).generate(&rewritten), "x = x + 1;\nf();");
+        assert_eq!(apply_passes(&[], Parser::new(&input, "f()\n").parse().unwrap()).unwrap().to_sexp(), "(program (stmt (call (name \"f\"))))");
+
+        // 書き換えた AST の範囲は元のソースを指す (中間のテキスト "x += 1\nf()" ではない)
+        assert_eq!(rewritten.span, Some(Span { start: 0, end: 10 }));
+        let stmts = rewritten.get_children("stmt");
+        assert_eq!(stmts[0].get_child("assign").unwrap().span, Some(Span { start: 0, end: 6 }));
+        assert_eq!(stmts[1].get_child("call").unwrap().get_child("name").unwrap().span, Some(Span { start: 6, end: 7 }));
+
+        // 中間のテキストをパースできなければ、そのテキストでの診断にする
+        let broken = RewritePass::new("broken", MetaParser::new("program := stmt join \";\";\nstmt := incr | call;\nincr := name;\ncall := name;").parse_output_grammar().unwrap(), desugar.input);
+        let ast = Parser::new(&input, "inc x\nf()\n").parse().unwrap();
+        let err = apply_passes(&[broken], ast).unwrap_err();
+        assert!(err.contains(" --> <broken>:1:2"), "{}", err);
+        assert!(err.contains("= note: in the text generated by rewrite pass `broken`"), "{}", err);
+    }
+}
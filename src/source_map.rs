@@ -0,0 +1,209 @@
+use std::fmt::Write;
+
+use serde_json::json;
+
+use crate::generator::GenerationTrace;
+use crate::line_index::{ColumnEncoding, LineIndex};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// テキスト中の位置 (行・列とも0始まり)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    /// 文字単位の列
+    pub column: usize,
+    /// UTF-16 単位の列 (Source Map v3 の列)
+    pub utf16_column: usize,
+}
+
+/// 生成コードの位置と、それを生成した AST ノードの入力ソース中の位置の対応
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    pub generated: Position,
+    pub source: Position,
+    /// 出力したルール
+    pub rule: String,
+}
+
+/// 生成コードから入力ソースへのソースマップ
+pub struct SourceMap {
+    pub mappings: Vec<Mapping>,
+}
+
+impl SourceMap {
+    /// 生成トレースの区間からソースマップを作る
+    /// 区間の先頭と、区間内で改行した後の各行の先頭を、AST ノードの開始位置に対応づける
+    pub fn new(output: &str, source: &str, trace: &GenerationTrace) -> Self {
+        let generated_index = LineIndex::new(output);
+        let source_index = LineIndex::new(source);
+        let position = |index: &LineIndex, text: &str, offset: usize| {
+            let (line, column) = index.line_col(text, offset, ColumnEncoding::Char);
+            Position { line, column, utf16_column: index.line_col(text, offset, ColumnEncoding::Utf16).1 }
+        };
+
+        let mut mappings: Vec<Mapping> = Vec::new();
+        for span in &trace.spans {
+            // JSON から読んだ AST などには入力位置がない
+            let Some(node_span) = span.source else { continue };
+            let source_position = position(&source_index, source, node_span.start);
+
+            let line_starts = output[span.start..span.end]
+                .match_indices('\n')
+                .map(|(i, _)| span.start + i + 1)
+                .filter(|&offset| offset < span.end);
+            for offset in std::iter::once(span.start).chain(line_starts) {
+                let generated = position(&generated_index, output, offset);
+                // 同じ行で同じ位置を指すだけの対応は省く
+                if let Some(last) = mappings.last() {
+                    if last.generated.line == generated.line && last.source == source_position {
+                        continue;
+                    }
+                }
+                mappings.push(Mapping { generated, source: source_position, rule: span.rule.clone() });
+            }
+        }
+
+        SourceMap { mappings }
+    }
+
+    /// Source Map v3 の JSON にする
+    /// file: 生成ファイル名、source_name: 入力ソースのファイル名
+    pub fn to_json(&self, file: &str, source_name: &str, source: &str) -> String {
+        let mut names: Vec<&str> = Vec::new();
+        let mut encoded = String::new();
+        let mut line = 0;
+        let mut previous_column = 0;
+        // 列以外のフィールドは行をまたいで前のセグメントからの差分
+        let (mut previous_source_line, mut previous_source_column, mut previous_name) = (0, 0, 0);
+
+        for mapping in &self.mappings {
+            if mapping.generated.line > line {
+                encoded.push_str(&";".repeat(mapping.generated.line - line));
+                line = mapping.generated.line;
+                previous_column = 0;
+            } else if !encoded.is_empty() && !encoded.ends_with(';') {
+                encoded.push(',');
+            }
+
+            let name = names.iter().position(|n| *n == mapping.rule).unwrap_or_else(|| {
+                names.push(&mapping.rule);
+                names.len() - 1
+            });
+
+            encode_vlq(&mut encoded, mapping.generated.utf16_column as i64 - previous_column as i64);
+            // 入力ソースは1つだけなので、ソースの番号の差分は常に0
+            encode_vlq(&mut encoded, 0);
+            encode_vlq(&mut encoded, mapping.source.line as i64 - previous_source_line as i64);
+            encode_vlq(&mut encoded, mapping.source.utf16_column as i64 - previous_source_column as i64);
+            encode_vlq(&mut encoded, name as i64 - previous_name as i64);
+
+            previous_column = mapping.generated.utf16_column;
+            previous_source_line = mapping.source.line;
+            previous_source_column = mapping.source.utf16_column;
+            previous_name = name;
+        }
+
+        let map = json!({
+            "version": 3,
+            "file": file,
+            "sources": [source_name],
+            "sourcesContent": [source],
+            "names": names,
+            "mappings": encoded,
+        });
+        serde_json::to_string_pretty(&map).unwrap()
+    }
+
+    /// 1行に1つの対応を書いた表にする
+    /// "生成行:列 入力行:列 ルール" (行・列とも1始まり、列は文字単位)
+    pub fn to_line_table(&self) -> String {
+        let mut table = String::new();
+        for mapping in &self.mappings {
+            let _ = writeln!(
+                table,
+                "{}:{}\t{}:{}\t{}",
+                mapping.generated.line + 1,
+                mapping.generated.column + 1,
+                mapping.source.line + 1,
+                mapping.source.column + 1,
+                mapping.rule
+            );
+        }
+        table
+    }
+}
+
+/// 値を Base64 VLQ で書く
+fn encode_vlq(out: &mut String, value: i64) {
+    // 最下位ビットが符号
+    let mut rest = if value < 0 { ((-value) << 1) | 1 } else { value << 1 };
+    loop {
+        let mut digit = rest & 0b11111;
+        rest >>= 5;
+        if rest > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64[digit as usize] as char);
+        if rest == 0 {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::Generator;
+    use crate::meta_parser::MetaParser;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_encode_vlq() {
+        let mut out = String::new();
+        for value in [0, 1, -1, 15, 16, -16, 1000] {
+            encode_vlq(&mut out, value);
+            out.push(' ');
+        }
+        assert_eq!(out, "A C D e gB hB w+B ");
+    }
+
+    #[test]
+    fn test_source_map() {
+        let input = MetaParser::new(
+            r#"
+            program := func+;
+            func    := "def" name "(" ")" ":" NEWLINE INDENT body DEDENT;
+            body    := "pass" NEWLINE;
+            name    := "[a-zé]+";
+            "#,
+        )
+        .parse_input_grammar().unwrap();
+        let output = MetaParser::new(
+            r#"
+            program := func join "\n";
+            func    := "fn " name "() {\n" body "}";
+            body    := "    // pass\n";
+            "#,
+        )
+        .parse_output_grammar().unwrap();
+
+        let source = "def é():\n    pass\ndef g():\n    pass\n";
+        let ast = Parser::new(&input, source).parse().unwrap();
+        let (text, trace) = Generator::new(&output).generate_traced(&ast);
+        assert_eq!(text, "fn é() {\n    // pass\n}\nfn g() {\n    // pass\n}");
+
+        let map = SourceMap::new(&text, source, &trace);
+        let table = map.to_line_table();
+        // 2つ目の関数は入力の3行目、その本体は4行目
+        assert!(table.contains("4:1\t3:1\tfunc\n"), "{}", table);
+        assert!(table.contains("5:1\t4:5\tbody\n"), "{}", table);
+        // 列は文字単位 ("é" は1列)
+        assert!(table.contains("1:4\t1:5\tname\n1:5\t1:1\tfunc\n"), "{}", table);
+
+        let json: serde_json::Value = serde_json::from_str(&map.to_json("out.rs", "in.py", source)).unwrap();
+        assert_eq!(json["version"], 3);
+        assert_eq!(json["sources"][0], "in.py");
+        assert_eq!(json["mappings"].as_str().unwrap().matches(';').count(), text.matches('\n').count());
+    }
+}
@@ -0,0 +1,164 @@
+/// 入力言語のテンプレート
+pub struct SourceLanguage {
+    pub name: &'static str,
+    /// ソースファイルの拡張子
+    pub ext: &'static str,
+    pub grammar: &'static str,
+    /// 例のソース
+    pub example: &'static str,
+    /// 変換できる出力言語 (先頭が --to を省略したときの既定)
+    pub targets: &'static [TargetLanguage],
+}
+
+/// 出力言語のテンプレート
+pub struct TargetLanguage {
+    pub name: &'static str,
+    /// 出力ファイルの拡張子
+    pub ext: &'static str,
+    pub grammar: &'static str,
+    /// 例のソースを変換した結果 (ファイルに書き出すときと同じく末尾に改行がある)
+    pub expected: &'static str,
+}
+
+/// 入力言語と出力言語の組
+pub struct Template {
+    pub source: &'static SourceLanguage,
+    pub target: &'static TargetLanguage,
+}
+
+/// 入力言語を省略したときのテンプレート
+pub const DEFAULT_SOURCE: &str = "c";
+
+/// `hensan init` で使う同梱のテンプレート
+/// 入力言語ごとに文法と例のソースを持ち、出力言語ごとに出力BNFと例の変換結果を持つ
+/// Python → Rust はリポジトリの grammar/ の文法をそのまま使う
+pub const LANGUAGES: &[SourceLanguage] = &[
+    SourceLanguage {
+        name: "python",
+        ext: "py",
+        grammar: include_str!("../grammar/input.bnf"),
+        example: include_str!("../templates/python/example.py"),
+        targets: &[TargetLanguage {
+            name: "rust",
+            ext: "rs",
+            grammar: include_str!("../grammar/output.bnf"),
+            expected: include_str!("../templates/python/example.expected.rs"),
+        }],
+    },
+    SourceLanguage {
+        name: "c",
+        ext: "c",
+        grammar: include_str!("../templates/c/input.bnf"),
+        example: include_str!("../templates/c/example.c"),
+        targets: &[TargetLanguage {
+            name: "rust",
+            ext: "rs",
+            grammar: include_str!("../templates/c/rust.bnf"),
+            expected: include_str!("../templates/c/example.expected.rs"),
+        }],
+    },
+    SourceLanguage {
+        name: "json",
+        ext: "json",
+        grammar: include_str!("../templates/json/input.bnf"),
+        example: include_str!("../templates/json/example.json"),
+        targets: &[
+            TargetLanguage {
+                name: "rust",
+                ext: "rs",
+                grammar: include_str!("../templates/json/rust.bnf"),
+                expected: include_str!("../templates/json/example.expected.rs"),
+            },
+            TargetLanguage {
+                name: "python",
+                ext: "py",
+                grammar: include_str!("../templates/json/python.bnf"),
+                expected: include_str!("../templates/json/example.expected.py"),
+            },
+        ],
+    },
+    SourceLanguage {
+        name: "ini",
+        ext: "ini",
+        grammar: include_str!("../templates/ini/input.bnf"),
+        example: include_str!("../templates/ini/example.ini"),
+        targets: &[
+            TargetLanguage {
+                name: "toml",
+                ext: "toml",
+                grammar: include_str!("../templates/ini/toml.bnf"),
+                expected: include_str!("../templates/ini/example.expected.toml"),
+            },
+            TargetLanguage {
+                name: "python",
+                ext: "py",
+                grammar: include_str!("../templates/ini/python.bnf"),
+                expected: include_str!("../templates/ini/example.expected.py"),
+            },
+        ],
+    },
+];
+
+/// 入力言語と出力言語の名前からテンプレートを選ぶ
+/// 出力言語を省略したら、その入力言語の既定の出力言語にする
+pub fn find(from: &str, to: Option<&str>) -> Result<Template, String> {
+    let source = LANGUAGES.iter().find(|lang| lang.name == from).ok_or_else(|| {
+        let names: Vec<_> = LANGUAGES.iter().map(|lang| lang.name).collect();
+        format!("unknown source language '{}' (available: {})", from, names.join(", "))
+    })?;
+    let target = match to {
+        None => &source.targets[0],
+        Some(to) => source.targets.iter().find(|target| target.name == to).ok_or_else(|| {
+            let names: Vec<_> = source.targets.iter().map(|target| target.name).collect();
+            format!("no template translates {} to '{}' (available: {})", from, to, names.join(", "))
+        })?,
+    };
+    Ok(Template { source, target })
+}
+
+/// テンプレートの一覧 ("python -> rust" を1行ずつ)
+pub fn list() -> String {
+    let mut text = String::new();
+    for source in LANGUAGES {
+        for target in source.targets {
+            text.push_str(&format!("{} -> {}\n", source.name, target.name));
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CompiledGrammars;
+    use crate::generator::Generator;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_examples_translate_to_expected() {
+        for source in LANGUAGES {
+            for target in source.targets {
+                let grammars = CompiledGrammars::compile(source.grammar, target.grammar)
+                    .unwrap_or_else(|(kind, e)| panic!("{} -> {}: {:?} grammar: {}", source.name, target.name, kind, e));
+                let ast = Parser::new(&grammars.input, source.example)
+                    .parse()
+                    .unwrap_or_else(|e| panic!("{} -> {}: {}", source.name, target.name, e));
+                let output = Generator::new(&grammars.output).generate(&ast);
+                assert_eq!(format!("{}\n", output), target.expected, "{} -> {}", source.name, target.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let template = find("json", None).unwrap();
+        assert_eq!((template.source.ext, template.target.name), ("json", "rust"));
+        assert_eq!(find("ini", Some("python")).unwrap().target.ext, "py");
+
+        let err = find("cobol", None).err().unwrap();
+        assert_eq!(err, "unknown source language 'cobol' (available: python, c, json, ini)");
+        let err = find("c", Some("go")).err().unwrap();
+        assert_eq!(err, "no template translates c to 'go' (available: rust)");
+        assert!(list().contains("ini -> toml\n"));
+    }
+}
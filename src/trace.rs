@@ -0,0 +1,174 @@
+use std::fmt::Write;
+
+use crate::line_index::{ColumnEncoding, LineIndex};
+
+/// トレースの種類
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceKind {
+    /// ルールに入った
+    Enter,
+    /// ルールが成功した (終了位置)
+    Success(usize),
+    /// ルールが失敗した
+    Fail,
+    /// 途中まで読み進めた後で失敗し、指定位置まで戻った
+    Backtrack(usize),
+}
+
+/// パーストレースのイベント
+#[derive(Debug, Clone)]
+pub struct TraceEvent {
+    /// ルール呼び出しの深さ
+    pub depth: usize,
+    pub rule: String,
+    pub kind: TraceKind,
+    /// イベントの位置 (バイトオフセット)
+    /// Enter・Success・Fail ではルールの開始位置、Backtrack では失敗した位置
+    pub position: usize,
+    /// イベント時点のインデントスタック
+    pub indent_stack: Vec<usize>,
+    /// イベント時点の保留中のDEDENT数
+    pub pending_dedents: usize,
+}
+
+/// トレースを字下げしたテキストにする
+pub fn format_text(events: &[TraceEvent], source: &str) -> String {
+    let index = LineIndex::new(source);
+    let mut log = String::new();
+    for event in events {
+        let _ = writeln!(log, "{}{}", "  ".repeat(event.depth), describe(event, &index, source));
+    }
+    log
+}
+
+/// トレースを折りたためるルール呼び出しの木として HTML にする
+pub fn format_html(events: &[TraceEvent], source: &str) -> String {
+    let index = LineIndex::new(source);
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>hensan parse trace</title>\n<style>\n");
+    html.push_str(STYLE);
+    html.push_str("</style>\n</head>\n<body>\n<h1>Parse trace</h1>\n");
+
+    // Enter から対応する Success / Fail までを1つの details にする
+    let mut open = 0;
+    for (i, event) in events.iter().enumerate() {
+        match event.kind {
+            TraceKind::Enter => {
+                let result = result_of(events, i);
+                let (class, label) = match result.map(|e| &e.kind) {
+                    Some(TraceKind::Success(end)) => ("ok", format!("ok → {}", line_col(&index, source, *end))),
+                    Some(TraceKind::Fail) => ("fail", "failed".to_string()),
+                    // 途中で打ち切られたトレース
+                    _ => ("fail", "unfinished".to_string()),
+                };
+                // 失敗した呼び出しは、理由を追えるよう開いた状態にする
+                let _ = writeln!(
+                    html,
+                    "<details class=\"{}\"{}><summary><b>{}</b> {} <span class=\"state\">{}</span> <i>{}</i></summary>",
+                    class,
+                    if class == "fail" { " open" } else { "" },
+                    escape(&event.rule),
+                    line_col(&index, source, event.position),
+                    escape(&state(event)),
+                    label
+                );
+                open += 1;
+            }
+            TraceKind::Success(_) | TraceKind::Fail => {
+                if open > 0 {
+                    html.push_str("</details>\n");
+                    open -= 1;
+                }
+            }
+            TraceKind::Backtrack(_) => {
+                let _ = writeln!(html, "<div class=\"backtrack\">{}</div>", escape(&describe(event, &index, source)));
+            }
+        }
+    }
+    for _ in 0..open {
+        html.push_str("</details>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Enter に対応する Success / Fail を探す
+fn result_of(events: &[TraceEvent], enter: usize) -> Option<&TraceEvent> {
+    let depth = events[enter].depth;
+    events[enter + 1..]
+        .iter()
+        .find(|e| e.depth == depth && matches!(e.kind, TraceKind::Success(_) | TraceKind::Fail | TraceKind::Enter))
+        .filter(|e| e.kind != TraceKind::Enter)
+}
+
+/// イベント1つを1行で説明
+fn describe(event: &TraceEvent, index: &LineIndex, source: &str) -> String {
+    let position = line_col(index, source, event.position);
+    match event.kind {
+        TraceKind::Enter => format!("{} {} {}", event.rule, position, state(event)),
+        TraceKind::Success(end) => format!("{} ok {}-{}", event.rule, position, line_col(index, source, end)),
+        TraceKind::Fail => format!("{} failed {}", event.rule, position),
+        TraceKind::Backtrack(to) => {
+            format!("backtrack in {} {} -> {} {}", event.rule, position, line_col(index, source, to), state(event))
+        }
+    }
+}
+
+/// インデントの状態
+fn state(event: &TraceEvent) -> String {
+    format!("indent={:?} dedents={}", event.indent_stack, event.pending_dedents)
+}
+
+/// バイトオフセットを "行:列" にする (1始まり、列は文字単位)
+fn line_col(index: &LineIndex, source: &str, position: usize) -> String {
+    let (line, column) = index.line_col(source, position, ColumnEncoding::Char);
+    format!("{}:{}", line + 1, column + 1)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+const STYLE: &str = r#"body { font-family: monospace; margin: 2em; }
+details { margin-left: 1.5em; border-left: 1px dotted #bbb; padding-left: 0.5em; }
+summary { cursor: pointer; }
+details.ok > summary i { color: #2a7a2a; }
+details.fail > summary i { color: #b22; }
+.state { color: #888; }
+.backtrack { margin-left: 1.5em; color: #a60; }
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta_parser::MetaParser;
+    use crate::parser::Parser;
+
+    const GRAMMAR: &str = r#"
+        block   := stmt+;
+        stmt    := SAME_INDENT (if_stmt | call) NEWLINE?;
+        if_stmt := "if" name ":" NEWLINE INDENT block DEDENT else?;
+        else    := SAME_INDENT "else" ":" NEWLINE INDENT block DEDENT;
+        call    := name "(" ")";
+        name    := "[a-z]+";
+    "#;
+
+    #[test]
+    fn test_trace_records_backtracks_and_indentation() {
+        let grammar = MetaParser::new(GRAMMAR).parse_input_grammar().unwrap();
+        let source = "if x:\n    f()\nelse\n";
+        let mut parser = Parser::new(&grammar, source).with_trace();
+        assert!(parser.parse().is_err());
+
+        let log = format_text(parser.trace(), source);
+        // else 節は ':' がなくて失敗し、インデントの状態が戻される
+        assert!(log.contains("    else 3:1 indent=[0] dedents=0\n"), "{}", log);
+        assert!(log.contains("    else failed 3:1\n"), "{}", log);
+        assert!(log.contains("backtrack in else 3:5 -> 3:1 indent=[0] dedents=0"), "{}", log);
+
+        let html = format_html(parser.trace(), source);
+        assert_eq!(html.matches("<details").count(), html.matches("</details>").count());
+        assert!(html.contains("<details class=\"fail\" open><summary><b>else</b> 3:1"));
+    }
+}
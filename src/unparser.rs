@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::ASTNode;
+use crate::meta_parser::{GrammarExpr, InputGrammar};
+
+/// 逆パースで生成されるトークン
+/// レイアウト (改行・インデント) はレンダリング時に解決する
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// リテラルやキャプチャされた値
+    Text(String),
+    /// 改行 (NEWLINE)
+    Newline,
+    /// インデント増加 (INDENT)
+    Indent,
+    /// インデント減少 (DEDENT)
+    Dedent,
+    /// 現在のインデントレベルで新しい行を開始 (SAME_INDENT)
+    SameIndent,
+    /// 非葉ルール内のパターン (マッチしたテキストは AST に残らないので再生成できない)
+    Unsupported { rule: String, pattern: String },
+}
+
+/// 逆パースできない構文
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnparseError {
+    pub rule: String,
+    pub message: String,
+}
+
+impl fmt::Display for UnparseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cannot unparse rule '{}': {}", self.rule, self.message)
+    }
+}
+
+/// 逆パーサー
+/// 入力BNFだけを使ってASTからソースコードを再生成する
+pub struct Unparser<'a> {
+    grammar: &'a InputGrammar,
+    /// インデント1段あたりのスペース数
+    indent_width: usize,
+}
+
+/// 子ノードの消費位置 (ルール名ごとの次のインデックス)
+type Cursor = HashMap<String, usize>;
+
+impl<'a> Unparser<'a> {
+    pub fn new(grammar: &'a InputGrammar) -> Self {
+        Unparser {
+            grammar,
+            indent_width: 4,
+        }
+    }
+
+    /// インデント幅を設定
+    pub fn with_indent_width(mut self, width: usize) -> Self {
+        self.indent_width = width;
+        self
+    }
+
+    /// ASTからソースコードを再生成
+    /// 再生成に使う部分に、テキストが AST に残らない構文があれば UnparseError を返す
+    pub fn unparse(&self, ast: &ASTNode) -> Result<String, UnparseError> {
+        let tokens = self.tokens(ast);
+        if let Some(Token::Unsupported { rule, pattern }) = tokens.iter().find(|t| matches!(t, Token::Unsupported { .. })) {
+            return Err(UnparseError {
+                rule: rule.clone(),
+                message: format!(
+                    "the text matched by the pattern \"{}\" is not kept in the AST (move the pattern into a rule of its own)",
+                    pattern
+                ),
+            });
+        }
+        Ok(self.render(&tokens))
+    }
+
+    /// ASTからトークン列を生成
+    pub fn tokens(&self, ast: &ASTNode) -> Vec<Token> {
+        let mut out = Vec::new();
+        self.unparse_rule(&ast.name, ast, &mut out);
+        out
+    }
+
+    /// 指定したルールに基づいてトークンを生成
+    fn unparse_rule(&self, rule_name: &str, ast: &ASTNode, out: &mut Vec<Token>) {
+        let rule = match self.grammar.rules.get(rule_name) {
+            Some(rule) => rule,
+            None => {
+                // 入力ルールが見つからない場合は値をそのまま出力
+                push_value(&ast.value, out);
+                return;
+            }
+        };
+
+        if ast.children.is_empty() && !ast.value.is_empty() {
+            // 葉ノード: パターンやリテラルの選択結果は値として保持されている
+            push_value(&ast.value, out);
+            return;
+        }
+
+        // 別名ルール (例: lhs := expr;) はパーサーが参照先のノードを
+        // そのまま改名して返すため、参照先のルールで逆パースする
+        if let Some(target) = alias_target(&rule.expr) {
+            if ast.get_child(target).is_none() {
+                self.unparse_rule(target, ast, out);
+                return;
+            }
+        }
+
+        let mut cursor = Cursor::new();
+        self.unparse_expr(&rule.expr, ast, &mut cursor, out);
+    }
+
+    /// 式に基づいてトークンを生成
+    /// 必要な子ノードが見つからない場合は false を返す (out は呼び出し側で巻き戻す)
+    fn unparse_expr(&self, expr: &GrammarExpr, ast: &ASTNode, cursor: &mut Cursor, out: &mut Vec<Token>) -> bool {
+        match expr {
+            GrammarExpr::Literal(lit) => {
+                out.push(Token::Text(lit.clone()));
+                true
+            }
+
+            // 非葉ルール内のパターンはASTに値が残らないため出力できない
+            // (選択肢を試しているだけかもしれないので、ここではエラーにせず印だけ残す)
+            GrammarExpr::Pattern(pattern) => {
+                out.push(Token::Unsupported { rule: ast.name.clone(), pattern: pattern.clone() });
+                true
+            }
+
+            GrammarExpr::RuleRef(name) => {
+                let index = cursor.get(name).copied().unwrap_or(0);
+                match ast.get_children(name).get(index) {
+                    Some(child) => {
+                        cursor.insert(name.clone(), index + 1);
+                        self.unparse_rule(name, child, out);
+                        true
+                    }
+                    None => false,
+                }
+            }
+
+            GrammarExpr::Sequence(items) => {
+                for item in items {
+                    if !self.unparse_expr(item, ast, cursor, out) {
+                        return false;
+                    }
+                }
+                true
+            }
+
+            GrammarExpr::Choice(choices) => {
+                // 最も多くの子ノードを消費する選択肢を採用する
+                // (例: "[" "]" | "[" call_args "]" で引数付きの方を選ぶ)
+                let mut best: Option<(usize, Cursor, Vec<Token>)> = None;
+                for choice in choices {
+                    let mut trial_cursor = cursor.clone();
+                    let mut trial_out = Vec::new();
+                    if self.unparse_expr(choice, ast, &mut trial_cursor, &mut trial_out) {
+                        let consumed = consumed_count(&trial_cursor);
+                        if best.as_ref().is_none_or(|(n, _, _)| consumed > *n) {
+                            best = Some((consumed, trial_cursor, trial_out));
+                        }
+                    }
+                }
+                match best {
+                    Some((_, best_cursor, best_out)) => {
+                        *cursor = best_cursor;
+                        out.extend(best_out);
+                        true
+                    }
+                    None => false,
+                }
+            }
+
+            GrammarExpr::ZeroOrMore(inner) => {
+                self.unparse_repeat(inner, ast, cursor, out);
+                true
+            }
+
+            GrammarExpr::OneOrMore(inner) => {
+                // パース済みのASTなので、最低1回の出現は子ノードの有無で判定する
+                self.unparse_repeat(inner, ast, cursor, out) > 0
+            }
+
+            GrammarExpr::Optional(inner) => {
+                let before = consumed_count(cursor);
+                let mut trial_cursor = cursor.clone();
+                let mut trial_out = Vec::new();
+                if self.unparse_expr(inner, ast, &mut trial_cursor, &mut trial_out)
+                    && (consumed_count(&trial_cursor) > before || is_layout_only(inner))
+                {
+                    *cursor = trial_cursor;
+                    out.extend(trial_out);
+                }
+                true
+            }
+
+            GrammarExpr::Group(inner) | GrammarExpr::Annotated { inner, .. } => self.unparse_expr(inner, ast, cursor, out),
+
+            GrammarExpr::Indent => {
+                out.push(Token::Indent);
+                true
+            }
+            GrammarExpr::Dedent => {
+                out.push(Token::Dedent);
+                true
+            }
+            GrammarExpr::Newline => {
+                out.push(Token::Newline);
+                true
+            }
+            GrammarExpr::SameIndent => {
+                out.push(Token::SameIndent);
+                true
+            }
+        }
+    }
+
+    /// 子ノードを消費できる限り繰り返し、繰り返した回数を返す
+    fn unparse_repeat(&self, inner: &GrammarExpr, ast: &ASTNode, cursor: &mut Cursor, out: &mut Vec<Token>) -> usize {
+        let mut count = 0;
+        loop {
+            let before = consumed_count(cursor);
+            let mut trial_cursor = cursor.clone();
+            let mut trial_out = Vec::new();
+            if !self.unparse_expr(inner, ast, &mut trial_cursor, &mut trial_out) {
+                break;
+            }
+            // 子ノードを消費しない繰り返しは無限ループになるので打ち切る
+            if consumed_count(&trial_cursor) == before {
+                break;
+            }
+            *cursor = trial_cursor;
+            out.extend(trial_out);
+            count += 1;
+        }
+        count
+    }
+
+    /// トークン列をソースコードに整形
+    pub fn render(&self, tokens: &[Token]) -> String {
+        let mut result = String::new();
+        let mut level: usize = 0;
+        let mut at_line_start = true;
+        let mut prev: Option<&str> = None;
+
+        for token in tokens {
+            match token {
+                Token::Text(text) => {
+                    if at_line_start {
+                        result.push_str(&" ".repeat(level * self.indent_width));
+                        at_line_start = false;
+                    } else if prev.is_some_and(|p| needs_space(p, text)) {
+                        result.push(' ');
+                    }
+                    result.push_str(text);
+                    prev = Some(text);
+                }
+                Token::Newline | Token::SameIndent => {
+                    if !at_line_start {
+                        result.push('\n');
+                        at_line_start = true;
+                    }
+                    prev = None;
+                }
+                Token::Indent => {
+                    if !at_line_start {
+                        result.push('\n');
+                        at_line_start = true;
+                    }
+                    level += 1;
+                    prev = None;
+                }
+                Token::Dedent => {
+                    if !at_line_start {
+                        result.push('\n');
+                        at_line_start = true;
+                    }
+                    level = level.saturating_sub(1);
+                    prev = None;
+                }
+                Token::Unsupported { .. } => {}
+            }
+        }
+
+        if !at_line_start {
+            result.push('\n');
+        }
+        result
+    }
+}
+
+/// 値トークンを追加 (パース時に取り込まれた前後の空白は除く)
+fn push_value(value: &str, out: &mut Vec<Token>) {
+    let trimmed = value.trim();
+    if !trimmed.is_empty() {
+        out.push(Token::Text(trimmed.to_string()));
+    }
+}
+
+/// 単一のルール参照だけからなる式なら、その参照先を返す
+fn alias_target(expr: &GrammarExpr) -> Option<&str> {
+    match expr {
+        GrammarExpr::RuleRef(name) => Some(name),
+        GrammarExpr::Group(inner) | GrammarExpr::Annotated { inner, .. } => alias_target(inner),
+        _ => None,
+    }
+}
+
+/// 消費済みの子ノード数の合計
+fn consumed_count(cursor: &Cursor) -> usize {
+    cursor.values().sum()
+}
+
+/// 式がレイアウトトークンだけで構成されているか (例: NEWLINE?)
+fn is_layout_only(expr: &GrammarExpr) -> bool {
+    match expr {
+        GrammarExpr::Indent | GrammarExpr::Dedent | GrammarExpr::Newline | GrammarExpr::SameIndent => true,
+        GrammarExpr::Sequence(items) | GrammarExpr::Choice(items) => items.iter().all(is_layout_only),
+        GrammarExpr::Group(inner) | GrammarExpr::Optional(inner) | GrammarExpr::Annotated { inner, .. } => is_layout_only(inner),
+        _ => false,
+    }
+}
+
+/// 2つのトークンの間に空白が必要か
+pub fn needs_space(prev: &str, next: &str) -> bool {
+    let last = prev.chars().last();
+    let first = next.chars().next();
+    match (last, first) {
+        (Some(l), Some(f)) => {
+            // 開き括弧の直後と、閉じ括弧・区切り記号の直前には空白を入れない
+            if matches!(l, '(' | '[' | '{') || matches!(f, ')' | ']' | '}' | ',' | ':' | ';') {
+                return false;
+            }
+            // 識別子の直後の開き括弧は関数呼び出しや添字とみなす
+            if matches!(f, '(' | '[') && (l.is_alphanumeric() || l == '_') {
+                return false;
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta_parser::MetaParser;
+    use crate::parser::Parser;
+
+    fn roundtrip(grammar: &str, source: &str) -> String {
+        let grammar = MetaParser::new(grammar).parse_input_grammar().unwrap();
+        let ast = Parser::new(&grammar, source).parse().expect("parse failed");
+        Unparser::new(&grammar).unparse(&ast).unwrap()
+    }
+
+    #[test]
+    fn test_unparse_func_decl() {
+        let grammar = r#"
+            func_decl := ret_type name "(" args? ")" ";";
+            args      := arg ("," arg)*;
+            arg       := type name;
+            ret_type  := "void" | "int";
+            type      := "int" | "float";
+            name      := "[a-zA-Z_]+";
+        "#;
+        assert_eq!(
+            roundtrip(grammar, "int   my_func( int a,float b ) ;"),
+            "int my_func(int a, float b);\n"
+        );
+        assert_eq!(roundtrip(grammar, "void f();"), "void f();\n");
+    }
+
+    #[test]
+    fn test_unparse_indentation() {
+        let grammar = r#"
+            block := stmt+;
+            stmt  := SAME_INDENT (if_stmt | call);
+            if_stmt := "if" name ":" NEWLINE INDENT block DEDENT;
+            call  := name "(" ")" NEWLINE?;
+            name  := "[a-zA-Z_]+";
+        "#;
+        let source = "if x:\n  if y:\n      foo()\n  bar()\nbaz()\n";
+        assert_eq!(
+            roundtrip(grammar, source),
+            "if x:\n    if y:\n        foo()\n    bar()\nbaz()\n"
+        );
+    }
+
+    #[test]
+    fn test_unparse_pattern_in_non_leaf_rule() {
+        let grammar = MetaParser::new(r#"call := name "(" [[0-9]*] ")"; name := "[a-z]+";"#).parse_input_grammar().unwrap();
+        let ast = Parser::new(&grammar, "f(12)").parse().unwrap();
+        let err = Unparser::new(&grammar).unparse(&ast).unwrap_err();
+        assert_eq!(err.rule, "call");
+        assert_eq!(
+            err.to_string(),
+            "Cannot unparse rule 'call': the text matched by the pattern \"[0-9]*\" is not kept in the AST (move the pattern into a rule of its own)"
+        );
+
+        // 選ばれなかった選択肢のパターンはエラーにしない
+        let grammar = MetaParser::new(r#"value := name | "=" [[0-9]+]; name := "[a-z]+";"#).parse_input_grammar().unwrap();
+        let ast = Parser::new(&grammar, "abc").parse().unwrap();
+        assert_eq!(Unparser::new(&grammar).unparse(&ast).unwrap(), "abc\n");
+    }
+}
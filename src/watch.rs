@@ -0,0 +1,492 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::batch;
+use crate::cache::CompiledGrammars;
+use crate::config::Pass;
+use crate::diagnostics::{Diagnostic, Renderer};
+use crate::generator::Generator;
+use crate::incremental::{ReparseError, TextEdit};
+use crate::meta_parser::{InputGrammar, OutputGrammar};
+use crate::parser::{ParseError, Parser};
+use crate::rewrite::{self, RewritePass};
+
+/// 続けて届いた通知をまとめる間隔
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// ファイルの更新を見分けるための更新時刻と大きさ (読めなければ None)
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// ソースと入力・出力BNFを監視し、変わるたびに変換し直して結果を表示する
+/// ファイルシステムの通知が使えなければ一定間隔で更新時刻を調べる
+pub struct Watch {
+    /// ソースファイルか、ソースを含むディレクトリ
+    source: PathBuf,
+    input_path: PathBuf,
+    output_path: PathBuf,
+    /// 生成したコードを書き出すディレクトリ (None なら書き出さない)
+    out_dir: Option<PathBuf>,
+    output_ext: Option<String>,
+    source_exts: Vec<String>,
+    start_rule: Option<String>,
+    recovery_rules: Vec<String>,
+    passes: Vec<Pass>,
+    poll_interval: Duration,
+    /// コンパイル済み文法のキャッシュを使う
+    use_cache: bool,
+    /// 端末に表示する (画面を消して書き直し、診断に色を付ける)
+    terminal: bool,
+}
+
+impl Watch {
+    pub fn new(source: &Path, input_path: &Path, output_path: &Path) -> Self {
+        Watch {
+            source: source.to_path_buf(),
+            input_path: input_path.to_path_buf(),
+            output_path: output_path.to_path_buf(),
+            out_dir: None,
+            output_ext: None,
+            source_exts: Vec::new(),
+            start_rule: None,
+            recovery_rules: Vec::new(),
+            passes: Vec::new(),
+            poll_interval: Duration::from_millis(500),
+            use_cache: true,
+            terminal: false,
+        }
+    }
+
+    /// 生成したコードを、ソースと同じ相対パスで out_dir に書き出す
+    pub fn with_out_dir(mut self, out_dir: &Path) -> Self {
+        self.out_dir = Some(out_dir.to_path_buf());
+        self
+    }
+
+    /// 出力ファイルの拡張子を付け替える
+    pub fn with_output_ext(mut self, ext: &str) -> Self {
+        self.output_ext = Some(ext.trim_start_matches('.').to_string());
+        self
+    }
+
+    /// ディレクトリを監視する場合に、指定した拡張子のファイルだけを変換する
+    pub fn with_source_exts(mut self, exts: &[String]) -> Self {
+        self.source_exts = exts.iter().map(|e| e.trim_start_matches('.').to_string()).collect();
+        self
+    }
+
+    /// 開始ルール (Parser::with_start_rule と同じ)
+    pub fn with_start_rule(mut self, rule: Option<&str>) -> Self {
+        self.start_rule = rule.map(|rule| rule.to_string());
+        self
+    }
+
+    /// 指定したルールでエラーから回復する (Parser::with_recovery と同じ)
+    pub fn with_recovery(mut self, rules: &[String]) -> Self {
+        self.recovery_rules = rules.to_vec();
+        self
+    }
+
+    /// 出力の前に AST を書き換えるパス (パスの文法も監視する)
+    pub fn with_passes(mut self, passes: &[Pass]) -> Self {
+        self.passes = passes.to_vec();
+        self
+    }
+
+    /// 通知が使えない場合に更新を調べる間隔
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    pub fn with_cache(mut self, use_cache: bool) -> Self {
+        self.use_cache = use_cache;
+        self
+    }
+
+    pub fn with_terminal(mut self, terminal: bool) -> Self {
+        self.terminal = terminal;
+        self
+    }
+
+    /// 中断されるまで監視を続ける
+    pub fn run(&self, writer: &mut impl Write) -> io::Result<()> {
+        let (sender, events) = mpsc::channel();
+        let notifier = self.notifier(sender);
+        if notifier.is_none() {
+            eprintln!("Warning: file notifications are unavailable; polling every {} ms", self.poll_interval.as_millis());
+        }
+
+        loop {
+            let grammar_stamps = self.grammar_stamps();
+            let loaded = CompiledGrammars::load(&self.input_path, &self.output_path, self.use_cache).and_then(|mut compiled| {
+                let passes = rewrite::load_passes(&self.passes, self.use_cache, &mut compiled.warnings)?;
+                Ok((compiled, passes))
+            });
+            match loaded {
+                Ok((CompiledGrammars { input, output, warnings }, passes)) => {
+                    // キャッシュの警告は毎回の表示の先頭に出す
+                    let warnings: String = warnings.iter().map(|warning| format!("Warning: {}\n", warning)).collect();
+                    // 文法が変わるまでは、ソースごとのパーサーを使い回して差分だけパースし直す
+                    let mut session = Session::new(&input, &output, &self.recovery_rules)
+                        .with_start_rule(self.start_rule.as_deref())
+                        .with_passes(&passes);
+                    loop {
+                        if self.refresh(&mut session) {
+                            self.show(writer, &format!("{}{}", warnings, self.render(&session)))?;
+                        }
+                        self.wait(&events, notifier.is_some());
+                        if self.grammar_stamps() != grammar_stamps {
+                            break;
+                        }
+                    }
+                }
+                // 文法を直すまで待つ
+                Err(e) => {
+                    self.show(writer, &format!("{}\n\nWaiting for the grammar to change (Ctrl+C to stop)\n", e))?;
+                    while self.grammar_stamps() == grammar_stamps {
+                        self.wait(&events, notifier.is_some());
+                    }
+                }
+            }
+        }
+    }
+
+    /// 監視するファイルが変わったら sender に知らせる
+    fn notifier(&self, sender: Sender<()>) -> Option<RecommendedWatcher> {
+        let mut watcher = notify::recommended_watcher(move |_| {
+            let _ = sender.send(());
+        })
+        .ok()?;
+        // エディタは保存するときにファイルを置き換えることがあるので、ファイルを含むディレクトリを見る
+        for path in self.grammar_paths() {
+            watcher.watch(parent_dir(path), RecursiveMode::NonRecursive).ok()?;
+        }
+        if self.source.is_dir() {
+            watcher.watch(&self.source, RecursiveMode::Recursive).ok()?;
+        } else {
+            watcher.watch(parent_dir(&self.source), RecursiveMode::NonRecursive).ok()?;
+        }
+        Some(watcher)
+    }
+
+    /// 次の変更まで待つ
+    fn wait(&self, events: &Receiver<()>, notified: bool) {
+        if !notified {
+            thread::sleep(self.poll_interval);
+            return;
+        }
+        // 保存1回で複数の通知が届くので、続けて届いた分は読み捨てる
+        if events.recv().is_ok() {
+            while events.recv_timeout(DEBOUNCE).is_ok() {}
+        }
+    }
+
+    /// 入力・出力BNFと書き換えパスの文法
+    fn grammar_paths(&self) -> Vec<&Path> {
+        let passes = self.passes.iter().flat_map(|pass| [pass.output.as_path(), pass.input.as_path()]);
+        [self.input_path.as_path(), self.output_path.as_path()].into_iter().chain(passes).collect()
+    }
+
+    fn grammar_stamps(&self) -> Vec<Stamp> {
+        self.grammar_paths().into_iter().map(stamp).collect()
+    }
+
+    /// 変換するソースのディレクトリと、そこからの相対パス
+    fn sources(&self) -> (PathBuf, Vec<PathBuf>) {
+        if self.source.is_dir() {
+            let out_dir = self.out_dir.clone().unwrap_or_default();
+            let paths = batch::source_files(&self.source, &out_dir, &self.source_exts).unwrap_or_default();
+            (self.source.clone(), paths)
+        } else {
+            let name = self.source.file_name().map(PathBuf::from).unwrap_or_default();
+            (parent_dir(&self.source).to_path_buf(), vec![name])
+        }
+    }
+
+    /// 更新されたソースを変換し直して書き出す (何か変わったら true)
+    fn refresh(&self, session: &mut Session) -> bool {
+        let (dir, paths) = self.sources();
+        let count = session.files.len();
+        session.files.retain(|path, _| paths.contains(path));
+        let mut changed = session.files.len() != count;
+
+        for path in paths {
+            let full_path = dir.join(&path);
+            let stamp = stamp(&full_path);
+            if session.files.get(&path).is_some_and(|state| state.stamp == stamp) {
+                continue;
+            }
+            // 書き込み途中などで読めなければ次の通知を待つ
+            let Ok(source) = fs::read_to_string(&full_path) else { continue };
+            let state = session.update(&path, source, stamp);
+            changed = true;
+
+            let Some(out_dir) = &self.out_dir else { continue };
+            let output_path = batch::output_path(out_dir, &path, self.output_ext.as_deref());
+            state.write_error = match &state.output {
+                Some(output) => output_path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(&output_path, format!("{}\n", output)))
+                    .err()
+                    .map(|e| format!("Error writing {}: {}", output_path.display(), e)),
+                // 変換に失敗したら、前の内容から生成した古い出力を残さない
+                None => match fs::remove_file(&output_path) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => Some(format!("Error removing {}: {}", output_path.display(), e)),
+                    _ => None,
+                },
+            };
+        }
+        changed
+    }
+
+    /// 各ソースの状態と診断 (ファイル1つを書き出さずに監視している場合は生成したコードも)
+    fn render(&self, session: &Session) -> String {
+        let (dir, _) = self.sources();
+        let mut text = String::new();
+        for (path, state) in &session.files {
+            let name = dir.join(path).display().to_string();
+            let errors = state.errors();
+            let status = match (errors.len(), &state.write_error) {
+                (_, Some(message)) => message.clone(),
+                (0, None) if state.pass_error.is_some() => "rewrite pass failed".to_string(),
+                (0, None) => "ok".to_string(),
+                (1, None) => "1 error".to_string(),
+                (n, None) => format!("{} errors", n),
+            };
+            let _ = write!(text, "{}: {}", name, status);
+            if let Some((changed, reused)) = state.reparsed {
+                let _ = write!(text, " ({} nodes changed, {} results reused)", changed, reused);
+            }
+            text.push('\n');
+
+            let renderer = Renderer::new(&name, &state.source).with_color(self.terminal);
+            for err in errors {
+                let _ = writeln!(text, "\n{}", renderer.render(&Diagnostic::from_parse_error(err, session.input, &state.source)));
+            }
+            if let Some(diagnostic) = &state.pass_error {
+                let _ = writeln!(text, "\n{}", diagnostic);
+            }
+        }
+
+        if self.out_dir.is_none() && !self.source.is_dir() {
+            if let Some(output) = session.files.values().next().and_then(|state| state.output.as_ref()) {
+                let _ = writeln!(text, "\n{}", output);
+            }
+        }
+        let plural = if session.files.len() == 1 { "" } else { "s" };
+        let _ = writeln!(text, "\nWatching {} file{} and the grammars for changes (Ctrl+C to stop)", session.files.len(), plural);
+        text
+    }
+
+    /// 前の表示を消して書き直す
+    fn show(&self, writer: &mut impl Write, text: &str) -> io::Result<()> {
+        if self.terminal {
+            write!(writer, "\x1b[2J\x1b[H")?;
+        }
+        write!(writer, "{}", text)?;
+        writer.flush()
+    }
+}
+
+/// ファイルを含むディレクトリ ("a.py" なら ".")
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// 1組の文法でのソースごとの変換状態
+struct Session<'g> {
+    input: &'g InputGrammar,
+    output: &'g OutputGrammar,
+    start_rule: Option<&'g str>,
+    recovery_rules: &'g [String],
+    passes: &'g [RewritePass],
+    files: BTreeMap<PathBuf, SourceState<'g>>,
+}
+
+struct SourceState<'g> {
+    stamp: Stamp,
+    source: String,
+    /// 前回の内容を覚えているパーサー (差分だけパースし直す)
+    parser: Parser<'g>,
+    /// パースに失敗した場合のエラー
+    error: Option<ParseError>,
+    /// 書き換えパスに失敗した場合の診断
+    pass_error: Option<String>,
+    /// 生成したコード (パースに失敗したら None)
+    output: Option<String>,
+    /// パースし直した場合の、変わったノードと再利用した結果の数
+    reparsed: Option<(usize, usize)>,
+    write_error: Option<String>,
+}
+
+impl SourceState<'_> {
+    /// 回復したエラーとパースに失敗したエラー
+    fn errors(&self) -> Vec<&ParseError> {
+        self.parser.errors().iter().chain(&self.error).collect()
+    }
+}
+
+impl<'g> Session<'g> {
+    fn new(input: &'g InputGrammar, output: &'g OutputGrammar, recovery_rules: &'g [String]) -> Self {
+        Session { input, output, start_rule: None, recovery_rules, passes: &[], files: BTreeMap::new() }
+    }
+
+    fn with_start_rule(mut self, rule: Option<&'g str>) -> Self {
+        self.start_rule = rule;
+        self
+    }
+
+    fn with_passes(mut self, passes: &'g [RewritePass]) -> Self {
+        self.passes = passes;
+        self
+    }
+
+    /// ソースの新しい内容をパースして変換する
+    /// 前にパースしたソースなら、前の内容との差分だけをパースし直す
+    fn update(&mut self, path: &Path, source: String, stamp: Stamp) -> &mut SourceState<'g> {
+        // 新しいファイルは、空のソースへの編集としてパースする
+        let (input, start_rule, recovery_rules) = (self.input, self.start_rule, self.recovery_rules);
+        let state = self.files.entry(path.to_path_buf()).or_insert_with(|| SourceState {
+            stamp: None,
+            source: String::new(),
+            parser: {
+                let parser = Parser::new(input, "").with_incremental().with_recovery(recovery_rules);
+                match start_rule {
+                    Some(rule) => parser.with_start_rule(rule),
+                    None => parser,
+                }
+            },
+            error: None,
+            pass_error: None,
+            output: None,
+            reparsed: None,
+            write_error: None,
+        });
+
+        let first = state.stamp.is_none();
+        let edit = TextEdit::between(&state.source, &source);
+        let result = state.parser.reparse(&edit);
+        state.stamp = stamp;
+        state.source = source;
+        state.reparsed = None;
+        state.output = None;
+        state.error = None;
+        state.pass_error = None;
+        match result {
+            Ok(reparsed) => {
+                if !first {
+                    state.reparsed = Some((reparsed.changed.len(), state.parser.reused()));
+                }
+                match rewrite::apply_passes(self.passes, reparsed.ast) {
+                    Ok(ast) => state.output = Some(Generator::new(self.output).generate(&ast)),
+                    Err(diagnostic) => state.pass_error = Some(diagnostic),
+                }
+            }
+            Err(ReparseError::Parse(err)) => state.error = Some(err),
+            // between で求めた編集は必ず前回のソースに当てはまる
+            Err(ReparseError::Edit(err)) => unreachable!("{}", err),
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta_parser::MetaParser;
+
+    const INPUT: &str = r#"
+        program := call+;
+        call    := name "(" ")" NEWLINE;
+        name    := "[a-z]+";
+    "#;
+    const OUTPUT: &str = r#"
+        program := call join "\n";
+        call    := name "();";
+    "#;
+
+    #[test]
+    fn test_refresh() {
+        let dir = std::env::temp_dir().join(format!("hensan-watch-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/a.py"), "f()\ng()\n").unwrap();
+        fs::write(dir.join("src/b.py"), "h(\n").unwrap();
+
+        let input = MetaParser::new(INPUT).parse_input_grammar().unwrap();
+        let output = MetaParser::new(OUTPUT).parse_output_grammar().unwrap();
+        let watch = Watch::new(&dir.join("src"), &dir.join("input.bnf"), &dir.join("output.bnf"))
+            .with_out_dir(&dir.join("gen"))
+            .with_output_ext("rs");
+        let mut session = Session::new(&input, &output, &[]);
+
+        assert!(watch.refresh(&mut session));
+        assert_eq!(fs::read_to_string(dir.join("gen/a.rs")).unwrap(), "f();\ng();\n");
+        let screen = watch.render(&session);
+        assert!(screen.contains("a.py: ok\n"), "{}", screen);
+        assert!(screen.contains("b.py: 1 error\n"), "{}", screen);
+        assert!(screen.contains("expected `)`"), "{}", screen);
+
+        // 変わっていなければ何もしない
+        assert!(!watch.refresh(&mut session));
+
+        // 直したファイルは、前の内容との差分だけパースし直す
+        fs::write(dir.join("src/a.py"), "f()\ng()\nk()\n").unwrap();
+        fs::write(dir.join("src/b.py"), "h()\n").unwrap();
+        assert!(watch.refresh(&mut session));
+        assert_eq!(fs::read_to_string(dir.join("gen/a.rs")).unwrap(), "f();\ng();\nk();\n");
+        assert_eq!(fs::read_to_string(dir.join("gen/b.rs")).unwrap(), "h();\n");
+        let screen = watch.render(&session);
+        assert!(screen.contains("a.py: ok (3 nodes changed, "), "{}", screen);
+        assert!(screen.contains("Watching 2 files"), "{}", screen);
+
+        // パースし直しに失敗したら、古い出力を消す
+        fs::write(dir.join("src/b.py"), "h(\n").unwrap();
+        assert!(watch.refresh(&mut session));
+        assert!(!dir.join("gen/b.rs").exists());
+        assert!(watch.render(&session).contains("b.py: 1 error\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_render_single_file() {
+        let dir = std::env::temp_dir().join(format!("hensan-watch-single-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.py"), "f()\n").unwrap();
+
+        let input = MetaParser::new(INPUT).parse_input_grammar().unwrap();
+        let output = MetaParser::new(OUTPUT).parse_output_grammar().unwrap();
+        let watch = Watch::new(&dir.join("a.py"), &dir.join("input.bnf"), &dir.join("output.bnf"));
+        let mut session = Session::new(&input, &output, &[]);
+
+        assert!(watch.refresh(&mut session));
+        let screen = watch.render(&session);
+        assert!(screen.ends_with("a.py: ok\n\nf();\n\nWatching 1 file and the grammars for changes (Ctrl+C to stop)\n"), "{}", screen);
+
+        // 開始ルールを変えると断片をパースできる
+        fs::write(dir.join("b.py"), "abc").unwrap();
+        let watch = Watch::new(&dir.join("b.py"), &dir.join("input.bnf"), &dir.join("output.bnf"));
+        let mut session = Session::new(&input, &output, &[]).with_start_rule(Some("name"));
+        assert!(watch.refresh(&mut session));
+        assert!(watch.render(&session).contains("b.py: ok\n\nabc\n"), "{}", watch.render(&session));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
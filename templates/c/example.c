@@ -0,0 +1,4 @@
+int add(int a, int b);
+void reset();
+double scale(double x, float factor);
+int count = 0;
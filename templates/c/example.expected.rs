@@ -0,0 +1,4 @@
+fn add(a: i32, b: i32) -> i32;
+fn reset() -> ();
+fn scale(x: f64, factor: f32) -> f64;
+static count: i32 = 0;
@@ -0,0 +1,11 @@
+config = {
+    # hensan example
+    'name': 'demo',
+    'server': {
+        'host': 'localhost',
+        'port': '8080',
+    },
+    'paths': {
+        'data': '/var/lib/demo',
+    },
+}
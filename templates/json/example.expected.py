@@ -0,0 +1 @@
+value = {"name": "hensan", "version": 1.2, "tags": ["parser", "translator"], "stable": False, "license": None}
@@ -0,0 +1 @@
+let value = serde_json::json!({"name": "hensan", "version": 1.2, "tags": ["parser", "translator"], "stable": false, "license": null});
@@ -0,0 +1,15 @@
+fn greet(name) {
+    print(name);
+}
+
+fn check(x) {
+    if x == 0 {
+    greet(x);
+    } else {
+    print(x);
+    }
+}
+
+fn main() {
+    check(3);
+}
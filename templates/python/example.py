@@ -0,0 +1,10 @@
+def greet(name):
+    print(name)
+
+def check(x):
+    if x == 0:
+        greet(x)
+    else:
+        print(x)
+
+check(3)
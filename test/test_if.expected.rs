@@ -0,0 +1,13 @@
+fn check(x) {
+    if x == 0 {
+    print(zero);
+    } else if x       >0 {
+    print(positive);
+    } else {
+    print(negative);
+    }
+}
+
+fn main() {
+    check(5);
+}
@@ -0,0 +1,9 @@
+fn test() {
+    if x {
+    foo();
+    }
+}
+
+fn main() {
+    test();
+}
@@ -0,0 +1,7 @@
+fn foo() {
+    bar();
+}
+
+fn main() {
+    baz();
+}
@@ -0,0 +1,259 @@
+//! コマンドラインの振る舞い (ソースの指定、-o、終了コード、メッセージの量) を実行ファイルで確かめる
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+const INPUT: &str = "program := call+;\ncall := name \"(\" \")\";\nname := \"[a-z]+\";\n";
+const OUTPUT: &str = "program := call join \" \";\ncall := name \"();\";\n";
+
+/// 文法を置いた作業ディレクトリ (テストごとに分ける)
+fn project(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hensan-cli-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("in.bnf"), INPUT).unwrap();
+    fs::write(dir.join("out.bnf"), OUTPUT).unwrap();
+    dir
+}
+
+/// dir で hensan を実行する (stdin を渡す)
+fn hensan(dir: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hensan"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[test]
+fn test_source_selection() {
+    let dir = project("source");
+    fs::write(dir.join("a.src"), "f()").unwrap();
+    fs::write(dir.join("-b.src"), "h()").unwrap();
+
+    // 位置引数、--file、--code、- (標準入力) のどれでもソースを渡せる
+    let cases: [(&[&str], &str, &str); 5] = [
+        (&["translate", "a.src", "in.bnf", "out.bnf"], "", "f();\n"),
+        (&["translate", "--file", "a.src", "in.bnf", "out.bnf"], "", "f();\n"),
+        (&["translate", "--file", "-b.src", "in.bnf", "out.bnf"], "", "h();\n"),
+        (&["translate", "--code", "f() g()", "in.bnf", "out.bnf"], "", "f(); g();\n"),
+        (&["translate", "-", "in.bnf", "out.bnf"], "g()", "g();\n"),
+    ];
+    for (args, stdin, expected) in cases {
+        let output = hensan(&dir, args, stdin);
+        assert!(output.status.success(), "{:?}: {}", args, stderr(&output));
+        assert_eq!(stdout(&output), expected, "{:?}", args);
+    }
+
+    // サブコマンドを省略したら translate
+    assert_eq!(stdout(&hensan(&dir, &["a.src", "in.bnf", "out.bnf"], "")), "f();\n");
+    // parse は入力BNFだけを使う
+    assert_eq!(stdout(&hensan(&dir, &["parse", "--code", "f()", "in.bnf"], "")), "(program (call (name \"f\")))\n");
+
+    let output = hensan(&dir, &["translate", "--code", "f()", "--file", "a.src", "in.bnf", "out.bnf"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("--code and --file cannot be used together"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_output_file() {
+    let dir = project("output");
+
+    let output = hensan(&dir, &["translate", "--code", "f()", "in.bnf", "out.bnf", "-o", "gen.txt"], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(fs::read_to_string(dir.join("gen.txt")).unwrap(), "f();\n");
+
+    let output = hensan(&dir, &["parse", "-o", "ast.txt", "--code", "f()", "in.bnf"], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(fs::read_to_string(dir.join("ast.txt")).unwrap(), "(program (call (name \"f\")))\n");
+
+    // 書けない -o は入出力のエラー
+    let output = hensan(&dir, &["translate", "--code", "f()", "in.bnf", "out.bnf", "-o", "missing/gen.txt"], "");
+    assert_eq!(output.status.code(), Some(4));
+    assert!(stderr(&output).contains("Error writing missing/gen.txt"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_exit_codes() {
+    let dir = project("exit");
+    fs::write(dir.join("bad.bnf"), "call := name \"(\" \")\"\nname := \"[a-z+\";\n").unwrap();
+
+    // (引数, 終了コード, 標準エラー出力に含まれるもの)
+    let cases: [(&[&str], i32, &str); 9] = [
+        (&["check", "--code", "f()", "in.bnf"], 0, ""),
+        (&["check", "--code", "f(", "in.bnf"], 1, "expected `)`"),
+        (&["translate", "--code", "f(", "in.bnf", "out.bnf"], 1, "expected `)`"),
+        (&["check", "--code", "f()", "in.bnf", "out.bnf"], 2, "Unexpected argument: out.bnf"),
+        (&["parse", "--code", "f()", "in.bnf", "--frobnicate"], 2, "Unknown option: --frobnicate"),
+        (&["translate", "--code", "f()", "in.bnf", "out.bnf", "extra.bnf"], 2, "Unexpected argument: extra.bnf"),
+        (&["watch", "in.bnf", "in.bnf", "out.bnf", "extra.bnf"], 2, "Unexpected argument: extra.bnf"),
+        (&["check", "--code", "f()", "bad.bnf"], 3, "Error in bad.bnf"),
+        (&["check", "missing.src", "in.bnf"], 4, "Error reading source file missing.src"),
+    ];
+    for (args, code, message) in cases {
+        let output = hensan(&dir, args, "");
+        assert_eq!(output.status.code(), Some(code), "{:?}: {}", args, stderr(&output));
+        assert!(stderr(&output).contains(message), "{:?}: {}", args, stderr(&output));
+    }
+
+    // 文法ファイルが読めなければ入出力のエラー
+    let output = hensan(&dir, &["translate", "--code", "f()", "missing.bnf", "out.bnf"], "");
+    assert_eq!(output.status.code(), Some(4));
+    assert!(stderr(&output).contains("Error reading missing.bnf"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_verbosity() {
+    let dir = project("verbosity");
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("src/a.src"), "f()").unwrap();
+    fs::write(dir.join("src/b.src"), "g()").unwrap();
+
+    // 既定では要約だけ、--verbose では読み込んだ文法とファイルごとの結果も、--quiet では何も書かない
+    let output = hensan(&dir, &["check", "src", "in.bnf"], "");
+    assert!(output.status.success());
+    assert_eq!(stderr(&output), "Checked 2 files (0 failed)\n");
+
+    let output = hensan(&dir, &["check", "src", "in.bnf", "--verbose"], "");
+    let messages = stderr(&output);
+    assert!(messages.contains("Input grammar: in.bnf\n"), "{}", messages);
+    assert!(messages.contains("ok src/a.src\n"), "{}", messages);
+    assert!(messages.ends_with("Checked 2 files (0 failed)\n"), "{}", messages);
+
+    let output = hensan(&dir, &["--quiet", "check", "src", "in.bnf"], "");
+    assert!(output.status.success());
+    assert_eq!(stderr(&output), "");
+
+    // --quiet でもエラーは書く
+    fs::write(dir.join("src/b.src"), "g(").unwrap();
+    let output = hensan(&dir, &["check", "src", "in.bnf", "--quiet"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("expected `)`"));
+
+    // キャッシュを書けない警告も --quiet では書かない
+    fs::write(dir.join(".cache"), "").unwrap();
+    let output = hensan(&dir, &["translate", "--code", "f()", "in.bnf", "out.bnf"], "");
+    assert!(stderr(&output).starts_with("Warning: could not write grammar cache"), "{}", stderr(&output));
+    let output = hensan(&dir, &["translate", "--code", "f()", "in.bnf", "out.bnf", "--quiet"], "");
+    assert_eq!(stderr(&output), "");
+    assert_eq!(stdout(&output), "f();\n");
+
+    let output = hensan(&dir, &["check", "src", "in.bnf", "--quiet", "--verbose"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("--quiet and --verbose cannot be used together"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_help_and_unknown_command() {
+    let dir = project("help");
+
+    // --help で求めた使い方は成功として終わる
+    let cases: [&[&str]; 5] = [&["--help"], &["help"], &["translate", "--help"], &["check", "-h"], &["repl", "--help"]];
+    for args in cases {
+        let output = hensan(&dir, args, "");
+        assert_eq!(output.status.code(), Some(0), "{:?}: {}", args, stderr(&output));
+        assert!(stderr(&output).contains("Usage:"), "{:?}: {}", args, stderr(&output));
+    }
+
+    // 引数が足りないときの使い方は誤りとして終わる
+    let output = hensan(&dir, &[], "");
+    assert_eq!(output.status.code(), Some(2));
+    let output = hensan(&dir, &["parse"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Usage:"));
+
+    // サブコマンドの打ち間違いはソースとして読まない
+    let output = hensan(&dir, &["trasnlate", "--code", "f()", "in.bnf", "out.bnf"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Unknown command 'trasnlate'"), "{}", stderr(&output));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_batch_options() {
+    let dir = project("batch");
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("src/a.src"), "f").unwrap();
+
+    // 開始ルールはディレクトリの変換にも効く
+    let output = hensan(&dir, &["translate", "src", "in.bnf", "out.bnf", "--out-dir", "gen", "--start", "name"], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(fs::read_to_string(dir.join("gen/a.src")).unwrap(), "f\n");
+
+    // 1ファイル向けのオプションは使い方の誤り
+    let cases: [&[&str]; 5] = [&["--trace"], &["--dump-ast=json"], &["--unparse"], &["--trace-output"], &["--source-map", "out.map"]];
+    for option in cases {
+        let args = [&["translate", "src", "in.bnf", "out.bnf", "--out-dir", "gen"], option].concat();
+        let output = hensan(&dir, &args, "");
+        assert_eq!(output.status.code(), Some(2), "{:?}: {}", option, stderr(&output));
+        let name = option[0].split('=').next().unwrap();
+        assert!(stderr(&output).contains(&format!("{} can only be used when translating a single source", name)), "{}", stderr(&output));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_source_map_with_passes() {
+    let dir = project("passes");
+    fs::write(
+        dir.join("hensan.toml"),
+        "[pipeline.main]\ninput = \"sugar.bnf\"\n\n[[pipeline.main.output]]\ngrammar = \"assign.bnf\"\n\n[[pipeline.main.pass]]\noutput = \"desugar.out.bnf\"\ninput = \"desugar.in.bnf\"\n",
+    )
+    .unwrap();
+    fs::write(dir.join("sugar.bnf"), "program := stmt+;\nstmt := incr | call;\nincr := \"inc\" name NEWLINE;\ncall := name \"(\" \")\" NEWLINE;\nname := \"\\w+\";\n").unwrap();
+    fs::write(dir.join("desugar.out.bnf"), "program := stmt join \"\\n\";\nstmt := incr | call;\nincr := name \" += 1\";\ncall := name \"()\";\n").unwrap();
+    fs::write(
+        dir.join("desugar.in.bnf"),
+        "program := stmt (NEWLINE stmt)* NEWLINE?;\nstmt := assign | call;\nassign := name \"\\+=\" value;\ncall := name \"(\" \")\";\nname := \"\\w+\";\nvalue := \"[0-9]+\";\n",
+    )
+    .unwrap();
+    fs::write(dir.join("assign.bnf"), "program := stmt join \"\\n\";\nstmt := assign | call;\nassign := name \" = \" name \" + \" value \";\";\ncall := name \"();\";\n").unwrap();
+    fs::write(dir.join("a.src"), "inc 日\nf()\n").unwrap();
+
+    // 書き換えパスの後でも、ソースマップは中間のテキストではなく元のソースを指す
+    let output = hensan(&dir, &["translate", "a.src", "--source-map", "a.txt"], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "日 = 日 + 1;\nf();\n");
+    assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "1:1\t1:5\tname\n1:2\t1:1\tassign\n1:5\t1:5\tname\n1:6\t1:1\tassign\n2:1\t2:1\tname\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_recovered_errors() {
+    let dir = project("recover");
+    fs::write(dir.join("lines.bnf"), "program := call+;\ncall := name \"(\" \")\" NEWLINE?;\nname := \"[a-z]+\";\n").unwrap();
+
+    // 回復したエラーは全て報告し、読み飛ばしたところには印を出力して、失敗として終了する
+    let output = hensan(&dir, &["translate", "--code", "f()\ng(\nh()\n", "lines.bnf", "out.bnf", "--recover", "call"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "f(); <<parse error in 'call': \"g(\">> h();\n");
+    assert!(stderr(&output).contains("expected `)`"), "{}", stderr(&output));
+
+    fs::remove_dir_all(&dir).unwrap();
+}